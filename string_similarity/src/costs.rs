@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::unit::{ Symbol, Unit };
+
+
+/// QWERTY rows, each shifted half a key right of the row above it.
+const QWERTY_ROWS: [&str; 4] = ["1234567890", "qwertyuiop", "asdfghjkl", "zxcvbnm"];
+
+/// Pairs of characters that OCR engines commonly confuse.
+const OCR_CONFUSIONS: [(&str, &str); 16] = [
+    ("0", "O"), ("0", "o"), ("0", "D"), ("O", "Q"),
+    ("1", "l"), ("1", "I"), ("l", "I"), ("1", "i"),
+    ("5", "S"), ("2", "Z"), ("8", "B"), ("6", "b"),
+    ("9", "g"), ("g", "q"), ("u", "v"), ("c", "e"),
+];
+
+
+/// Checks that `cost` is a usable edit cost, which is finite and not
+/// negative, as the kernels rely on no alignment being cheaper than free.
+pub fn check_cost(cost: f64) -> Result<f64, String> {
+    if cost.is_finite() && cost >= 0.0 {
+        Ok(cost)
+    } else {
+        Err(format!("edit costs must be finite and not negative, got {}", cost))
+    }
+}
+
+
+/// Per unit edit costs for weighted levenshtein. Units without an entry fall
+/// back to the scalar costs, and substituting a unit with itself is free.
+/// Substitution costs are directed, so `a -> b` and `b -> a` are set
+/// separately.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CostTable {
+    deletion_cost: f64,
+    insertion_cost: f64,
+    substitution_cost: f64,
+    ids: HashMap<String, usize>,
+    deletion: Vec<f64>,
+    insertion: Vec<f64>,
+    substitution: Vec<Vec<f64>>,
+}
+
+impl CostTable {
+    pub fn new(deletion_cost: f64, insertion_cost: f64, substitution_cost: f64) -> Result<CostTable, String> {
+        Ok(CostTable {
+            deletion_cost: check_cost(deletion_cost)?,
+            insertion_cost: check_cost(insertion_cost)?,
+            substitution_cost: check_cost(substitution_cost)?,
+            ids: HashMap::new(),
+            deletion: Vec::new(),
+            insertion: Vec::new(),
+            substitution: Vec::new(),
+        })
+    }
+
+    /// Substituting keys that are next to each other on a QWERTY keyboard,
+    /// in either case, costs `cost`.
+    pub fn keyboard(mut self, cost: f64) -> Result<CostTable, String> {
+        check_cost(cost)?;
+        let rows: Vec<Vec<char>> = QWERTY_ROWS.iter().map(|row| row.chars().collect()).collect();
+        let mut neighbours = Vec::new();
+        for (row_idx, row) in rows.iter().enumerate() {
+            for (idx, &key) in row.iter().enumerate() {
+                if let Some(&right) = row.get(idx + 1) {
+                    neighbours.push((key, right));
+                }
+                if let Some(below) = rows.get(row_idx + 1) {
+                    for below_idx in [idx.wrapping_sub(1), idx] {
+                        if let Some(&below_key) = below.get(below_idx) {
+                            neighbours.push((key, below_key));
+                        }
+                    }
+                }
+            }
+        }
+
+        for (a, b) in neighbours {
+            for (a, b) in [(a, b), (a.to_ascii_uppercase(), b.to_ascii_uppercase())] {
+                self.set_substitution(&a.to_string(), &b.to_string(), cost)?;
+                self.set_substitution(&b.to_string(), &a.to_string(), cost)?;
+            }
+        }
+        Ok(self)
+    }
+
+    /// Substituting characters OCR commonly confuses, such as 0 and O or 1
+    /// and l, costs `cost`.
+    pub fn ocr(mut self, cost: f64) -> Result<CostTable, String> {
+        for (a, b) in OCR_CONFUSIONS {
+            self.set_substitution(a, b, cost)?;
+            self.set_substitution(b, a, cost)?;
+        }
+        Ok(self)
+    }
+
+    fn id(&mut self, unit: &str) -> usize {
+        if let Some(&id) = self.ids.get(unit) {
+            return id;
+        }
+        let id = self.ids.len();
+        self.ids.insert(unit.to_string(), id);
+        self.deletion.push(self.deletion_cost);
+        self.insertion.push(self.insertion_cost);
+        for row in self.substitution.iter_mut() {
+            row.push(self.substitution_cost);
+        }
+        let mut row = vec![self.substitution_cost; id + 1];
+        row[id] = 0.0;
+        self.substitution.push(row);
+        id
+    }
+
+    pub fn set_deletion(&mut self, unit: &str, cost: f64) -> Result<(), String> {
+        let cost = check_cost(cost)?;
+        let id = self.id(unit);
+        self.deletion[id] = cost;
+        Ok(())
+    }
+
+    pub fn set_insertion(&mut self, unit: &str, cost: f64) -> Result<(), String> {
+        let cost = check_cost(cost)?;
+        let id = self.id(unit);
+        self.insertion[id] = cost;
+        Ok(())
+    }
+
+    /// The cost of replacing `from` with `to`. Replacing a unit with itself
+    /// is always free.
+    pub fn set_substitution(&mut self, from: &str, to: &str, cost: f64) -> Result<(), String> {
+        let cost = check_cost(cost)?;
+        if from == to {
+            return Ok(());
+        }
+        let from = self.id(from);
+        let to = self.id(to);
+        self.substitution[from][to] = cost;
+        Ok(())
+    }
+
+    /// Checks that every unit in the table is a single `unit`, since longer
+    /// keys could never match.
+    pub fn check_unit(&self, unit: Unit) -> Result<(), String> {
+        for key in self.ids.keys() {
+            let single = match unit {
+                Unit::Byte => key.len() == 1,
+                Unit::Char => key.chars().count() == 1,
+                Unit::Grapheme => key.graphemes(true).count() == 1,
+            };
+            if !single {
+                return Err(format!("cost table entries must be single {}s, got '{}'", unit, key));
+            }
+        }
+        Ok(())
+    }
+
+    /// The table ids of `units`, `None` for units without an entry.
+    fn lookup<T: Symbol>(&self, units: &[T]) -> Vec<Option<usize>> {
+        let mut buf = [0; 4];
+        units
+            .iter()
+            .map(|unit| unit.as_str(&mut buf).and_then(|key| self.ids.get(key).copied()))
+            .collect()
+    }
+
+    pub(crate) fn deletion_costs<T: Symbol>(&self, units: &[T]) -> (Vec<Option<usize>>, Vec<f64>) {
+        let ids = self.lookup(units);
+        let costs = ids.iter().map(|id| id.map_or(self.deletion_cost, |id| self.deletion[id])).collect();
+        (ids, costs)
+    }
+
+    pub(crate) fn insertion_costs<T: Symbol>(&self, units: &[T]) -> (Vec<Option<usize>>, Vec<f64>) {
+        let ids = self.lookup(units);
+        let costs = ids.iter().map(|id| id.map_or(self.insertion_cost, |id| self.insertion[id])).collect();
+        (ids, costs)
+    }
+
+    /// The cost of substituting two units that differ, given their ids.
+    pub(crate) fn substitution_cost(&self, from: Option<usize>, to: Option<usize>) -> f64 {
+        match (from, to) {
+            (Some(from), Some(to)) => self.substitution[from][to],
+            _ => self.substitution_cost,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets() {
+        let keyboard = CostTable::new(1.0, 1.0, 1.0).unwrap().keyboard(0.5).unwrap();
+        let id = |table: &CostTable, key: &str| table.ids.get(key).copied();
+        assert_eq!(keyboard.substitution_cost(id(&keyboard, "q"), id(&keyboard, "w")), 0.5);
+        assert_eq!(keyboard.substitution_cost(id(&keyboard, "S"), id(&keyboard, "W")), 0.5);
+        assert_eq!(keyboard.substitution_cost(id(&keyboard, "g"), id(&keyboard, "b")), 0.5);
+        assert_eq!(keyboard.substitution_cost(id(&keyboard, "q"), id(&keyboard, "p")), 1.0);
+        assert_eq!(keyboard.substitution_cost(id(&keyboard, "q"), None), 1.0);
+
+        let ocr = CostTable::new(1.0, 1.0, 1.0).unwrap().ocr(0.25).unwrap();
+        assert_eq!(ocr.substitution_cost(id(&ocr, "l"), id(&ocr, "1")), 0.25);
+        assert_eq!(ocr.substitution_cost(id(&ocr, "O"), id(&ocr, "0")), 0.25);
+        assert!(ocr.check_unit(Unit::Byte).is_ok());
+    }
+
+    #[test]
+    fn single_units() {
+        let mut table = CostTable::new(1.0, 1.0, 1.0).unwrap();
+        table.set_substitution("é", "e", 0.1).unwrap();
+        assert!(table.check_unit(Unit::Char).is_ok());
+        assert!(table.check_unit(Unit::Byte).is_err());
+
+        table.set_insertion("rn", 0.5).unwrap();
+        assert!(table.check_unit(Unit::Grapheme).is_err());
+    }
+
+    #[test]
+    fn rejects_unusable_costs() {
+        assert!(CostTable::new(-1.0, 1.0, 1.0).is_err());
+        assert!(CostTable::new(1.0, f64::NAN, 1.0).is_err());
+        assert!(CostTable::new(1.0, 1.0, f64::INFINITY).is_err());
+
+        let mut table = CostTable::new(1.0, 1.0, 0.0).unwrap();
+        assert!(table.set_deletion("a", -0.5).is_err());
+        assert!(table.set_insertion("a", f64::NEG_INFINITY).is_err());
+        assert!(table.set_substitution("a", "a", f64::NAN).is_err());
+        assert!(table.clone().keyboard(-1.0).is_err());
+        assert!(table.ocr(f64::NAN).is_err());
+    }
+}
@@ -0,0 +1,162 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+
+/// Optimal string alignment distance, i.e. levenshtein distance where
+/// swapping two adjacent units costs `transposition_cost`. Unlike true
+/// damerau-levenshtein no substring may be edited more than once, so
+/// "ca" -> "abc" costs 3 rather than 2. Costs add up saturating at
+/// `usize::MAX`.
+pub fn get_osa_distance<T: Eq>(
+    str1: &[T],
+    str2: &[T],
+    deletion_cost: usize,
+    insertion_cost: usize,
+    substitution_cost: usize,
+    transposition_cost: usize,
+    ) -> usize {
+    let len1 = str1.len();
+    let len2 = str2.len();
+
+    let mut table: Vec<Vec<usize>> = vec![vec![0; len2 + 1]; len1 + 1];
+
+    for idx in 1..(len1 + 1) {
+        table[idx][0] = table[idx - 1][0].saturating_add(deletion_cost);
+    }
+
+    for idx in 1..(len2 + 1) {
+        table[0][idx] = table[0][idx - 1].saturating_add(insertion_cost);
+    }
+
+    for idx in 1..(len1 + 1) {
+        for jdx in 1..(len2 + 1) {
+            let sub_cost = if str1[idx - 1] == str2[jdx - 1] { 0 } else { substitution_cost };
+            let mut distance = cmp::min(
+                cmp::min(
+                    table[idx - 1][jdx].saturating_add(deletion_cost),
+                    table[idx][jdx - 1].saturating_add(insertion_cost)
+                    ),
+                table[idx - 1][jdx - 1].saturating_add(sub_cost)
+                );
+
+            if idx > 1 && jdx > 1 && str1[idx - 1] == str2[jdx - 2] && str1[idx - 2] == str2[jdx - 1] {
+                distance = cmp::min(distance, table[idx - 2][jdx - 2].saturating_add(transposition_cost));
+            }
+            table[idx][jdx] = distance;
+        }
+    }
+    table[len1][len2]
+}
+
+
+/// True (unrestricted) damerau-levenshtein distance using the algorithm of
+/// Lowrance and Wagner (1975), which allows insertions and deletions between
+/// transposed units. The result is only a minimum if
+/// `2 * transposition_cost >= insertion_cost + deletion_cost`. Costs add up
+/// saturating at `usize::MAX`.
+pub fn get_damerau_levenshtein_distance<T: Eq + Hash>(
+    str1: &[T],
+    str2: &[T],
+    deletion_cost: usize,
+    insertion_cost: usize,
+    substitution_cost: usize,
+    transposition_cost: usize,
+    ) -> usize {
+    let len1 = str1.len();
+    let len2 = str2.len();
+    let infinity = usize::MAX;
+
+    // Shifted by one row and column so that row and column 0 are a sentinel
+    // for "no earlier occurrence".
+    let mut table: Vec<Vec<usize>> = vec![vec![infinity; len2 + 2]; len1 + 2];
+    for idx in 0..(len1 + 1) {
+        table[idx + 1][1] = idx.saturating_mul(deletion_cost);
+    }
+    for jdx in 0..(len2 + 1) {
+        table[1][jdx + 1] = jdx.saturating_mul(insertion_cost);
+    }
+
+    // Last row at which each unit was seen in str1.
+    let mut last_row: HashMap<&T, usize> = HashMap::new();
+
+    for idx in 1..(len1 + 1) {
+        // Last column in this row at which str1[idx - 1] matched.
+        let mut last_match_col = 0;
+
+        for jdx in 1..(len2 + 1) {
+            let prev_row = *last_row.get(&str2[jdx - 1]).unwrap_or(&0);
+            let prev_col = last_match_col;
+
+            let sub_cost = if str1[idx - 1] == str2[jdx - 1] {
+                last_match_col = jdx;
+                0
+            } else {
+                substitution_cost
+            };
+
+            let mut distance = cmp::min(
+                cmp::min(
+                    table[idx][jdx + 1].saturating_add(deletion_cost),
+                    table[idx + 1][jdx].saturating_add(insertion_cost)
+                    ),
+                table[idx][jdx].saturating_add(sub_cost)
+                );
+
+            if prev_row > 0 && prev_col > 0 {
+                distance = cmp::min(
+                    distance,
+                    table[prev_row][prev_col]
+                        .saturating_add((idx - prev_row - 1).saturating_mul(deletion_cost))
+                        .saturating_add(transposition_cost)
+                        .saturating_add((jdx - prev_col - 1).saturating_mul(insertion_cost)),
+                    );
+            }
+            table[idx + 1][jdx + 1] = distance;
+        }
+        last_row.insert(&str1[idx - 1], idx);
+    }
+    table[len1 + 1][len2 + 1]
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adjacent_transpositions() {
+        assert_eq!(get_osa_distance(b"teh", b"the", 1, 1, 1, 1), 1);
+        assert_eq!(get_damerau_levenshtein_distance(b"teh", b"the", 1, 1, 1, 1), 1);
+        assert_eq!(get_osa_distance(b"abcdef", b"badcfe", 1, 1, 1, 1), 3);
+        assert_eq!(get_damerau_levenshtein_distance(b"abcdef", b"badcfe", 1, 1, 1, 1), 3);
+
+        // A costly transposition falls back to two substitutions.
+        assert_eq!(get_osa_distance(b"teh", b"the", 1, 1, 1, 5), 2);
+        assert_eq!(get_damerau_levenshtein_distance(b"teh", b"the", 1, 1, 1, 5), 2);
+    }
+
+    #[test]
+    fn osa_is_restricted() {
+        assert_eq!(get_osa_distance(b"ca", b"abc", 1, 1, 1, 1), 3);
+        assert_eq!(get_damerau_levenshtein_distance(b"ca", b"abc", 1, 1, 1, 1), 2);
+    }
+
+    #[test]
+    fn empty_and_identical() {
+        assert_eq!(get_osa_distance(b"", b"abc", 2, 3, 1, 1), 9);
+        assert_eq!(get_damerau_levenshtein_distance(b"abc", b"", 2, 3, 1, 1), 6);
+        assert_eq!(get_osa_distance(b"abc", b"abc", 1, 1, 1, 1), 0);
+        assert_eq!(get_damerau_levenshtein_distance(b"abc", b"abc", 1, 1, 1, 1), 0);
+    }
+
+    #[test]
+    fn huge_costs_saturate() {
+        let half = 1 << (usize::BITS - 1);
+        assert_eq!(get_osa_distance(b"abc", b"xyz", half, half, half, half), usize::MAX);
+        assert_eq!(get_damerau_levenshtein_distance(b"abc", b"xyz", half, half, half, half), usize::MAX);
+        assert_eq!(get_osa_distance(b"abc", b"acb", half, half, half, half), half);
+        assert_eq!(get_damerau_levenshtein_distance(b"ca", b"abc", half, half, half, half), usize::MAX);
+        assert_eq!(get_damerau_levenshtein_distance(b"abc", b"acb", half, half, half, half), half);
+    }
+}
@@ -0,0 +1,277 @@
+//! Groups near-duplicate strings of a single list. Every pair that passes
+//! the threshold is linked and the connected components, found with a
+//! union-find, are the clusters.
+
+use std::collections::HashMap;
+
+use rayon::prelude::*;
+
+use crate::process::Scorer;
+use crate::qgram::qgrams;
+use crate::unit::{ Symbol, Unit, with_unit_batches };
+
+
+/// Which pairs are compared. `None` compares every pair; the others only
+/// compare strings sharing a q-gram of `q` units (padded, so short strings
+/// have grams too) or a prefix of `n` units. Blocking can miss pairs that
+/// would pass the threshold but makes large lists tractable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Blocking {
+    #[default]
+    None,
+    QGram(usize),
+    Prefix(usize),
+}
+
+
+/// Disjoint sets over `0..n` with path halving and union by size.
+#[derive(Debug, Clone)]
+pub struct UnionFind {
+    parents: Vec<usize>,
+    sizes: Vec<usize>,
+}
+
+impl UnionFind {
+    pub fn new(n: usize) -> UnionFind {
+        UnionFind { parents: (0..n).collect(), sizes: vec![1; n] }
+    }
+
+    pub fn find(&mut self, mut item: usize) -> usize {
+        while self.parents[item] != item {
+            self.parents[item] = self.parents[self.parents[item]];
+            item = self.parents[item];
+        }
+        item
+    }
+
+    pub fn union(&mut self, item1: usize, item2: usize) {
+        let (mut root1, mut root2) = (self.find(item1), self.find(item2));
+        if root1 == root2 {
+            return;
+        }
+        if self.sizes[root1] < self.sizes[root2] {
+            std::mem::swap(&mut root1, &mut root2);
+        }
+        self.parents[root2] = root1;
+        self.sizes[root1] += self.sizes[root2];
+    }
+
+    /// The set of every item, numbered in order of first appearance.
+    pub fn labels(&mut self) -> Vec<usize> {
+        let mut labels = HashMap::new();
+        (0..self.parents.len())
+            .map(|item| {
+                let root = self.find(item);
+                let next = labels.len();
+                *labels.entry(root).or_insert(next)
+            })
+            .collect()
+    }
+}
+
+
+/// The cluster id of every string, numbered in order of first appearance.
+/// Strings that are equal are always in the same cluster and are scored
+/// once; other pairs are linked if they pass `threshold` under `scorer`
+/// (at least `threshold` for similarities, at most for distances). Pairs
+/// are scored in parallel.
+pub fn dedupe<S: AsRef<str> + Sync>(
+    strings: &[S],
+    scorer: &Scorer,
+    unit: Unit,
+    threshold: f32,
+    blocking: Blocking,
+    ) -> Vec<usize> {
+    let mut unique_ids: HashMap<&str, usize> = HashMap::new();
+    let mut unique: Vec<&str> = vec![];
+    let ids: Vec<usize> = strings
+        .iter()
+        .map(|string| {
+            *unique_ids.entry(string.as_ref()).or_insert_with(|| {
+                unique.push(string.as_ref());
+                unique.len() - 1
+            })
+        })
+        .collect();
+
+    let links = with_unit_batches!(unit, [unique => units], {
+        linked_pairs(&units, scorer, threshold, blocking)
+    });
+
+    let mut sets = UnionFind::new(unique.len());
+    for (id1, id2) in links {
+        sets.union(id1, id2);
+    }
+    // unique strings are numbered in order of first appearance, so their
+    // labels are too
+    let labels = sets.labels();
+    ids.into_iter().map(|id| labels[id]).collect()
+}
+
+
+/// Q-grams shared by more strings than this, such as the padded first
+/// letter of a common name, say little about whether two strings match, and
+/// comparing every pair in them would be quadratic again. They are not
+/// blocked on.
+const MAX_BLOCK_SIZE: usize = 1_000;
+
+/// The groups of strings compared under `Blocking::QGram` or
+/// `Blocking::Prefix`, and the groups each string is in.
+struct Blocks {
+    blocks: Vec<Vec<usize>>,
+    string_blocks: Vec<Vec<usize>>,
+}
+
+impl Blocks {
+    fn new<T: Symbol, C: AsRef<[T]>>(strings: &[C], blocking: Blocking) -> Blocks {
+        let mut blocks: HashMap<Vec<Option<T>>, Vec<usize>> = HashMap::new();
+        for (id, string) in strings.iter().enumerate() {
+            let string = string.as_ref();
+            match blocking {
+                Blocking::None => {}
+                Blocking::QGram(q) => {
+                    let mut grams = qgrams(string, q.max(1), true);
+                    grams.sort();
+                    grams.dedup();
+                    for gram in grams {
+                        blocks.entry(gram).or_default().push(id);
+                    }
+                }
+                Blocking::Prefix(length) => {
+                    let prefix = string[..length.min(string.len())].iter().cloned().map(Some).collect();
+                    blocks.entry(prefix).or_default().push(id);
+                }
+            }
+        }
+
+        let too_large = |block: &Vec<usize>| matches!(blocking, Blocking::QGram(_)) && block.len() > MAX_BLOCK_SIZE;
+        let blocks: Vec<Vec<usize>> = blocks.into_values().filter(|block| block.len() > 1 && !too_large(block)).collect();
+        let mut string_blocks = vec![vec![]; strings.len()];
+        for (block_id, block) in blocks.iter().enumerate() {
+            for &id in block {
+                string_blocks[id].push(block_id);
+            }
+        }
+        Blocks { blocks, string_blocks }
+    }
+
+    /// Calls `f` once for every string after `id1` sharing a block with it,
+    /// collecting them in `buffer` first, which is only reused to save
+    /// allocations.
+    fn candidates(&self, id1: usize, buffer: &mut Vec<usize>, f: impl FnMut(usize)) {
+        buffer.clear();
+        for &block_id in &self.string_blocks[id1] {
+            // ids are pushed in order, so the later strings are a suffix
+            let block = &self.blocks[block_id];
+            buffer.extend_from_slice(&block[block.partition_point(|&id| id <= id1)..]);
+        }
+        // strings sharing several blocks with `id1` are compared once
+        buffer.sort_unstable();
+        buffer.dedup();
+        buffer.iter().copied().for_each(f);
+    }
+}
+
+
+/// The pairs `(i, j)` with `i < j` that are compared under `blocking` and
+/// pass `threshold`.
+fn linked_pairs<T: Symbol, C: AsRef<[T]> + Sync>(
+    strings: &[C],
+    scorer: &Scorer,
+    threshold: f32,
+    blocking: Blocking,
+    ) -> Vec<(usize, usize)> {
+    let n = strings.len();
+    let blocks = Blocks::new(strings, blocking);
+
+    (0..n).into_par_iter().map_init(Vec::new, |buffer, id1| {
+        let mut links = vec![];
+        let compare = |id2: usize| {
+            let score = scorer.score(strings[id1].as_ref(), strings[id2].as_ref(), Some(threshold));
+            if scorer.passes(score, Some(threshold)) {
+                links.push((id1, id2));
+            }
+        };
+        match blocking {
+            Blocking::None => (id1 + 1..n).for_each(compare),
+            _ => blocks.candidates(id1, buffer, compare),
+        }
+        links
+    }).flatten().collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::EditDistance;
+
+    #[test]
+    fn union_find() {
+        let mut sets = UnionFind::new(5);
+        sets.union(3, 1);
+        sets.union(4, 3);
+        assert_eq!(sets.find(4), sets.find(1));
+        assert_ne!(sets.find(0), sets.find(1));
+        assert_eq!(sets.labels(), vec![0, 1, 2, 1, 1]);
+    }
+
+    #[test]
+    fn clusters_near_duplicates() {
+        let strings = ["jonathan smith", "acme corp", "jonathon smith", "acme corp", "jon smyth", "zeta"];
+        let jw = Scorer::JaroWinkler { max_prefix_length: 4, scaling_factor: 0.1 };
+
+        let clusters = dedupe(&strings, &jw, Unit::Byte, 0.9, Blocking::None);
+        assert_eq!(clusters, vec![0, 1, 0, 1, 2, 3]);
+        assert_eq!(dedupe(&strings, &jw, Unit::Byte, 0.9, Blocking::QGram(2)), clusters);
+        assert_eq!(dedupe(&strings, &jw, Unit::Char, 0.9, Blocking::Prefix(3)), clusters);
+
+        // Clusters are transitive: a-b and b-c link a and c.
+        let lev = Scorer::Distance(EditDistance::WeightedLevenshtein { deletion_cost: 1, insertion_cost: 1, substitution_cost: 1 });
+        assert_eq!(dedupe(&["abc", "abd", "xbd", "xyz"], &lev, Unit::Byte, 1.0, Blocking::None), vec![0, 0, 0, 1]);
+
+        // Blocking only compares strings that share a block.
+        assert_eq!(dedupe(&["abc", "xbc"], &lev, Unit::Byte, 1.0, Blocking::Prefix(1)), vec![0, 1]);
+        assert_eq!(dedupe::<&str>(&[], &jw, Unit::Byte, 0.9, Blocking::QGram(3)), Vec::<usize>::new());
+    }
+
+    /// The number of pairs scored under `blocking`.
+    fn comparisons(strings: &[&[u8]], blocking: Blocking) -> usize {
+        let blocks = Blocks::new(strings, blocking);
+        let mut buffer = vec![];
+        let mut count = 0;
+        for id1 in 0..strings.len() {
+            blocks.candidates(id1, &mut buffer, |id2| {
+                assert!(id2 > id1);
+                count += 1;
+            });
+        }
+        count
+    }
+
+    #[test]
+    fn blocking_reduces_comparisons() {
+        let letter = |idx: usize, pos: usize| (b'a' + ((idx * 8 + pos).wrapping_mul(2_654_435_761) >> 9) as u8 % 26) as char;
+        let names: Vec<String> = (0..300).map(|idx| (0..8).map(|pos| letter(idx, pos)).collect()).collect();
+        let names: Vec<&[u8]> = names.iter().map(|name| name.as_bytes()).collect();
+        let all = names.len() * (names.len() - 1) / 2;
+
+        // pairs sharing several grams are compared once
+        let qgram = comparisons(&names, Blocking::QGram(3));
+        let prefix = comparisons(&names, Blocking::Prefix(2));
+        assert!(qgram < all / 4, "{} of {}", qgram, all);
+        assert!(prefix < all / 10, "{} of {}", prefix, all);
+        assert_eq!(comparisons(&[b"abcd", b"abcd", b"abce"], Blocking::QGram(2)), 3);
+
+        // a gram every string has is too common to block on
+        let numbers: Vec<String> = (0..MAX_BLOCK_SIZE + 1).map(|idx| format!("#{}", idx)).collect();
+        let numbers: Vec<&[u8]> = numbers.iter().map(|number| number.as_bytes()).collect();
+        let all = numbers.len() * (numbers.len() - 1) / 2;
+        let qgram = comparisons(&numbers, Blocking::QGram(2));
+        assert!(qgram < all / 4, "{} of {}", qgram, all);
+
+        let lev = Scorer::Distance(EditDistance::WeightedLevenshtein { deletion_cost: 1, insertion_cost: 1, substitution_cost: 1 });
+        let clusters = dedupe(&["#12", "#13", "#1234"], &lev, Unit::Byte, 1.0, Blocking::QGram(2));
+        assert_eq!(clusters, vec![0, 0, 1]);
+    }
+}
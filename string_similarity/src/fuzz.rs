@@ -0,0 +1,325 @@
+use std::collections::BTreeSet;
+
+use crate::lcs::{ get_lcs_length, lcs_length_with_pattern };
+use crate::levenshtein::PatternMatchVector;
+use crate::preprocess::Preprocessor;
+use crate::unit::Symbol;
+
+
+/// The fuzzywuzzy / rapidfuzz ratio family. Every ratio is on a 0-100 scale
+/// and is built on the normalized indel similarity, so `Ratio` of two strings
+/// is `100 * 2 * lcs / (len1 + len2)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fuzz {
+    Ratio,
+    PartialRatio,
+    TokenSortRatio,
+    TokenSetRatio,
+    TokenRatio,
+    PartialTokenSortRatio,
+    PartialTokenSetRatio,
+    PartialTokenRatio,
+    WRatio,
+}
+
+impl Fuzz {
+    /// The processing applied when none is asked for. As in fuzzywuzzy the
+    /// token ratios and `WRatio` fully process their strings, while `Ratio`
+    /// and `PartialRatio` compare them as they are.
+    pub fn default_processor(&self) -> Preprocessor {
+        match self {
+            Fuzz::Ratio | Fuzz::PartialRatio => Preprocessor::default(),
+            _ => Preprocessor::all(),
+        }
+    }
+
+    pub fn score<T: Symbol>(&self, str1: &[T], str2: &[T]) -> f32 {
+        match self {
+            Fuzz::Ratio => ratio(str1, str2),
+            Fuzz::PartialRatio => partial_ratio(str1, str2),
+            Fuzz::TokenSortRatio => token_sort_ratio(str1, str2),
+            Fuzz::TokenSetRatio => token_set_ratio(str1, str2),
+            Fuzz::TokenRatio => token_ratio(str1, str2),
+            Fuzz::PartialTokenSortRatio => partial_token_sort_ratio(str1, str2),
+            Fuzz::PartialTokenSetRatio => partial_token_set_ratio(str1, str2),
+            Fuzz::PartialTokenRatio => partial_token_ratio(str1, str2),
+            Fuzz::WRatio => wratio(str1, str2),
+        }
+    }
+}
+
+
+/// Splits on whitespace units, dropping empty tokens.
+fn tokenize<T: Symbol>(string: &[T]) -> Vec<&[T]> {
+    string.split(|symbol| symbol.is_whitespace()).filter(|token| !token.is_empty()).collect()
+}
+
+/// Joins tokens with a single space.
+fn join<'a, T: Symbol + 'a, I: IntoIterator<Item = &'a [T]>>(tokens: I) -> Vec<T> {
+    let mut joined = Vec::new();
+    for token in tokens {
+        if !joined.is_empty() {
+            joined.push(T::space());
+        }
+        joined.extend_from_slice(token);
+    }
+    joined
+}
+
+fn sorted_tokens<T: Symbol>(string: &[T]) -> Vec<T> {
+    let mut tokens = tokenize(string);
+    tokens.sort();
+    join(tokens)
+}
+
+fn ratio_from_lcs(lcs: usize, len1: usize, len2: usize) -> f32 {
+    if len1 + len2 == 0 {
+        return 100.0;
+    }
+    100.0 * (2 * lcs) as f32 / (len1 + len2) as f32
+}
+
+
+/// Normalized indel similarity scaled to 0-100. Two empty strings score 100.
+pub fn ratio<T: Symbol>(str1: &[T], str2: &[T]) -> f32 {
+    ratio_from_lcs(get_lcs_length(str1, str2), str1.len(), str2.len())
+}
+
+
+/// The best `ratio` of the shorter string against any window of the longer
+/// one. Windows are as long as the shorter string, plus the shorter prefixes
+/// and suffixes of the longer string so that partial overlaps at either end
+/// are found as well. If both strings have the same length the search runs in
+/// both directions, which keeps the result symmetric.
+pub fn partial_ratio<T: Symbol>(str1: &[T], str2: &[T]) -> f32 {
+    if str1.is_empty() || str2.is_empty() {
+        return if str1.is_empty() && str2.is_empty() { 100.0 } else { 0.0 };
+    }
+
+    if str1.len() < str2.len() {
+        best_window_ratio(str1, str2)
+    } else if str1.len() > str2.len() {
+        best_window_ratio(str2, str1)
+    } else {
+        best_window_ratio(str1, str2).max(best_window_ratio(str2, str1))
+    }
+}
+
+fn best_window_ratio<T: Symbol>(shorter: &[T], longer: &[T]) -> f32 {
+    let pm = PatternMatchVector::new(shorter);
+    let len = shorter.len();
+    let mut best: f32 = 0.0;
+
+    let score = |window: &[T]| {
+        ratio_from_lcs(lcs_length_with_pattern(&pm, len, window), len, window.len())
+    };
+
+    for start in 0..=(longer.len() - len) {
+        best = best.max(score(&longer[start..start + len]));
+        if best == 100.0 {
+            return best;
+        }
+    }
+    for width in 1..len {
+        best = best.max(score(&longer[..width]));
+        best = best.max(score(&longer[longer.len() - width..]));
+    }
+    best
+}
+
+
+/// `ratio` after sorting the whitespace separated tokens of both strings.
+pub fn token_sort_ratio<T: Symbol>(str1: &[T], str2: &[T]) -> f32 {
+    ratio(&sorted_tokens(str1), &sorted_tokens(str2))
+}
+
+/// `partial_ratio` after sorting the whitespace separated tokens of both
+/// strings.
+pub fn partial_token_sort_ratio<T: Symbol>(str1: &[T], str2: &[T]) -> f32 {
+    partial_ratio(&sorted_tokens(str1), &sorted_tokens(str2))
+}
+
+
+/// The sorted tokens shared by both strings and the sorted tokens only in
+/// either one, each joined back into a string.
+struct TokenSets<T> {
+    intersection: Vec<T>,
+    diff_ab: Vec<T>,
+    diff_ba: Vec<T>,
+}
+
+impl<T: Symbol> TokenSets<T> {
+    /// `None` if either string has no tokens.
+    fn new(str1: &[T], str2: &[T]) -> Option<TokenSets<T>> {
+        let tokens1: BTreeSet<&[T]> = tokenize(str1).into_iter().collect();
+        let tokens2: BTreeSet<&[T]> = tokenize(str2).into_iter().collect();
+        if tokens1.is_empty() || tokens2.is_empty() {
+            return None;
+        }
+        Some(TokenSets {
+            intersection: join(tokens1.intersection(&tokens2).copied()),
+            diff_ab: join(tokens1.difference(&tokens2).copied()),
+            diff_ba: join(tokens2.difference(&tokens1).copied()),
+        })
+    }
+
+    /// The shared tokens followed by the tokens in `diff`.
+    fn with_intersection(&self, diff: &[T]) -> Vec<T> {
+        join([&self.intersection[..], diff].into_iter().filter(|part| !part.is_empty()))
+    }
+}
+
+
+/// Compares the tokens shared by both strings against the shared tokens plus
+/// the remaining tokens of either string, taking the best of the three
+/// ratios. One token set being a subset of the other scores 100. Strings
+/// without any tokens score 0.
+pub fn token_set_ratio<T: Symbol>(str1: &[T], str2: &[T]) -> f32 {
+    let sets = match TokenSets::new(str1, str2) {
+        Some(sets) => sets,
+        None => return 0.0,
+    };
+    if !sets.intersection.is_empty() && (sets.diff_ab.is_empty() || sets.diff_ba.is_empty()) {
+        return 100.0;
+    }
+
+    let combined_ab = sets.with_intersection(&sets.diff_ab);
+    let combined_ba = sets.with_intersection(&sets.diff_ba);
+    let mut best = ratio(&combined_ab, &combined_ba);
+    if !sets.intersection.is_empty() {
+        best = best.max(ratio(&sets.intersection, &combined_ab));
+        best = best.max(ratio(&sets.intersection, &combined_ba));
+    }
+    best
+}
+
+/// Any shared token scores 100, otherwise the `partial_ratio` of the tokens
+/// only in either string. Strings without any tokens score 0.
+pub fn partial_token_set_ratio<T: Symbol>(str1: &[T], str2: &[T]) -> f32 {
+    let sets = match TokenSets::new(str1, str2) {
+        Some(sets) => sets,
+        None => return 0.0,
+    };
+    if !sets.intersection.is_empty() {
+        return 100.0;
+    }
+    partial_ratio(&sets.diff_ab, &sets.diff_ba)
+}
+
+
+/// The better of `token_sort_ratio` and `token_set_ratio`.
+pub fn token_ratio<T: Symbol>(str1: &[T], str2: &[T]) -> f32 {
+    token_sort_ratio(str1, str2).max(token_set_ratio(str1, str2))
+}
+
+/// The better of `partial_token_sort_ratio` and `partial_token_set_ratio`.
+pub fn partial_token_ratio<T: Symbol>(str1: &[T], str2: &[T]) -> f32 {
+    partial_token_sort_ratio(str1, str2).max(partial_token_set_ratio(str1, str2))
+}
+
+
+/// fuzzywuzzy's weighted ratio. Strings of similar length are compared with
+/// `ratio` and the token ratios scaled by 0.95. Once one string is at least
+/// 1.5 times longer the partial ratios are used instead, scaled by 0.9, or by
+/// 0.6 once it is at least 8 times longer. An empty string scores 0.
+pub fn wratio<T: Symbol>(str1: &[T], str2: &[T]) -> f32 {
+    const UNBASE_SCALE: f32 = 0.95;
+
+    if str1.is_empty() || str2.is_empty() {
+        return 0.0;
+    }
+
+    let len_ratio = str1.len().max(str2.len()) as f32 / str1.len().min(str2.len()) as f32;
+    let base = ratio(str1, str2);
+
+    if len_ratio < 1.5 {
+        let token = token_sort_ratio(str1, str2).max(token_set_ratio(str1, str2));
+        return base.max(token * UNBASE_SCALE);
+    }
+
+    let partial_scale = if len_ratio < 8.0 { 0.9 } else { 0.6 };
+    let partial = partial_ratio(str1, str2) * partial_scale;
+    let partial_token = partial_token_sort_ratio(str1, str2).max(partial_token_set_ratio(str1, str2));
+    base.max(partial).max(partial_token * UNBASE_SCALE * partial_scale)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    #[test]
+    fn simple_ratios() {
+        assert!(close(ratio(b"this is a test", b"this is a test!"), 96.55172));
+        assert_eq!(ratio(b"", b""), 100.0);
+        assert_eq!(ratio(b"", b"abc"), 0.0);
+
+        assert_eq!(partial_ratio(b"this is a test", b"this is a test!"), 100.0);
+        assert_eq!(partial_ratio(b"yankees", b"new york yankees"), 100.0);
+        assert!(close(partial_ratio(b"abcd", b"xxabxx"), ratio(b"abcd", b"xxab")));
+        // Overlaps at the ends of the longer string count as well.
+        assert!(close(partial_ratio(b"abcd", b"cdxxxx"), ratio(b"abcd", b"cd")));
+        assert_eq!(partial_ratio(b"", b"abc"), 0.0);
+    }
+
+    #[test]
+    fn partial_ratio_is_best_window() {
+        let shorter = b"fuzzy wuzzy";
+        let longer = b"was fuzzy wazzy a bear";
+        let mut expected: f32 = 0.0;
+        for start in 0..=(longer.len() - shorter.len()) {
+            expected = expected.max(ratio(shorter, &longer[start..start + shorter.len()]));
+        }
+        for width in 1..shorter.len() {
+            expected = expected.max(ratio(shorter, &longer[..width]));
+            expected = expected.max(ratio(shorter, &longer[longer.len() - width..]));
+        }
+        assert_eq!(partial_ratio(shorter, longer), expected);
+        assert_eq!(partial_ratio(longer, shorter), expected);
+    }
+
+    #[test]
+    fn token_ratios() {
+        let str1 = "fuzzy wuzzy was a bear".as_bytes();
+        let str2 = "wuzzy fuzzy was a bear".as_bytes();
+        assert_eq!(token_sort_ratio(str1, str2), 100.0);
+        assert!(ratio(str1, str2) < 100.0);
+
+        assert_eq!(token_set_ratio(b"fuzzy was a bear", b"fuzzy fuzzy was a bear"), 100.0);
+        assert_eq!(token_set_ratio(b"new york mets", b"mets  new york"), 100.0);
+        assert_eq!(token_set_ratio(b"", b"abc"), 0.0);
+
+        // Neither set contains the other, so the combined strings are compared.
+        let mixed = token_set_ratio(b"a b d", b"a b c");
+        assert!(close(mixed, ratio(b"a b d", b"a b c")));
+
+        assert_eq!(partial_token_set_ratio(b"new york mets", b"the mets"), 100.0);
+        assert_eq!(partial_token_sort_ratio(b"york new", b"a new york"), 100.0);
+        assert_eq!(token_ratio(b"b a", b"a b"), 100.0);
+        assert_eq!(partial_token_ratio(b"x y", b"y"), 100.0);
+    }
+
+    #[test]
+    fn weighted_ratio() {
+        assert_eq!(wratio(b"new york mets", b"new york mets"), 100.0);
+        assert_eq!(wratio(b"", b"abc"), 0.0);
+        assert!(close(wratio(b"new york mets", b"the wonderful new york mets"), 90.0));
+        assert!(close(wratio(b"mets new york", b"new york mets"), 95.0));
+    }
+
+    #[test]
+    fn unit_aware_tokens() {
+        let str1: Vec<char> = "café crème".chars().collect();
+        let str2: Vec<char> = "crème café".chars().collect();
+        assert_eq!(token_sort_ratio(&str1, &str2), 100.0);
+
+        // Non-breaking spaces separate tokens when comparing chars.
+        let str1: Vec<char> = "a\u{a0}b".chars().collect();
+        let str2: Vec<char> = "b a".chars().collect();
+        assert_eq!(token_sort_ratio(&str1, &str2), 100.0);
+    }
+}
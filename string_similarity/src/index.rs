@@ -0,0 +1,526 @@
+//! Metric space indexes for repeated lookups against a fixed set of strings,
+//! which prune most of the set using the triangle inequality instead of
+//! scoring every string.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::hash::Hash;
+use std::ops::Range;
+
+use rayon::prelude::*;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::process::{ EditDistance, Scorer };
+use crate::unit::{ Symbol, Unit };
+
+mod file;
+
+pub use file::{ IndexError, MappedBkTree, MappedVpTree, VERSION };
+
+
+/// The units an index stores, owned so that they outlive the strings they
+/// were split from.
+pub trait IndexUnit: Symbol {
+    const UNIT: Unit;
+
+    fn split(string: &str) -> Vec<Self>;
+}
+
+impl IndexUnit for u8 {
+    const UNIT: Unit = Unit::Byte;
+
+    fn split(string: &str) -> Vec<u8> {
+        string.as_bytes().to_vec()
+    }
+}
+
+impl IndexUnit for char {
+    const UNIT: Unit = Unit::Char;
+
+    fn split(string: &str) -> Vec<char> {
+        string.chars().collect()
+    }
+}
+
+impl IndexUnit for Box<str> {
+    const UNIT: Unit = Unit::Grapheme;
+
+    fn split(string: &str) -> Vec<Box<str>> {
+        string.graphemes(true).map(Into::into).collect()
+    }
+}
+
+
+/// A match as `(id, distance)`, ordered by distance and then id so that
+/// the worst of a heap of matches is on top.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Candidate<D> {
+    distance: D,
+    id: usize,
+}
+
+impl<D: PartialOrd> Eq for Candidate<D> {}
+
+impl<D: PartialOrd> PartialOrd for Candidate<D> {
+    fn partial_cmp(&self, other: &Candidate<D>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<D: PartialOrd> Ord for Candidate<D> {
+    fn cmp(&self, other: &Candidate<D>) -> Ordering {
+        self.distance
+            .partial_cmp(&other.distance)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| self.id.cmp(&other.id))
+    }
+}
+
+/// The `k` closest candidates seen so far, with the furthest on top.
+struct Nearest<D> {
+    k: usize,
+    heap: BinaryHeap<Candidate<D>>,
+}
+
+impl<D: PartialOrd + Copy> Nearest<D> {
+    fn new(k: usize) -> Nearest<D> {
+        Nearest { k, heap: BinaryHeap::with_capacity(k + 1) }
+    }
+
+    fn push(&mut self, id: usize, distance: D) {
+        self.heap.push(Candidate { distance, id });
+        if self.heap.len() > self.k {
+            self.heap.pop();
+        }
+    }
+
+    /// The distance a candidate has to be within to be kept, `None` while
+    /// fewer than `k` have been seen.
+    fn radius(&self) -> Option<D> {
+        if self.heap.len() < self.k {
+            return None;
+        }
+        self.heap.peek().map(|furthest| furthest.distance)
+    }
+
+    fn into_sorted_vec(self) -> Vec<(usize, D)> {
+        self.heap.into_sorted_vec().into_iter().map(|candidate| (candidate.id, candidate.distance)).collect()
+    }
+}
+
+fn sort_matches<D: PartialOrd + Copy>(matches: &mut [(usize, D)]) {
+    matches.sort_by(|a, b| Candidate { distance: a.1, id: a.0 }.cmp(&Candidate { distance: b.1, id: b.0 }));
+}
+
+
+/// A BK-tree over whole number edit distances. Every item is a node whose
+/// children are keyed by their distance to it, so a search for items within
+/// `max_distance` of a query at distance `d` from a node only follows the
+/// children keyed `d - max_distance ..= d + max_distance`.
+#[derive(Debug, Clone)]
+pub struct BkTree<T> {
+    distance: EditDistance,
+    items: Vec<Vec<T>>,
+    /// The `(distance, child)` pairs of every item, the first item being
+    /// the root.
+    children: Vec<Vec<(usize, usize)>>,
+}
+
+/// Fails for distances that break the triangle inequality, which BK-tree
+/// pruning relies on: OSA, the legacy semantics, insertions that cost
+/// something other than deletions and damerau-levenshtein transpositions
+/// cheaper than half a deletion and an insertion, under which the distance
+/// isn't even a minimum.
+fn check_metric(distance: &EditDistance) -> Result<(), String> {
+    let (deletion_cost, insertion_cost) = match *distance {
+        EditDistance::WeightedLevenshtein { deletion_cost, insertion_cost, .. } => (deletion_cost, insertion_cost),
+        EditDistance::DamerauLevenshtein { deletion_cost, insertion_cost, transposition_cost, .. } => {
+            if transposition_cost.saturating_mul(2) < deletion_cost.saturating_add(insertion_cost) {
+                return Err("damerau_levenshtein needs 2 * transposition_cost >= insertion_cost + deletion_cost".to_string());
+            }
+            (deletion_cost, insertion_cost)
+        }
+        EditDistance::Indel => return Ok(()),
+        EditDistance::Osa { .. } => return Err("the osa distance breaks the triangle inequality, use damerau_levenshtein".to_string()),
+        EditDistance::LegacyWeightedLevenshtein { .. } => {
+            return Err("legacy_semantics break the triangle inequality".to_string());
+        }
+    };
+    if deletion_cost != insertion_cost {
+        return Err("deletion_cost and insertion_cost must be equal for the distance to be symmetric".to_string());
+    }
+    Ok(())
+}
+
+impl<T: Eq + Hash + Sync> BkTree<T> {
+    /// Fails for distances that break the triangle inequality, see
+    /// `check_metric`.
+    pub fn new(distance: EditDistance) -> Result<BkTree<T>, String> {
+        check_metric(&distance)?;
+        Ok(BkTree { distance, items: vec![], children: vec![] })
+    }
+
+    pub fn edit_distance(&self) -> EditDistance {
+        self.distance
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn get(&self, id: usize) -> Option<&[T]> {
+        self.items.get(id).map(Vec::as_slice)
+    }
+
+    /// Adds an item, returning its id. Ids count up from 0.
+    pub fn add(&mut self, item: Vec<T>) -> usize {
+        let id = self.items.len();
+        if id > 0 {
+            let mut node = 0;
+            loop {
+                let distance = self.distance.distance(&item, &self.items[node], usize::MAX);
+                match self.children[node].iter().find(|(key, _)| *key == distance) {
+                    Some(&(_, child)) => node = child,
+                    None => {
+                        self.children[node].push((distance, id));
+                        break;
+                    }
+                }
+            }
+        }
+        self.items.push(item);
+        self.children.push(vec![]);
+        id
+    }
+
+    /// Every item within `max_distance` of `query` as `(id, distance)`
+    /// pairs, closest first.
+    pub fn query(&self, query: &[T], max_distance: usize) -> Vec<(usize, usize)> {
+        bk_query(&self.children, self.len(), |id| self.distance.distance(query, &self.items[id], usize::MAX), max_distance)
+    }
+
+    /// The `k` items closest to `query` as `(id, distance)` pairs, closest
+    /// first. Ties are broken by id.
+    pub fn nearest(&self, query: &[T], k: usize) -> Vec<(usize, usize)> {
+        bk_nearest(&self.children, self.len(), |id| self.distance.distance(query, &self.items[id], usize::MAX), k)
+    }
+}
+
+/// The `(distance, child)` pairs of every node of a BK-tree, held in memory
+/// or read from a saved index.
+trait BkLinks {
+    fn children(&self, node: usize) -> impl Iterator<Item = (usize, usize)> + '_;
+}
+
+impl BkLinks for Vec<Vec<(usize, usize)>> {
+    fn children(&self, node: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self[node].iter().copied()
+    }
+}
+
+/// Searches a BK-tree of `len` items, `distance_to` giving the distance
+/// from the query to an item.
+fn bk_query(links: &impl BkLinks, len: usize, distance_to: impl Fn(usize) -> usize, max_distance: usize) -> Vec<(usize, usize)> {
+    let mut matches = vec![];
+    let mut stack = if len == 0 { vec![] } else { vec![0] };
+    while let Some(node) = stack.pop() {
+        let distance = distance_to(node);
+        if distance <= max_distance {
+            matches.push((node, distance));
+        }
+        let range = distance.saturating_sub(max_distance)..=distance.saturating_add(max_distance);
+        stack.extend(links.children(node).filter(|(key, _)| range.contains(key)).map(|(_, child)| child));
+    }
+    sort_matches(&mut matches);
+    matches
+}
+
+fn bk_nearest(links: &impl BkLinks, len: usize, distance_to: impl Fn(usize) -> usize, k: usize) -> Vec<(usize, usize)> {
+    if len == 0 || k == 0 {
+        return vec![];
+    }
+    let mut nearest = Nearest::new(k);
+    let mut stack = vec![0];
+    while let Some(node) = stack.pop() {
+        let distance = distance_to(node);
+        nearest.push(node, distance);
+        let radius = nearest.radius().unwrap_or(usize::MAX);
+        let range = distance.saturating_sub(radius)..=distance.saturating_add(radius);
+        stack.extend(links.children(node).filter(|(key, _)| range.contains(key)).map(|(_, child)| child));
+    }
+    nearest.into_sorted_vec()
+}
+
+
+/// A vantage point tree over a normalized score, searched with the distance
+/// `1 - similarity` (normalized distances as they are, fuzz ratios divided
+/// by 100 first). Every node splits the items below it into those within
+/// the median distance of its vantage point and those outside.
+///
+/// Searches are exact when that distance is a metric, such as the Jaccard
+/// distance over q-gram sets, and approximate otherwise, for example for
+/// jaro winkler. Items added after the tree was built are scanned until the
+/// next rebuild, which happens once they make up a third of the items.
+#[derive(Debug, Clone)]
+pub struct VpTree<T> {
+    scorer: Scorer,
+    items: Vec<Vec<T>>,
+    nodes: Vec<VpNode>,
+    /// Items `0..built` are in `nodes`, the rest are scanned.
+    built: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct VpNode {
+    item: usize,
+    radius: f32,
+    inside: Option<usize>,
+    outside: Option<usize>,
+}
+
+impl<T: Symbol> VpTree<T> {
+    /// Fails for raw distances, which have no upper bound to normalize
+    /// them by; use a `BkTree` for those.
+    pub fn new(scorer: Scorer) -> Result<VpTree<T>, String> {
+        if matches!(scorer, Scorer::Distance(_) | Scorer::CostDistance(_)) {
+            return Err("the metric must be a similarity or normalized distance, use a BK-tree for raw distances".to_string());
+        }
+        Ok(VpTree { scorer, items: vec![], nodes: vec![], built: 0 })
+    }
+
+    pub fn scorer(&self) -> &Scorer {
+        &self.scorer
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn get(&self, id: usize) -> Option<&[T]> {
+        self.items.get(id).map(Vec::as_slice)
+    }
+
+    fn distance(&self, str1: &[T], str2: &[T]) -> f32 {
+        vp_distance(&self.scorer, str1, str2)
+    }
+
+    /// Adds an item, returning its id. Ids count up from 0.
+    pub fn add(&mut self, item: Vec<T>) -> usize {
+        self.items.push(item);
+        if (self.items.len() - self.built) * 3 > self.items.len().max(96) {
+            self.build();
+        }
+        self.items.len() - 1
+    }
+
+    /// Rebuilds the tree over every item added so far.
+    pub fn build(&mut self) {
+        let mut ids: Vec<(usize, f32)> = (0..self.items.len()).map(|id| (id, 0.0)).collect();
+        self.nodes = Vec::with_capacity(ids.len());
+        self.build_node(&mut ids);
+        self.built = self.items.len();
+    }
+
+    /// Builds the subtree over `ids` and returns the index of its root. The
+    /// first id is the vantage point, the distances to it are computed in
+    /// parallel and the rest are split around their median.
+    fn build_node(&mut self, ids: &mut [(usize, f32)]) -> Option<usize> {
+        let (&mut (vantage_point, _), rest) = ids.split_first_mut()?;
+        let node = self.nodes.len();
+        self.nodes.push(VpNode { item: vantage_point, radius: 0.0, inside: None, outside: None });
+        if rest.is_empty() {
+            return Some(node);
+        }
+
+        let items = &self.items;
+        let vantage_point = &items[vantage_point];
+        rest.par_iter_mut().for_each(|(id, distance)| *distance = self.distance(vantage_point, &items[*id]));
+        let median = rest.len() / 2;
+        rest.select_nth_unstable_by(median, |a, b| a.1.total_cmp(&b.1));
+        let radius = rest[median].1;
+
+        let (inside, outside) = rest.split_at_mut(median + 1);
+        let inside = self.build_node(inside);
+        let outside = self.build_node(outside);
+        self.nodes[node] = VpNode { radius, inside, outside, ..self.nodes[node] };
+        Some(node)
+    }
+
+    /// Every item within `max_distance` of `query` as `(id, distance)`
+    /// pairs, closest first.
+    pub fn query(&self, query: &[T], max_distance: f32) -> Vec<(usize, f32)> {
+        vp_query(&self.nodes, self.built..self.len(), |id| self.distance(query, &self.items[id]), max_distance)
+    }
+
+    /// The `k` items closest to `query` as `(id, distance)` pairs, closest
+    /// first. Ties are broken by id.
+    pub fn nearest(&self, query: &[T], k: usize) -> Vec<(usize, f32)> {
+        vp_nearest(&self.nodes, self.built..self.len(), |id| self.distance(query, &self.items[id]), k)
+    }
+}
+
+/// The distance a VP-tree searches by.
+fn vp_distance<T: Symbol>(scorer: &Scorer, str1: &[T], str2: &[T]) -> f32 {
+    let score = scorer.score(str1, str2, None);
+    match scorer {
+        Scorer::NormalizedDistance(_) => score,
+        Scorer::Fuzz(_) => 1.0 - score / 100.0,
+        _ => 1.0 - score,
+    }
+}
+
+/// The nodes of a VP-tree, held in memory or read from a saved index. The
+/// root is node 0.
+trait VpNodes {
+    fn len(&self) -> usize;
+
+    fn node(&self, index: usize) -> VpNode;
+}
+
+impl VpNodes for Vec<VpNode> {
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn node(&self, index: usize) -> VpNode {
+        self[index]
+    }
+}
+
+/// Searches a VP-tree and scans the items in `pending`, `distance_to`
+/// giving the distance from the query to an item.
+fn vp_query(nodes: &impl VpNodes, pending: Range<usize>, distance_to: impl Fn(usize) -> f32, max_distance: f32) -> Vec<(usize, f32)> {
+    let mut matches = vec![];
+    let mut stack = if nodes.len() == 0 { vec![] } else { vec![0] };
+    while let Some(node) = stack.pop() {
+        let VpNode { item, radius, inside, outside } = nodes.node(node);
+        let distance = distance_to(item);
+        if distance <= max_distance {
+            matches.push((item, distance));
+        }
+        if distance - max_distance <= radius {
+            stack.extend(inside);
+        }
+        if distance + max_distance >= radius {
+            stack.extend(outside);
+        }
+    }
+    for id in pending {
+        let distance = distance_to(id);
+        if distance <= max_distance {
+            matches.push((id, distance));
+        }
+    }
+    sort_matches(&mut matches);
+    matches
+}
+
+fn vp_nearest(nodes: &impl VpNodes, pending: Range<usize>, distance_to: impl Fn(usize) -> f32, k: usize) -> Vec<(usize, f32)> {
+    if k == 0 {
+        return vec![];
+    }
+    let mut nearest = Nearest::new(k);
+    for id in pending {
+        nearest.push(id, distance_to(id));
+    }
+    let mut stack = if nodes.len() == 0 { vec![] } else { vec![0] };
+    while let Some(node) = stack.pop() {
+        let VpNode { item, radius, inside, outside } = nodes.node(node);
+        let distance = distance_to(item);
+        nearest.push(item, distance);
+        let tau = nearest.radius().unwrap_or(f32::INFINITY);
+        // the side the query falls on is searched first, as it is more
+        // likely to shrink tau
+        let (near, far) = if distance <= radius { (inside, outside) } else { (outside, inside) };
+        let far_reachable = if distance <= radius { distance + tau >= radius } else { distance - tau <= radius };
+        if far_reachable {
+            stack.extend(far);
+        }
+        stack.extend(near);
+    }
+    nearest.into_sorted_vec()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qgram::{ QGram, SetMeasure };
+
+    const WORDS: [&str; 12] = [
+        "book", "books", "boo", "cake", "cape", "cart", "boon", "cook", "cooks", "bake", "brook", "take",
+    ];
+
+    fn brute_force<D: PartialOrd + Copy>(distance: impl Fn(&[u8], &[u8]) -> D, query: &str, max: D) -> Vec<(usize, D)> {
+        let mut matches: Vec<(usize, D)> = WORDS
+            .iter()
+            .enumerate()
+            .map(|(id, word)| (id, distance(query.as_bytes(), word.as_bytes())))
+            .filter(|&(_, d)| d <= max)
+            .collect();
+        sort_matches(&mut matches);
+        matches
+    }
+
+    #[test]
+    fn bk_tree_matches_brute_force() {
+        let lev = EditDistance::WeightedLevenshtein { deletion_cost: 1, insertion_cost: 1, substitution_cost: 1 };
+        let mut tree = BkTree::new(lev).unwrap();
+        for word in WORDS {
+            tree.add(word.as_bytes().to_vec());
+        }
+        assert_eq!(tree.len(), WORDS.len());
+
+        for query in ["bo", "cook", "bakes", "xyz", ""] {
+            for max_distance in 0..4 {
+                let expected = brute_force(|a, b| lev.distance(a, b, usize::MAX), query, max_distance);
+                assert_eq!(tree.query(query.as_bytes(), max_distance), expected);
+            }
+            let all = brute_force(|a, b| lev.distance(a, b, usize::MAX), query, usize::MAX);
+            assert_eq!(tree.nearest(query.as_bytes(), 3), all[..3].to_vec());
+        }
+
+        let osa = EditDistance::Osa { deletion_cost: 1, insertion_cost: 1, substitution_cost: 1, transposition_cost: 1 };
+        assert!(BkTree::<u8>::new(osa).is_err());
+        let asymmetric = EditDistance::WeightedLevenshtein { deletion_cost: 1, insertion_cost: 2, substitution_cost: 1 };
+        assert!(BkTree::<u8>::new(asymmetric).is_err());
+        let cheap_swaps = EditDistance::DamerauLevenshtein { deletion_cost: 1, insertion_cost: 1, substitution_cost: 1, transposition_cost: 0 };
+        assert!(BkTree::<u8>::new(cheap_swaps).is_err());
+        let swaps = EditDistance::DamerauLevenshtein { deletion_cost: 2, insertion_cost: 2, substitution_cost: 3, transposition_cost: 2 };
+        assert!(BkTree::<u8>::new(swaps).is_ok());
+    }
+
+    #[test]
+    fn vp_tree_matches_brute_force() {
+        // The Jaccard distance is a metric, so searches are exact.
+        let jaccard = Scorer::QGram(QGram::new(SetMeasure::Jaccard).q(2).pad(true));
+        let distance = |a: &[u8], b: &[u8]| 1.0 - jaccard.score(a, b, None);
+
+        let mut tree = VpTree::new(jaccard.clone()).unwrap();
+        for (count, word) in WORDS.iter().enumerate() {
+            tree.add(word.as_bytes().to_vec());
+            if count == 6 {
+                tree.build();
+            }
+        }
+
+        for query in ["bo", "cook", "bakes", "xyz"] {
+            for max_distance in [0.0, 0.3, 0.6, 1.0] {
+                assert_eq!(tree.query(query.as_bytes(), max_distance), brute_force(distance, query, max_distance));
+            }
+            let all = brute_force(distance, query, f32::INFINITY);
+            assert_eq!(tree.nearest(query.as_bytes(), 4), all[..4].to_vec());
+        }
+
+        let lev = EditDistance::WeightedLevenshtein { deletion_cost: 1, insertion_cost: 1, substitution_cost: 1 };
+        assert!(VpTree::<u8>::new(Scorer::Distance(lev)).is_err());
+    }
+}
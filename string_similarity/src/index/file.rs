@@ -0,0 +1,1151 @@
+//! Saving indexes and opening them memory mapped, so that a large index is
+//! built once and every process using it shares the same pages.
+//!
+//! A file starts with a 32 byte header, every integer being little endian:
+//!
+//! | offset | contents                                                 |
+//! |--------|----------------------------------------------------------|
+//! | 0      | the magic bytes `STRSIMIX`                               |
+//! | 8      | the format version, a `u32`                              |
+//! | 12     | the CRC-32 of everything from offset 16 on, a `u32`      |
+//! | 16     | the length of the file, a `u64`                          |
+//! | 24     | the kind of tree, the unit and the preprocessor, a byte each, then 5 reserved bytes |
+//!
+//! followed by the metric, the indexed strings, the labels the strings were
+//! added under if they differ and the links of the tree. Strings and links
+//! are flat tables of `u64`s that are read in place rather than loaded.
+//!
+//! Only BK-trees and VP-trees are saved. A q-gram inverted index is out of
+//! scope, as the only one is the blocking `dedupe` builds for a single call.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::fs::{ self, File };
+use std::io::{ self, Write };
+use std::ops::{ Deref, Range };
+use std::path::Path;
+
+use memmap2::Mmap;
+use regex::Regex;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::check_jaro_winkler;
+use crate::fuzz::Fuzz;
+use crate::preprocess::Preprocessor;
+use crate::process::{ EditDistance, Scorer };
+use crate::qgram::{ QGram, SetMeasure };
+use crate::token::{ TokenMeasure, TokenSimilarity, Tokenizer };
+use crate::unit::Unit;
+use super::{ bk_nearest, bk_query, check_metric, vp_distance, vp_nearest, vp_query };
+use super::{ BkLinks, BkTree, IndexUnit, VpNode, VpNodes, VpTree };
+
+
+const MAGIC: &[u8; 8] = b"STRSIMIX";
+/// The version of the format written, and the only one read.
+pub const VERSION: u32 = 1;
+const HEADER_LEN: usize = 32;
+
+const BK_TREE: u8 = 0;
+const VP_TREE: u8 = 1;
+
+/// Links that aren't there, such as the children of a leaf of a VP-tree.
+const NONE: u64 = u64::MAX;
+
+
+/// Why an index couldn't be saved or opened.
+#[derive(Debug)]
+pub enum IndexError {
+    Io(io::Error),
+    /// The file isn't an index at all.
+    NotAnIndex,
+    /// The file was written in another version of the format, and has to be
+    /// rebuilt and saved again.
+    Version(u32),
+    /// The file is shorter or longer than it was written.
+    Truncated { expected: u64, found: u64 },
+    Checksum { expected: u32, found: u32 },
+    /// The checksum matched but the contents don't make sense, or the
+    /// file holds another kind of tree than was asked for.
+    Invalid(String),
+    /// The index uses something that can't be saved, such as a cost table.
+    Unsupported(String),
+}
+
+impl fmt::Display for IndexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IndexError::Io(err) => write!(f, "{}", err),
+            IndexError::NotAnIndex => write!(f, "not an index file"),
+            IndexError::Version(version) => write!(
+                f,
+                "the index file has format version {} but version {} is supported, rebuild the index and save it again",
+                version,
+                VERSION,
+            ),
+            IndexError::Truncated { expected, found } => {
+                write!(f, "the index file should be {} bytes long but is {}", expected, found)
+            }
+            IndexError::Checksum { expected, found } => {
+                write!(f, "the index file is corrupt, its checksum is {:08x} but should be {:08x}", found, expected)
+            }
+            IndexError::Invalid(reason) => write!(f, "invalid index file: {}", reason),
+            IndexError::Unsupported(reason) => write!(f, "can't save the index: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for IndexError {}
+
+impl From<io::Error> for IndexError {
+    fn from(err: io::Error) -> IndexError {
+        IndexError::Io(err)
+    }
+}
+
+fn invalid<T>(reason: &str) -> Result<T, IndexError> {
+    Err(IndexError::Invalid(reason.to_string()))
+}
+
+
+/// Appends little endian values to a file being built.
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn usize(&mut self, value: usize) {
+        self.u64(value as u64);
+    }
+
+    fn f32(&mut self, value: f32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn str(&mut self, value: &str) {
+        self.usize(value.len());
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    /// A table of strings: their `len + 1` offsets and then their bytes.
+    fn strings<S: AsRef<str>>(&mut self, strings: impl Iterator<Item = S> + Clone) {
+        let mut offset = 0;
+        self.u64(0);
+        for string in strings.clone() {
+            offset += string.as_ref().len();
+            self.usize(offset);
+        }
+        for string in strings {
+            self.bytes.extend_from_slice(string.as_ref().as_bytes());
+        }
+    }
+
+    /// Fills in the header and writes the file next to `path` before moving
+    /// it over `path`, so that processes with the old file mapped keep
+    /// reading the old file.
+    fn save(mut self, path: &Path, kind: u8, unit: Unit, preprocessor: Preprocessor) -> Result<(), IndexError> {
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        header.extend_from_slice(&[0; 4]);
+        header.extend_from_slice(&((HEADER_LEN + self.bytes.len()) as u64).to_le_bytes());
+        header.extend_from_slice(&[kind, unit_code(unit), preprocessor_code(preprocessor), 0, 0, 0, 0, 0]);
+        self.bytes.splice(0..0, header);
+        let checksum = crc32fast::hash(&self.bytes[16..]);
+        self.bytes[12..16].copy_from_slice(&checksum.to_le_bytes());
+        write_file(path, &self.bytes)
+    }
+}
+
+fn write_file(path: &Path, bytes: &[u8]) -> Result<(), IndexError> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    let mut file = File::create(&partial)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    fs::rename(&partial, path)?;
+    Ok(())
+}
+
+/// Reads little endian values from a file, checking every read is in
+/// bounds.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<Range<usize>, IndexError> {
+        let start = self.position;
+        match start.checked_add(len) {
+            Some(end) if end <= self.bytes.len() => {
+                self.position = end;
+                Ok(start..end)
+            }
+            _ => invalid("a table runs past the end of the file"),
+        }
+    }
+
+    fn u8(&mut self) -> Result<u8, IndexError> {
+        let range = self.take(1)?;
+        Ok(self.bytes[range.start])
+    }
+
+    fn u64(&mut self) -> Result<u64, IndexError> {
+        let range = self.take(8)?;
+        Ok(u64::from_le_bytes(self.bytes[range].try_into().unwrap()))
+    }
+
+    fn usize(&mut self) -> Result<usize, IndexError> {
+        usize::try_from(self.u64()?).or_else(|_| invalid("a length doesn't fit in memory"))
+    }
+
+    fn f32(&mut self) -> Result<f32, IndexError> {
+        let range = self.take(4)?;
+        Ok(f32::from_le_bytes(self.bytes[range].try_into().unwrap()))
+    }
+
+    fn str(&mut self) -> Result<&'a str, IndexError> {
+        let len = self.usize()?;
+        let range = self.take(len)?;
+        std::str::from_utf8(&self.bytes[range]).or_else(|_| invalid("a string isn't UTF-8"))
+    }
+
+    /// The byte range of a table of `len` `u64`s.
+    fn table(&mut self, len: usize) -> Result<Range<usize>, IndexError> {
+        let bytes = len.checked_mul(8).map_or_else(|| invalid("a table doesn't fit in memory"), Ok)?;
+        self.take(bytes)
+    }
+
+    /// A table written by `Writer::strings`, checked to hold `len` UTF-8
+    /// strings.
+    fn strings(&mut self, len: usize) -> Result<StringTable, IndexError> {
+        let offsets = self.table(len.checked_add(1).map_or_else(|| invalid("too many strings"), Ok)?)?;
+        let table = Table(&self.bytes[offsets.clone()]);
+        let blob_len = usize::try_from(table.get(len)).or_else(|_| invalid("a string table doesn't fit in memory"))?;
+        let blob = self.take(blob_len)?;
+        let text = std::str::from_utf8(&self.bytes[blob.clone()]).or_else(|_| invalid("a string isn't UTF-8"))?;
+        let mut previous = 0;
+        for index in 0..=len {
+            let offset = table.get(index);
+            if offset < previous || offset > blob_len as u64 || !text.is_char_boundary(offset as usize) {
+                return invalid("the string offsets are out of order");
+            }
+            previous = offset;
+        }
+        Ok(StringTable { offsets, blob })
+    }
+}
+
+/// A slice of little endian `u64`s, read without being copied or aligned.
+#[derive(Clone, Copy)]
+struct Table<'a>(&'a [u8]);
+
+impl<'a> Table<'a> {
+    fn get(&self, index: usize) -> u64 {
+        u64::from_le_bytes(self.0[index * 8..index * 8 + 8].try_into().unwrap())
+    }
+
+    fn len(&self) -> usize {
+        self.0.len() / 8
+    }
+}
+
+
+fn unit_code(unit: Unit) -> u8 {
+    match unit {
+        Unit::Byte => 0,
+        Unit::Char => 1,
+        Unit::Grapheme => 2,
+    }
+}
+
+fn preprocessor_code(preprocessor: Preprocessor) -> u8 {
+    preprocessor.case_fold as u8
+        | (preprocessor.strip_accents as u8) << 1
+        | (preprocessor.strip_punctuation as u8) << 2
+        | (preprocessor.normalize_whitespace as u8) << 3
+}
+
+fn write_edit_distance(writer: &mut Writer, distance: &EditDistance) {
+    match *distance {
+        EditDistance::WeightedLevenshtein { deletion_cost, insertion_cost, substitution_cost } => {
+            writer.u8(0);
+            [deletion_cost, insertion_cost, substitution_cost, 0].into_iter().for_each(|cost| writer.usize(cost));
+        }
+        EditDistance::LegacyWeightedLevenshtein { deletion_cost, insertion_cost, substitution_cost } => {
+            writer.u8(1);
+            [deletion_cost, insertion_cost, substitution_cost, 0].into_iter().for_each(|cost| writer.usize(cost));
+        }
+        EditDistance::Osa { deletion_cost, insertion_cost, substitution_cost, transposition_cost } => {
+            writer.u8(2);
+            [deletion_cost, insertion_cost, substitution_cost, transposition_cost].into_iter().for_each(|cost| writer.usize(cost));
+        }
+        EditDistance::DamerauLevenshtein { deletion_cost, insertion_cost, substitution_cost, transposition_cost } => {
+            writer.u8(3);
+            [deletion_cost, insertion_cost, substitution_cost, transposition_cost].into_iter().for_each(|cost| writer.usize(cost));
+        }
+        EditDistance::Indel => writer.u8(4),
+    }
+}
+
+fn read_edit_distance(reader: &mut Reader) -> Result<EditDistance, IndexError> {
+    let tag = reader.u8()?;
+    if tag == 4 {
+        return Ok(EditDistance::Indel);
+    }
+    let (deletion_cost, insertion_cost) = (reader.usize()?, reader.usize()?);
+    let (substitution_cost, transposition_cost) = (reader.usize()?, reader.usize()?);
+    match tag {
+        0 => Ok(EditDistance::WeightedLevenshtein { deletion_cost, insertion_cost, substitution_cost }),
+        1 => Ok(EditDistance::LegacyWeightedLevenshtein { deletion_cost, insertion_cost, substitution_cost }),
+        2 => Ok(EditDistance::Osa { deletion_cost, insertion_cost, substitution_cost, transposition_cost }),
+        3 => Ok(EditDistance::DamerauLevenshtein { deletion_cost, insertion_cost, substitution_cost, transposition_cost }),
+        _ => invalid("unknown edit distance"),
+    }
+}
+
+fn write_set_measure(writer: &mut Writer, measure: &SetMeasure) {
+    match *measure {
+        SetMeasure::Jaccard => writer.u8(0),
+        SetMeasure::SorensenDice => writer.u8(1),
+        SetMeasure::Overlap => writer.u8(2),
+        SetMeasure::Cosine => writer.u8(3),
+        SetMeasure::Tversky { alpha, beta } => {
+            writer.u8(4);
+            writer.f32(alpha);
+            writer.f32(beta);
+        }
+    }
+}
+
+fn read_set_measure(reader: &mut Reader) -> Result<SetMeasure, IndexError> {
+    match reader.u8()? {
+        0 => Ok(SetMeasure::Jaccard),
+        1 => Ok(SetMeasure::SorensenDice),
+        2 => Ok(SetMeasure::Overlap),
+        3 => Ok(SetMeasure::Cosine),
+        4 => SetMeasure::tversky(reader.f32()?, reader.f32()?).map_err(IndexError::Invalid),
+        _ => invalid("unknown set measure"),
+    }
+}
+
+const FUZZES: [Fuzz; 9] = [
+    Fuzz::Ratio,
+    Fuzz::PartialRatio,
+    Fuzz::TokenSortRatio,
+    Fuzz::TokenSetRatio,
+    Fuzz::TokenRatio,
+    Fuzz::PartialTokenSortRatio,
+    Fuzz::PartialTokenSetRatio,
+    Fuzz::PartialTokenRatio,
+    Fuzz::WRatio,
+];
+
+fn write_scorer(writer: &mut Writer, scorer: &Scorer) -> Result<(), IndexError> {
+    match scorer {
+        Scorer::JaroWinkler { max_prefix_length, scaling_factor } => {
+            writer.u8(0);
+            writer.usize(*max_prefix_length);
+            writer.f32(*scaling_factor);
+        }
+        Scorer::LegacyJaroWinkler { max_prefix_length, scaling_factor } => {
+            writer.u8(1);
+            writer.usize(*max_prefix_length);
+            writer.f32(*scaling_factor);
+        }
+        Scorer::LegacyJaccard => writer.u8(2),
+        Scorer::QGram(qgram) => {
+            writer.u8(3);
+            writer.usize(qgram.q);
+            writer.u8(qgram.pad as u8);
+            writer.u8(qgram.weighted as u8);
+            write_set_measure(writer, &qgram.measure);
+        }
+        Scorer::LcsSimilarity => writer.u8(4),
+        Scorer::Distance(distance) => {
+            writer.u8(5);
+            write_edit_distance(writer, distance);
+        }
+        Scorer::NormalizedDistance(distance) => {
+            writer.u8(6);
+            write_edit_distance(writer, distance);
+        }
+        Scorer::NormalizedSimilarity(distance) => {
+            writer.u8(7);
+            write_edit_distance(writer, distance);
+        }
+        Scorer::Fuzz(fuzz) => {
+            writer.u8(8);
+            writer.u8(FUZZES.iter().position(|other| other == fuzz).unwrap() as u8);
+        }
+        Scorer::Token(TokenSimilarity { tokenizer, measure }) => {
+            writer.u8(9);
+            match tokenizer {
+                Tokenizer::Whitespace => writer.u8(0),
+                Tokenizer::WordBoundary => writer.u8(1),
+                Tokenizer::Regex(pattern) => {
+                    writer.u8(2);
+                    writer.str(pattern.as_str());
+                }
+            }
+            match measure {
+                TokenMeasure::Set(measure) => {
+                    writer.u8(0);
+                    write_set_measure(writer, measure);
+                }
+                TokenMeasure::MongeElkan(inner) => {
+                    writer.u8(1);
+                    write_scorer(writer, inner)?;
+                }
+            }
+        }
+        Scorer::CostDistance(_) => return Err(IndexError::Unsupported("cost tables can't be saved".to_string())),
+    }
+    Ok(())
+}
+
+/// Reads a metric, checking its parameters as building it would. `nested`
+/// is set for the inner metric of monge-elkan, which can't be a token
+/// metric itself, so a file can't nest metrics any deeper.
+fn read_scorer(reader: &mut Reader, nested: bool) -> Result<Scorer, IndexError> {
+    Ok(match reader.u8()? {
+        tag @ (0 | 1) => {
+            let (max_prefix_length, scaling_factor) = (reader.usize()?, reader.f32()?);
+            check_jaro_winkler(max_prefix_length, scaling_factor).map_err(IndexError::Invalid)?;
+            if tag == 0 {
+                Scorer::JaroWinkler { max_prefix_length, scaling_factor }
+            } else {
+                Scorer::LegacyJaroWinkler { max_prefix_length, scaling_factor }
+            }
+        }
+        2 => Scorer::LegacyJaccard,
+        3 => {
+            let q = reader.usize()?;
+            if q == 0 {
+                return invalid("q must be at least 1");
+            }
+            let pad = reader.u8()? != 0;
+            let weighted = reader.u8()? != 0;
+            Scorer::QGram(QGram::new(read_set_measure(reader)?).q(q).pad(pad).weighted(weighted))
+        }
+        4 => Scorer::LcsSimilarity,
+        5 => Scorer::Distance(read_edit_distance(reader)?),
+        6 => Scorer::NormalizedDistance(read_edit_distance(reader)?),
+        7 => Scorer::NormalizedSimilarity(read_edit_distance(reader)?),
+        8 => match FUZZES.get(reader.u8()? as usize) {
+            Some(fuzz) => Scorer::Fuzz(*fuzz),
+            None => return invalid("unknown fuzz ratio"),
+        },
+        9 if nested => return invalid("the inner metric of monge_elkan can't be a token metric"),
+        9 => {
+            let tokenizer = match reader.u8()? {
+                0 => Tokenizer::Whitespace,
+                1 => Tokenizer::WordBoundary,
+                2 => Tokenizer::Regex(Regex::new(reader.str()?).or_else(|_| invalid("the tokenizer pattern doesn't compile"))?),
+                _ => return invalid("unknown tokenizer"),
+            };
+            let measure = match reader.u8()? {
+                0 => TokenMeasure::Set(read_set_measure(reader)?),
+                1 => {
+                    let inner = read_scorer(reader, true)?;
+                    if inner.is_distance() {
+                        return invalid("the inner metric of monge_elkan must be a similarity");
+                    }
+                    TokenMeasure::MongeElkan(Box::new(inner))
+                }
+                _ => return invalid("unknown token measure"),
+            };
+            Scorer::Token(TokenSimilarity { tokenizer, measure })
+        }
+        _ => return invalid("unknown metric"),
+    })
+}
+
+
+/// The bytes of an opened index, mapped or read into memory.
+enum Bytes {
+    Mapped(Mmap),
+    Owned(Vec<u8>),
+}
+
+impl Deref for Bytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Bytes::Mapped(map) => map,
+            Bytes::Owned(bytes) => bytes,
+        }
+    }
+}
+
+/// The byte ranges of a table of strings written by `Writer::strings`.
+#[derive(Debug, Clone)]
+struct StringTable {
+    offsets: Range<usize>,
+    blob: Range<usize>,
+}
+
+/// What every saved index holds besides its links, with the position the
+/// links start at.
+struct IndexFile {
+    bytes: Bytes,
+    unit: Unit,
+    preprocessor: Preprocessor,
+    scorer: Scorer,
+    len: usize,
+    strings: StringTable,
+    labels: Option<StringTable>,
+    links: usize,
+}
+
+impl IndexFile {
+    fn open(path: &Path, kind: u8, verify: bool) -> Result<IndexFile, IndexError> {
+        let file = File::open(path)?;
+        if file.metadata()?.len() < HEADER_LEN as u64 {
+            return Err(IndexError::NotAnIndex);
+        }
+        // SAFETY: the map is read only and indexes are replaced by renaming
+        // a new file over them rather than being written in place, so the
+        // pages don't change under us unless the file is modified by hand.
+        let map = unsafe { Mmap::map(&file)? };
+        IndexFile::parse(Bytes::Mapped(map), kind, verify)
+    }
+
+    fn parse(bytes: Bytes, kind: u8, verify: bool) -> Result<IndexFile, IndexError> {
+        if bytes.len() < HEADER_LEN || &bytes[..8] != MAGIC {
+            return Err(IndexError::NotAnIndex);
+        }
+        let version = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+        if version != VERSION {
+            return Err(IndexError::Version(version));
+        }
+        let expected = u64::from_le_bytes(bytes[16..24].try_into().unwrap());
+        if expected != bytes.len() as u64 {
+            return Err(IndexError::Truncated { expected, found: bytes.len() as u64 });
+        }
+        if verify {
+            let expected = u32::from_le_bytes(bytes[12..16].try_into().unwrap());
+            let found = crc32fast::hash(&bytes[16..]);
+            if expected != found {
+                return Err(IndexError::Checksum { expected, found });
+            }
+        }
+
+        match bytes[24] {
+            found if found == kind => {}
+            BK_TREE => return invalid("the file holds a BK-tree"),
+            VP_TREE => return invalid("the file holds a VP-tree"),
+            _ => return invalid("unknown kind of tree"),
+        }
+        let unit = match bytes[25] {
+            0 => Unit::Byte,
+            1 => Unit::Char,
+            2 => Unit::Grapheme,
+            _ => return invalid("unknown unit"),
+        };
+        let code = bytes[26];
+        let preprocessor = Preprocessor {
+            case_fold: code & 1 != 0,
+            strip_accents: code & 2 != 0,
+            strip_punctuation: code & 4 != 0,
+            normalize_whitespace: code & 8 != 0,
+        };
+
+        let mut reader = Reader { bytes: &bytes, position: HEADER_LEN };
+        let scorer = read_scorer(&mut reader, false)?;
+        let len = reader.usize()?;
+        let strings = reader.strings(len)?;
+        let labels = match reader.u8()? {
+            0 => None,
+            _ => Some(reader.strings(len)?),
+        };
+        let links = reader.position;
+        Ok(IndexFile { bytes, unit, preprocessor, scorer, len, strings, labels, links })
+    }
+
+    fn reader(&self) -> Reader<'_> {
+        Reader { bytes: &self.bytes, position: self.links }
+    }
+
+    fn table(&self, range: &Range<usize>) -> Table<'_> {
+        Table(&self.bytes[range.clone()])
+    }
+
+    fn string_at(&self, table: &StringTable, id: usize) -> &str {
+        let offsets = self.table(&table.offsets);
+        let start = table.blob.start + offsets.get(id) as usize;
+        let end = table.blob.start + offsets.get(id + 1) as usize;
+        std::str::from_utf8(&self.bytes[start..end]).expect("strings are checked when the file is opened")
+    }
+
+    fn string(&self, id: usize) -> &str {
+        self.string_at(&self.strings, id)
+    }
+
+    fn label(&self, id: usize) -> &str {
+        self.string_at(self.labels.as_ref().unwrap_or(&self.strings), id)
+    }
+
+    /// Fails unless `T` is the unit the file was saved with.
+    fn check_unit<T: IndexUnit>(&self) -> Result<(), IndexError> {
+        if T::UNIT != self.unit {
+            return Err(IndexError::Invalid(format!("the index was saved with unit '{}', not '{}'", self.unit, T::UNIT)));
+        }
+        Ok(())
+    }
+}
+
+fn split_bytes(string: &str) -> Cow<'_, [u8]> {
+    Cow::Borrowed(string.as_bytes())
+}
+
+fn split_chars(string: &str) -> Cow<'_, [char]> {
+    Cow::Owned(string.chars().collect())
+}
+
+fn split_graphemes(string: &str) -> Cow<'_, [&str]> {
+    Cow::Owned(string.graphemes(true).collect())
+}
+
+/// Splits `$query` into the unit of `$file` as `$units` and evaluates
+/// `$body` with `$split` a function splitting the indexed strings the same
+/// way.
+macro_rules! with_split {
+    ($file:expr, $query:expr, |$units:ident, $split:ident| $body:expr) => {
+        match $file.unit {
+            Unit::Byte => {
+                let $split = split_bytes;
+                let $units = $split($query);
+                $body
+            }
+            Unit::Char => {
+                let $split = split_chars;
+                let $units = $split($query);
+                $body
+            }
+            Unit::Grapheme => {
+                let $split = split_graphemes;
+                let $units = $split($query);
+                $body
+            }
+        }
+    };
+}
+
+/// Writes the strings of a tree, and the labels they were added under if
+/// any.
+fn write_strings<T: IndexUnit, S: AsRef<str>>(
+    writer: &mut Writer,
+    items: &[Vec<T>],
+    labels: Option<&[S]>,
+    ) -> Result<(), IndexError> {
+    writer.usize(items.len());
+    writer.strings(items.iter().map(|item| T::concat(item)));
+    match labels {
+        Some(labels) if labels.len() != items.len() => {
+            return Err(IndexError::Unsupported("there must be a label for every string".to_string()));
+        }
+        Some(labels) => {
+            writer.u8(1);
+            writer.strings(labels.iter());
+        }
+        None => writer.u8(0),
+    }
+    Ok(())
+}
+
+
+impl<T: IndexUnit> BkTree<T> {
+    /// Saves the tree to `path` for `MappedBkTree::open`. `preprocessor` is
+    /// what the strings went through before they were added, which is saved
+    /// so that queries can be processed the same way, and `labels` are the
+    /// strings as they were before, if they should be kept.
+    pub fn save<S: AsRef<str>>(
+        &self,
+        path: impl AsRef<Path>,
+        preprocessor: Preprocessor,
+        labels: Option<&[S]>,
+        ) -> Result<(), IndexError> {
+        let mut writer = Writer::default();
+        write_scorer(&mut writer, &Scorer::Distance(self.distance))?;
+        write_strings(&mut writer, &self.items, labels)?;
+        let mut offset = 0;
+        writer.u64(0);
+        for children in &self.children {
+            offset += children.len();
+            writer.usize(offset);
+        }
+        for &(distance, child) in self.children.iter().flatten() {
+            writer.usize(distance);
+            writer.usize(child);
+        }
+        writer.save(path.as_ref(), BK_TREE, T::UNIT, preprocessor)
+    }
+}
+
+/// The links of a saved BK-tree: the offsets of every node's children and
+/// the `(distance, child)` pairs.
+struct MappedLinks<'a> {
+    offsets: Table<'a>,
+    links: Table<'a>,
+}
+
+impl BkLinks for MappedLinks<'_> {
+    fn children(&self, node: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let links = self.links;
+        (self.offsets.get(node) as usize..self.offsets.get(node + 1) as usize)
+            .map(move |link| (links.get(2 * link) as usize, links.get(2 * link + 1) as usize))
+    }
+}
+
+/// A BK-tree saved with `BkTree::save`, read in place from a memory mapped
+/// file. Queries take strings, which are split into the unit the tree was
+/// saved with but aren't preprocessed.
+pub struct MappedBkTree {
+    file: IndexFile,
+    distance: EditDistance,
+    offsets: Range<usize>,
+    links: Range<usize>,
+}
+
+impl MappedBkTree {
+    /// Maps the index saved at `path`. `verify` checks the checksum, which
+    /// reads the whole file; the structure of the tree is always checked.
+    pub fn open(path: impl AsRef<Path>, verify: bool) -> Result<MappedBkTree, IndexError> {
+        MappedBkTree::parse(IndexFile::open(path.as_ref(), BK_TREE, verify)?)
+    }
+
+    /// Reads an index from the bytes of a saved file.
+    pub fn from_bytes(bytes: Vec<u8>, verify: bool) -> Result<MappedBkTree, IndexError> {
+        MappedBkTree::parse(IndexFile::parse(Bytes::Owned(bytes), BK_TREE, verify)?)
+    }
+
+    fn parse(file: IndexFile) -> Result<MappedBkTree, IndexError> {
+        let distance = match file.scorer {
+            Scorer::Distance(distance) => distance,
+            _ => return invalid("a BK-tree needs an edit distance"),
+        };
+        check_metric(&distance).map_err(IndexError::Invalid)?;
+        let mut reader = file.reader();
+        let offsets = reader.table(file.len + 1)?;
+        let n_links = file.table(&offsets).get(file.len);
+        let links = reader.table(usize::try_from(n_links).or_else(|_| invalid("too many links"))?.saturating_mul(2))?;
+
+        // children are always added after their parent, which rules out
+        // cycles
+        let (offset_table, link_table) = (file.table(&offsets), file.table(&links));
+        if offset_table.get(0) != 0 {
+            return invalid("the links are out of order");
+        }
+        for node in 0..file.len {
+            let (start, end) = (offset_table.get(node), offset_table.get(node + 1));
+            if end < start || end > n_links {
+                return invalid("the links are out of order");
+            }
+            for link in start as usize..end as usize {
+                let child = link_table.get(2 * link + 1);
+                if child <= node as u64 || child >= file.len as u64 {
+                    return invalid("a link points outside the tree");
+                }
+            }
+        }
+        Ok(MappedBkTree { file, distance, offsets, links })
+    }
+
+    pub fn edit_distance(&self) -> EditDistance {
+        self.distance
+    }
+
+    pub fn unit(&self) -> Unit {
+        self.file.unit
+    }
+
+    pub fn preprocessor(&self) -> Preprocessor {
+        self.file.preprocessor
+    }
+
+    pub fn len(&self) -> usize {
+        self.file.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.file.len == 0
+    }
+
+    /// The string indexed under `id`.
+    pub fn get(&self, id: usize) -> Option<&str> {
+        (id < self.len()).then(|| self.file.string(id))
+    }
+
+    /// The label saved for `id`, or the indexed string if there are none.
+    pub fn label(&self, id: usize) -> Option<&str> {
+        (id < self.len()).then(|| self.file.label(id))
+    }
+
+    fn mapped_links(&self) -> MappedLinks<'_> {
+        MappedLinks { offsets: self.file.table(&self.offsets), links: self.file.table(&self.links) }
+    }
+
+    /// Every string within `max_distance` of `query` as `(id, distance)`
+    /// pairs, closest first.
+    pub fn query(&self, query: &str, max_distance: usize) -> Vec<(usize, usize)> {
+        with_split!(self.file, query, |units, split| {
+            let distance_to = |id| self.distance.distance(&units, &split(self.file.string(id)), usize::MAX);
+            bk_query(&self.mapped_links(), self.len(), distance_to, max_distance)
+        })
+    }
+
+    /// The `k` strings closest to `query` as `(id, distance)` pairs, closest
+    /// first. Ties are broken by id.
+    pub fn nearest(&self, query: &str, k: usize) -> Vec<(usize, usize)> {
+        with_split!(self.file, query, |units, split| {
+            let distance_to = |id| self.distance.distance(&units, &split(self.file.string(id)), usize::MAX);
+            bk_nearest(&self.mapped_links(), self.len(), distance_to, k)
+        })
+    }
+
+    /// Copies the tree into memory, so that more strings can be added.
+    pub fn to_tree<T: IndexUnit>(&self) -> Result<BkTree<T>, IndexError> {
+        self.file.check_unit::<T>()?;
+        let links = self.mapped_links();
+        Ok(BkTree {
+            distance: self.distance,
+            items: (0..self.len()).map(|id| T::split(self.file.string(id))).collect(),
+            children: (0..self.len()).map(|node| links.children(node).collect()).collect(),
+        })
+    }
+
+    /// Saves the index to another path.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), IndexError> {
+        write_file(path.as_ref(), &self.file.bytes)
+    }
+}
+
+
+impl<T: IndexUnit> VpTree<T> {
+    /// Saves the tree to `path` for `MappedVpTree::open`, as
+    /// `BkTree::save` does. Items added since the tree was last built are
+    /// saved as they are and scanned by every query.
+    pub fn save<S: AsRef<str>>(
+        &self,
+        path: impl AsRef<Path>,
+        preprocessor: Preprocessor,
+        labels: Option<&[S]>,
+        ) -> Result<(), IndexError> {
+        let mut writer = Writer::default();
+        write_scorer(&mut writer, &self.scorer)?;
+        write_strings(&mut writer, &self.items, labels)?;
+        writer.usize(self.built);
+        writer.usize(self.nodes.len());
+        let link = |link: Option<usize>| link.map_or(NONE, |node| node as u64);
+        for node in &self.nodes {
+            writer.usize(node.item);
+            writer.u64(node.radius.to_bits() as u64);
+            writer.u64(link(node.inside));
+            writer.u64(link(node.outside));
+        }
+        writer.save(path.as_ref(), VP_TREE, T::UNIT, preprocessor)
+    }
+}
+
+/// The nodes of a saved VP-tree, four `u64`s each.
+struct MappedNodes<'a>(Table<'a>);
+
+impl VpNodes for MappedNodes<'_> {
+    fn len(&self) -> usize {
+        self.0.len() / 4
+    }
+
+    fn node(&self, index: usize) -> VpNode {
+        let link = |link: u64| (link != NONE).then_some(link as usize);
+        VpNode {
+            item: self.0.get(4 * index) as usize,
+            radius: f32::from_bits(self.0.get(4 * index + 1) as u32),
+            inside: link(self.0.get(4 * index + 2)),
+            outside: link(self.0.get(4 * index + 3)),
+        }
+    }
+}
+
+/// A VP-tree saved with `VpTree::save`, read in place from a memory mapped
+/// file as `MappedBkTree` is.
+pub struct MappedVpTree {
+    file: IndexFile,
+    built: usize,
+    nodes: Range<usize>,
+}
+
+impl MappedVpTree {
+    /// Maps the index saved at `path`. `verify` checks the checksum, which
+    /// reads the whole file; the structure of the tree is always checked.
+    pub fn open(path: impl AsRef<Path>, verify: bool) -> Result<MappedVpTree, IndexError> {
+        MappedVpTree::parse(IndexFile::open(path.as_ref(), VP_TREE, verify)?)
+    }
+
+    /// Reads an index from the bytes of a saved file.
+    pub fn from_bytes(bytes: Vec<u8>, verify: bool) -> Result<MappedVpTree, IndexError> {
+        MappedVpTree::parse(IndexFile::parse(Bytes::Owned(bytes), VP_TREE, verify)?)
+    }
+
+    fn parse(file: IndexFile) -> Result<MappedVpTree, IndexError> {
+        if matches!(file.scorer, Scorer::Distance(_) | Scorer::CostDistance(_)) {
+            return invalid("a VP-tree needs a similarity or normalized distance");
+        }
+        let mut reader = file.reader();
+        let built = reader.usize()?;
+        let n_nodes = reader.usize()?;
+        let nodes = reader.table(n_nodes.saturating_mul(4))?;
+        if built > file.len || n_nodes != built {
+            return invalid("the tree doesn't hold every item it was built over");
+        }
+
+        // children always come after their parent, which rules out cycles
+        let table = MappedNodes(file.table(&nodes));
+        for index in 0..n_nodes {
+            let node = table.node(index);
+            let in_tree = |child: Option<usize>| child.is_none_or(|child| child > index && child < n_nodes);
+            if node.item >= built || !in_tree(node.inside) || !in_tree(node.outside) {
+                return invalid("a node points outside the tree");
+            }
+        }
+        Ok(MappedVpTree { file, built, nodes })
+    }
+
+    pub fn scorer(&self) -> &Scorer {
+        &self.file.scorer
+    }
+
+    pub fn unit(&self) -> Unit {
+        self.file.unit
+    }
+
+    pub fn preprocessor(&self) -> Preprocessor {
+        self.file.preprocessor
+    }
+
+    pub fn len(&self) -> usize {
+        self.file.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.file.len == 0
+    }
+
+    /// The string indexed under `id`.
+    pub fn get(&self, id: usize) -> Option<&str> {
+        (id < self.len()).then(|| self.file.string(id))
+    }
+
+    /// The label saved for `id`, or the indexed string if there are none.
+    pub fn label(&self, id: usize) -> Option<&str> {
+        (id < self.len()).then(|| self.file.label(id))
+    }
+
+    fn mapped_nodes(&self) -> MappedNodes<'_> {
+        MappedNodes(self.file.table(&self.nodes))
+    }
+
+    /// Every string within `max_distance` of `query` as `(id, distance)`
+    /// pairs, closest first.
+    pub fn query(&self, query: &str, max_distance: f32) -> Vec<(usize, f32)> {
+        with_split!(self.file, query, |units, split| {
+            let distance_to = |id| vp_distance(&self.file.scorer, &units, &split(self.file.string(id)));
+            vp_query(&self.mapped_nodes(), self.built..self.len(), distance_to, max_distance)
+        })
+    }
+
+    /// The `k` strings closest to `query` as `(id, distance)` pairs, closest
+    /// first. Ties are broken by id.
+    pub fn nearest(&self, query: &str, k: usize) -> Vec<(usize, f32)> {
+        with_split!(self.file, query, |units, split| {
+            let distance_to = |id| vp_distance(&self.file.scorer, &units, &split(self.file.string(id)));
+            vp_nearest(&self.mapped_nodes(), self.built..self.len(), distance_to, k)
+        })
+    }
+
+    /// Copies the tree into memory, so that more strings can be added.
+    pub fn to_tree<T: IndexUnit>(&self) -> Result<VpTree<T>, IndexError> {
+        self.file.check_unit::<T>()?;
+        let nodes = self.mapped_nodes();
+        Ok(VpTree {
+            scorer: self.file.scorer.clone(),
+            items: (0..self.len()).map(|id| T::split(self.file.string(id))).collect(),
+            nodes: (0..nodes.len()).map(|index| nodes.node(index)).collect(),
+            built: self.built,
+        })
+    }
+
+    /// Saves the index to another path.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), IndexError> {
+        write_file(path.as_ref(), &self.file.bytes)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names() -> Vec<&'static str> {
+        vec!["jonathan", "joanna", "johan", "jon", "jonas", "anna", "hannah", "johannes", "ann"]
+    }
+
+    #[test]
+    fn bk_tree_round_trip() {
+        let lev = EditDistance::DamerauLevenshtein { deletion_cost: 1, insertion_cost: 1, substitution_cost: 1, transposition_cost: 1 };
+        let mut tree: BkTree<char> = BkTree::new(lev).unwrap();
+        for name in names() {
+            tree.add(name.chars().collect());
+        }
+        let labels: Vec<String> = names().iter().map(|name| name.to_uppercase()).collect();
+        let path = std::env::temp_dir().join(format!("strsim-bk-{}.idx", std::process::id()));
+        tree.save(&path, Preprocessor::all(), Some(&labels)).unwrap();
+
+        let mapped = MappedBkTree::open(&path, true).unwrap();
+        assert_eq!((mapped.len(), mapped.unit(), mapped.preprocessor()), (9, Unit::Char, Preprocessor::all()));
+        assert_eq!((mapped.get(1), mapped.label(1), mapped.get(9)), (Some("joanna"), Some("JOANNA"), None));
+        for query in ["jonatan", "ana", "xyz"] {
+            let units: Vec<char> = query.chars().collect();
+            assert_eq!(mapped.query(query, 2), tree.query(&units, 2));
+            assert_eq!(mapped.nearest(query, 3), tree.nearest(&units, 3));
+        }
+        let copy: BkTree<char> = mapped.to_tree().unwrap();
+        assert_eq!(copy.query(&['j', 'o', 'n'], 1), tree.query(&['j', 'o', 'n'], 1));
+        assert!(mapped.to_tree::<u8>().is_err());
+        assert!(matches!(MappedVpTree::open(&path, true), Err(IndexError::Invalid(_))));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn vp_tree_round_trip() {
+        let mut tree: VpTree<u8> = VpTree::new(Scorer::QGram(QGram::new(SetMeasure::Jaccard).q(2).pad(true))).unwrap();
+        for name in names() {
+            tree.add(name.as_bytes().to_vec());
+            if name == "jonas" {
+                tree.build();
+            }
+        }
+        let path = std::env::temp_dir().join(format!("strsim-vp-{}.idx", std::process::id()));
+        tree.save::<&str>(&path, Preprocessor::default(), None).unwrap();
+
+        let mapped = MappedVpTree::open(&path, true).unwrap();
+        assert_eq!(mapped.label(2), Some("johan"));
+        for query in ["jonatan", "ana", "xyz"] {
+            assert_eq!(mapped.query(query, 0.6), tree.query(query.as_bytes(), 0.6));
+            assert_eq!(mapped.nearest(query, 3), tree.nearest(query.as_bytes(), 3));
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        let mut tree: BkTree<u8> = BkTree::new(EditDistance::Indel).unwrap();
+        tree.add(b"abc".to_vec());
+        tree.add(b"abd".to_vec());
+        let path = std::env::temp_dir().join(format!("strsim-bad-{}.idx", std::process::id()));
+        tree.save::<&str>(&path, Preprocessor::default(), None).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(MappedBkTree::from_bytes(bytes.clone(), true).unwrap().query("abe", 2), vec![(0, 2), (1, 2)]);
+
+        let mut other_version = bytes.clone();
+        other_version[8] = 2;
+        let err = MappedBkTree::from_bytes(other_version, true).err().unwrap();
+        assert!(matches!(err, IndexError::Version(2)));
+        assert!(err.to_string().contains("version 2 but version 1"));
+
+        let mut corrupt = bytes.clone();
+        *corrupt.last_mut().unwrap() ^= 1;
+        assert!(matches!(MappedBkTree::from_bytes(corrupt.clone(), true), Err(IndexError::Checksum { .. })));
+        // without verifying, the structure is still checked
+        assert!(matches!(MappedBkTree::from_bytes(corrupt, false), Err(IndexError::Invalid(_))));
+
+        assert!(matches!(MappedBkTree::from_bytes(bytes[..bytes.len() - 8].to_vec(), true), Err(IndexError::Truncated { .. })));
+        assert!(matches!(MappedBkTree::from_bytes(b"not an index at all, honestly!!!".to_vec(), true), Err(IndexError::NotAnIndex)));
+    }
+
+    #[test]
+    fn rejects_loaded_non_metrics() {
+        let lev = EditDistance::WeightedLevenshtein { deletion_cost: 1, insertion_cost: 1, substitution_cost: 1 };
+        let mut tree: BkTree<u8> = BkTree::new(lev).unwrap();
+        tree.add(b"abc".to_vec());
+        let path = std::env::temp_dir().join(format!("strsim-metric-{}.idx", std::process::id()));
+        tree.save::<&str>(&path, Preprocessor::default(), None).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(MappedBkTree::from_bytes(bytes.clone(), true).is_ok());
+
+        // the edit distance tag follows the header and the scorer tag, so
+        // these swap the distance for legacy semantics, OSA and a
+        // damerau-levenshtein with free transpositions
+        for tag in [1, 2, 3] {
+            let mut patched = bytes.clone();
+            patched[HEADER_LEN + 1] = tag;
+            assert!(matches!(MappedBkTree::from_bytes(patched, false), Err(IndexError::Invalid(_))));
+        }
+    }
+
+    /// The file of a VP-tree over `scorer` holding a single string.
+    fn saved_vp_tree(scorer: Scorer) -> Vec<u8> {
+        let mut tree: VpTree<u8> = VpTree::new(scorer).unwrap();
+        tree.add(b"abc".to_vec());
+        let path = std::env::temp_dir().join(format!("strsim-scorer-{}.idx", std::process::id()));
+        tree.save::<&str>(&path, Preprocessor::default(), None).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn rejects_loaded_bad_scorers() {
+        let jw = Scorer::JaroWinkler { max_prefix_length: 4, scaling_factor: 0.1 };
+        let bytes = saved_vp_tree(jw.clone());
+        assert!(MappedVpTree::from_bytes(bytes.clone(), true).is_ok());
+        // the scaling factor follows the header, the scorer tag and the
+        // max prefix length
+        let patch = |factor: f32| {
+            let mut patched = bytes.clone();
+            patched[HEADER_LEN + 9..HEADER_LEN + 13].copy_from_slice(&factor.to_le_bytes());
+            MappedVpTree::from_bytes(patched, false)
+        };
+        assert!(patch(0.25).is_ok());
+        assert!(matches!(patch(0.5), Err(IndexError::Invalid(_))));
+
+        // the weights follow the scorer tag, q, pad, weighted and the measure tag
+        let bytes = saved_vp_tree(Scorer::QGram(QGram::new(SetMeasure::Tversky { alpha: 1.0, beta: 1.0 })));
+        let patch = |alpha: f32| {
+            let mut patched = bytes.clone();
+            patched[HEADER_LEN + 12..HEADER_LEN + 16].copy_from_slice(&alpha.to_le_bytes());
+            MappedVpTree::from_bytes(patched, false)
+        };
+        assert!(patch(0.5).is_ok());
+        assert!(matches!(patch(-1.0), Err(IndexError::Invalid(_))));
+        assert!(matches!(patch(f32::NAN), Err(IndexError::Invalid(_))));
+
+        // monge-elkan over monge-elkan over ... nested far deeper than the
+        // stack, with a valid checksum, fails instead of overflowing it
+        let monge_elkan = TokenSimilarity { tokenizer: Tokenizer::Whitespace, measure: TokenMeasure::MongeElkan(Box::new(jw)) };
+        let bytes = saved_vp_tree(Scorer::Token(monge_elkan));
+        assert!(MappedVpTree::from_bytes(bytes.clone(), true).is_ok());
+        let mut nested = bytes[..HEADER_LEN].to_vec();
+        for _ in 0..200_000 {
+            nested.extend_from_slice(&[9, 0, 1]);
+        }
+        nested.extend_from_slice(&bytes[HEADER_LEN + 3..]);
+        let len = nested.len() as u64;
+        nested[16..24].copy_from_slice(&len.to_le_bytes());
+        let checksum = crc32fast::hash(&nested[16..]);
+        nested[12..16].copy_from_slice(&checksum.to_le_bytes());
+        assert!(matches!(MappedVpTree::from_bytes(nested, true), Err(IndexError::Invalid(_))));
+    }
+}
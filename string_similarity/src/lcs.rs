@@ -0,0 +1,113 @@
+use std::cmp;
+use std::hash::Hash;
+
+use crate::levenshtein::PatternMatchVector;
+
+
+/// Length of the longest common subsequence using the bit-parallel
+/// algorithm of Hyyrö (2004), processing 64 units of the shorter string per
+/// machine word.
+pub fn get_lcs_length<T: Eq + Hash>(str1: &[T], str2: &[T]) -> usize {
+    let (pattern, text) = if str1.len() <= str2.len() { (str1, str2) } else { (str2, str1) };
+
+    if pattern.is_empty() {
+        return 0;
+    }
+
+    let pm = PatternMatchVector::new(pattern);
+    lcs_length_with_pattern(&pm, pattern.len(), text)
+}
+
+
+/// `get_lcs_length` against a pattern whose match vector has already been
+/// built, so that one pattern can be compared to many texts.
+pub(crate) fn lcs_length_with_pattern<T: Eq + Hash>(
+    pm: &PatternMatchVector<T>,
+    pattern_len: usize,
+    text: &[T],
+    ) -> usize {
+    let blocks = pm.blocks();
+
+    // Zero bits of `s` mark the pattern positions that end a longer common subsequence.
+    let mut s: Vec<u64> = vec![!0; blocks];
+
+    for symbol in text {
+        let masks = pm.get(symbol);
+        let mut carry = false;
+
+        for block in 0..blocks {
+            let matches = s[block] & masks[block];
+            let (sum, carry1) = s[block].overflowing_add(matches);
+            let (sum, carry2) = sum.overflowing_add(carry as u64);
+            carry = carry1 || carry2;
+            s[block] = sum | (s[block] & !masks[block]);
+        }
+    }
+
+    let tail_bits = pattern_len % 64;
+    s.iter().enumerate().map(|(block, bits)| {
+        let zeros = !bits;
+        if block + 1 == blocks && tail_bits != 0 {
+            (zeros & ((1u64 << tail_bits) - 1)).count_ones() as usize
+        } else {
+            zeros.count_ones() as usize
+        }
+    }).sum()
+}
+
+
+/// Longest common subsequence length divided by the length of the longer
+/// string, in `[0, 1]`. Two empty strings are identical.
+pub fn get_lcs_similarity<T: Eq + Hash>(str1: &[T], str2: &[T]) -> f32 {
+    let max_len = cmp::max(str1.len(), str2.len());
+    if max_len == 0 {
+        return 1.0;
+    }
+    get_lcs_length(str1, str2) as f32 / max_len as f32
+}
+
+
+/// Edit distance allowing only insertions and deletions, which is the
+/// number of units not part of the longest common subsequence.
+pub fn get_indel_distance<T: Eq + Hash>(str1: &[T], str2: &[T]) -> usize {
+    str1.len() + str2.len() - 2 * get_lcs_length(str1, str2)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strategies::string;
+    use proptest::prelude::*;
+
+    fn dp_lcs(str1: &[u8], str2: &[u8]) -> usize {
+        let mut table = vec![vec![0; str2.len() + 1]; str1.len() + 1];
+        for (idx, c1) in str1.iter().enumerate() {
+            for (jdx, c2) in str2.iter().enumerate() {
+                table[idx + 1][jdx + 1] = if c1 == c2 {
+                    table[idx][jdx] + 1
+                } else {
+                    cmp::max(table[idx][jdx + 1], table[idx + 1][jdx])
+                };
+            }
+        }
+        table[str1.len()][str2.len()]
+    }
+
+    #[test]
+    fn lcs_and_indel() {
+        assert_eq!(get_lcs_length(b"kitten", b"sitting"), 4);
+        assert_eq!(get_indel_distance(b"kitten", b"sitting"), 5);
+        assert_eq!(get_lcs_similarity(b"kitten", b"sitting"), 4.0 / 7.0);
+        assert_eq!(get_lcs_length(b"", b"abc"), 0);
+        assert_eq!(get_indel_distance(b"", b"abc"), 3);
+        assert_eq!(get_lcs_similarity(b"", b""), 1.0);
+    }
+
+    proptest! {
+        #[test]
+        fn blocked_matches_dp(str1 in string(b"abcde", 0..300), str2 in string(b"abcde", 0..300)) {
+            prop_assert_eq!(get_lcs_length(&str1, &str2), dp_lcs(&str1, &str2));
+        }
+    }
+}
@@ -0,0 +1,646 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::costs::CostTable;
+use crate::unit::Symbol;
+
+
+/// Bitmasks of the positions at which each symbol occurs in a pattern, split
+/// into 64 bit blocks. Symbols that don't occur in the pattern map to zeros.
+pub(crate) struct PatternMatchVector<'a, T> {
+    blocks: usize,
+    masks: HashMap<&'a T, Vec<u64>>,
+    empty: Vec<u64>,
+}
+
+impl<'a, T: Eq + Hash> PatternMatchVector<'a, T> {
+    pub(crate) fn new(pattern: &'a [T]) -> PatternMatchVector<'a, T> {
+        let blocks = pattern.len().div_ceil(64);
+        let mut masks: HashMap<&T, Vec<u64>> = HashMap::new();
+
+        for (idx, symbol) in pattern.iter().enumerate() {
+            let mask = masks.entry(symbol).or_insert_with(|| vec![0; blocks]);
+            mask[idx / 64] |= 1 << (idx % 64);
+        }
+        PatternMatchVector { blocks, masks, empty: vec![0; blocks] }
+    }
+
+    pub(crate) fn blocks(&self) -> usize {
+        self.blocks
+    }
+
+    pub(crate) fn get(&self, symbol: &T) -> &[u64] {
+        self.masks.get(symbol).unwrap_or(&self.empty)
+    }
+}
+
+
+/// Unit cost levenshtein distance using the bit-parallel algorithm of Myers
+/// (1999) as formulated by Hyyrö (2003). The shorter string is used as the
+/// pattern, so strings of up to 64 units run in a single machine word per
+/// column and longer ones in blocks of 64.
+pub fn get_levenshtein_distance<T: Eq + Hash>(str1: &[T], str2: &[T]) -> usize {
+    let (pattern, text) = if str1.len() <= str2.len() { (str1, str2) } else { (str2, str1) };
+
+    if pattern.is_empty() {
+        return text.len();
+    }
+
+    let pm = PatternMatchVector::new(pattern);
+    if pm.blocks() == 1 {
+        myers_single_word(&pm, pattern.len(), text)
+    } else {
+        myers_blocked(&pm, pattern.len(), text)
+    }
+}
+
+
+fn myers_single_word<T: Eq + Hash>(pm: &PatternMatchVector<T>, pattern_len: usize, text: &[T]) -> usize {
+    let last = 1u64 << (pattern_len - 1);
+    let mut vp: u64 = !0;
+    let mut vn: u64 = 0;
+    let mut distance = pattern_len;
+
+    for symbol in text {
+        let x = pm.get(symbol)[0] | vn;
+        let d0 = (((x & vp).wrapping_add(vp)) ^ vp) | x;
+        let hp = vn | !(d0 | vp);
+        let hn = vp & d0;
+
+        if hp & last != 0 {
+            distance += 1;
+        } else if hn & last != 0 {
+            distance -= 1;
+        }
+
+        let hp = (hp << 1) | 1;
+        let hn = hn << 1;
+        vp = hn | !(d0 | hp);
+        vn = hp & d0;
+    }
+    distance
+}
+
+
+fn myers_blocked<T: Eq + Hash>(pm: &PatternMatchVector<T>, pattern_len: usize, text: &[T]) -> usize {
+    let blocks = pm.blocks();
+    let last = 1u64 << ((pattern_len - 1) % 64);
+    let mut vp: Vec<u64> = vec![!0; blocks];
+    let mut vn: Vec<u64> = vec![0; blocks];
+    let mut distance = pattern_len;
+
+    for symbol in text {
+        let masks = pm.get(symbol);
+
+        // The top row of the table increases by one per column.
+        let mut hp_carry: u64 = 1;
+        let mut hn_carry: u64 = 0;
+
+        for block in 0..blocks {
+            let x = masks[block] | hn_carry;
+            let d0 = (((x & vp[block]).wrapping_add(vp[block])) ^ vp[block]) | x | vn[block];
+            let hp = vn[block] | !(d0 | vp[block]);
+            let hn = vp[block] & d0;
+
+            let (hp_out, hn_out) = if block + 1 < blocks {
+                (hp >> 63, hn >> 63)
+            } else {
+                ((hp & last != 0) as u64, (hn & last != 0) as u64)
+            };
+
+            let hp = (hp << 1) | hp_carry;
+            let hn = (hn << 1) | hn_carry;
+            vp[block] = hn | !(d0 | hp);
+            vn[block] = hp & d0;
+
+            hp_carry = hp_out;
+            hn_carry = hn_out;
+        }
+
+        distance += hp_carry as usize;
+        distance -= hn_carry as usize;
+    }
+    distance
+}
+
+
+/// Weighted levenshtein distance restricted to Ukkonen's band. A cell on
+/// diagonal `d = i - j` can't be reached for less than the indels needed to
+/// get onto that diagonal, nor left for less than the indels needed to get
+/// back to the final one, so only diagonals where those together fit within
+/// `max_distance` are computed. Returns `max_distance + 1` as soon as a row
+/// of the band exceeds `max_distance`. Costs add up saturating at
+/// `usize::MAX`, which is also what a distance too large to count comes out
+/// as.
+pub(crate) fn banded_weighted_levenshtein_distance<T: Eq>(
+    str1: &[T],
+    str2: &[T],
+    deletion_cost: usize,
+    insertion_cost: usize,
+    substitution_cost: usize,
+    max_distance: usize,
+    ) -> usize {
+    let len1 = str1.len();
+    let len2 = str2.len();
+    let exceeded = max_distance.saturating_add(1);
+    let infinity = usize::MAX;
+
+    let indel_cost = |diagonal: isize| -> usize {
+        if diagonal > 0 {
+            (diagonal as usize).saturating_mul(deletion_cost)
+        } else {
+            (diagonal.unsigned_abs()).saturating_mul(insertion_cost)
+        }
+    };
+    let final_diagonal = len1 as isize - len2 as isize;
+    let in_band = |diagonal: isize| {
+        indel_cost(diagonal).saturating_add(indel_cost(final_diagonal - diagonal)) <= max_distance
+    };
+
+    // The band always contains the start and end diagonals unless the length
+    // difference alone exceeds `max_distance`.
+    if !in_band(0) {
+        return exceeded;
+    }
+    let mut band_lo: isize = 0;
+    while band_lo > -(len2 as isize) && in_band(band_lo - 1) {
+        band_lo -= 1;
+    }
+    let mut band_hi: isize = 0;
+    while band_hi < len1 as isize && in_band(band_hi + 1) {
+        band_hi += 1;
+    }
+
+    // Columns of row `idx` inside the band, as an inclusive range.
+    let columns = |idx: usize| -> (usize, usize) {
+        let lo = cmp::max(0, idx as isize - band_hi) as usize;
+        let hi = cmp::min(len2 as isize, idx as isize - band_lo) as usize;
+        (lo, hi)
+    };
+
+    let mut prev: Vec<usize> = vec![infinity; len2 + 2];
+    let mut curr: Vec<usize> = vec![infinity; len2 + 2];
+
+    let (lo, hi) = columns(0);
+    for (jdx, cell) in prev.iter_mut().enumerate().take(hi + 1).skip(lo) {
+        *cell = jdx.saturating_mul(insertion_cost);
+    }
+
+    for idx in 1..=len1 {
+        let (lo, hi) = columns(idx);
+        if lo > 0 {
+            curr[lo - 1] = infinity;
+        }
+        curr[hi + 1] = infinity;
+
+        let mut row_min = infinity;
+        for jdx in lo..=hi {
+            let value = if jdx == 0 {
+                prev[0].saturating_add(deletion_cost)
+            } else {
+                let sub_cost = if str1[idx - 1] == str2[jdx - 1] { 0 } else { substitution_cost };
+                cmp::min(
+                    cmp::min(
+                        prev[jdx].saturating_add(deletion_cost),
+                        curr[jdx - 1].saturating_add(insertion_cost)
+                        ),
+                    prev[jdx - 1].saturating_add(sub_cost)
+                    )
+            };
+            curr[jdx] = value;
+            row_min = cmp::min(row_min, curr[jdx]);
+        }
+
+        // Every alignment passes through each row, so the row minimum bounds the distance.
+        if row_min > max_distance {
+            return exceeded;
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+    cmp::min(prev[len2], exceeded)
+}
+
+
+/// Weighted levenshtein distance with per unit costs from `costs`. Returns
+/// `max_distance + 1` as soon as every alignment is known to cost more than
+/// `max_distance`.
+pub fn get_cost_table_levenshtein_distance<T: Symbol>(
+    str1: &[T],
+    str2: &[T],
+    costs: &CostTable,
+    max_distance: f64,
+    ) -> f64 {
+    let (ids1, deletion_costs) = costs.deletion_costs(str1);
+    let (ids2, insertion_costs) = costs.insertion_costs(str2);
+
+    let mut prev: Vec<f64> = Vec::with_capacity(str2.len() + 1);
+    prev.push(0.0);
+    for cost in &insertion_costs {
+        prev.push(prev[prev.len() - 1] + cost);
+    }
+    let mut curr = vec![0.0; str2.len() + 1];
+
+    for idx in 1..=str1.len() {
+        let deletion_cost = deletion_costs[idx - 1];
+        curr[0] = prev[0] + deletion_cost;
+        let mut row_min = curr[0];
+
+        for jdx in 1..=str2.len() {
+            let sub_cost = if str1[idx - 1] == str2[jdx - 1] {
+                0.0
+            } else {
+                costs.substitution_cost(ids1[idx - 1], ids2[jdx - 1])
+            };
+            curr[jdx] = (prev[jdx] + deletion_cost)
+                .min(curr[jdx - 1] + insertion_costs[jdx - 1])
+                .min(prev[jdx - 1] + sub_cost);
+            row_min = row_min.min(curr[jdx]);
+        }
+
+        if row_min > max_distance {
+            return max_distance + 1.0;
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+
+    let distance = prev[str2.len()];
+    if distance > max_distance { max_distance + 1.0 } else { distance }
+}
+
+
+/// A single edit turning one string into another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditOp {
+    Replace,
+    Insert,
+    Delete,
+}
+
+impl EditOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EditOp::Replace => "replace",
+            EditOp::Insert  => "insert",
+            EditOp::Delete  => "delete",
+        }
+    }
+}
+
+/// An edit at `src_pos` in the source and `dest_pos` in the destination,
+/// following python-Levenshtein: a replace or delete acts on `src[src_pos]`
+/// and a replace or insert uses `dest[dest_pos]`, with inserts placed before
+/// `src[src_pos]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Editop {
+    pub op: EditOp,
+    pub src_pos: usize,
+    pub dest_pos: usize,
+}
+
+/// A difflib style opcode saying that `src[src_start..src_end]` becomes
+/// `dest[dest_start..dest_end]`. A `tag` of `None` means the ranges are equal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Opcode {
+    pub tag: Option<EditOp>,
+    pub src_start: usize,
+    pub src_end: usize,
+    pub dest_start: usize,
+    pub dest_end: usize,
+}
+
+impl Opcode {
+    pub fn tag_str(&self) -> &'static str {
+        self.tag.map_or("equal", |op| op.as_str())
+    }
+}
+
+
+/// Backtracks through the full weighted levenshtein table to find a cheapest
+/// edit script turning `str1` into `str2`. Where several scripts are equally
+/// cheap, matches and substitutions are preferred over deletions, and
+/// deletions over insertions. Costs add up saturating at `usize::MAX`, past
+/// which every script counts as equally cheap.
+pub fn get_weighted_levenshtein_editops<T: Eq>(
+    str1: &[T],
+    str2: &[T],
+    deletion_cost: usize,
+    insertion_cost: usize,
+    substitution_cost: usize,
+    ) -> Vec<Editop> {
+    let len1 = str1.len();
+    let len2 = str2.len();
+
+    let mut table: Vec<Vec<usize>> = vec![vec![0; len2 + 1]; len1 + 1];
+
+    for idx in 1..(len1 + 1) {
+        table[idx][0] = table[idx - 1][0].saturating_add(deletion_cost);
+    }
+
+    for idx in 1..(len2 + 1) {
+        table[0][idx] = table[0][idx - 1].saturating_add(insertion_cost);
+    }
+
+    for (idx, c1) in str1.iter().enumerate() {
+        for (jdx, c2) in str2.iter().enumerate() {
+            let sub_cost = if c1 == c2 { 0 } else { substitution_cost };
+            table[idx + 1][jdx + 1] = cmp::min(
+                cmp::min(
+                    table[idx][jdx + 1].saturating_add(deletion_cost),
+                    table[idx + 1][jdx].saturating_add(insertion_cost)
+                    ),
+                table[idx][jdx].saturating_add(sub_cost)
+                );
+        }
+    }
+
+    let mut editops: Vec<Editop> = Vec::new();
+    let (mut idx, mut jdx) = (len1, len2);
+
+    while idx > 0 || jdx > 0 {
+        let current = table[idx][jdx];
+
+        if idx > 0 && jdx > 0 {
+            let equal = str1[idx - 1] == str2[jdx - 1];
+            let sub_cost = if equal { 0 } else { substitution_cost };
+            if current == table[idx - 1][jdx - 1].saturating_add(sub_cost) {
+                idx -= 1;
+                jdx -= 1;
+                if !equal {
+                    editops.push(Editop { op: EditOp::Replace, src_pos: idx, dest_pos: jdx });
+                }
+                continue;
+            }
+        }
+        if idx > 0 && current == table[idx - 1][jdx].saturating_add(deletion_cost) {
+            idx -= 1;
+            editops.push(Editop { op: EditOp::Delete, src_pos: idx, dest_pos: jdx });
+            continue;
+        }
+        jdx -= 1;
+        editops.push(Editop { op: EditOp::Insert, src_pos: idx, dest_pos: jdx });
+    }
+    editops.reverse();
+    editops
+}
+
+
+/// Groups an edit script into difflib style opcodes covering both strings
+/// from start to end, including the equal stretches between edits.
+pub fn editops_to_opcodes(editops: &[Editop], len1: usize, len2: usize) -> Vec<Opcode> {
+    let mut opcodes: Vec<Opcode> = Vec::new();
+    let (mut src_pos, mut dest_pos) = (0, 0);
+    let mut idx = 0;
+
+    while idx < editops.len() {
+        let editop = editops[idx];
+        if src_pos < editop.src_pos || dest_pos < editop.dest_pos {
+            opcodes.push(Opcode {
+                tag: None,
+                src_start: src_pos,
+                src_end: editop.src_pos,
+                dest_start: dest_pos,
+                dest_end: editop.dest_pos,
+            });
+            src_pos = editop.src_pos;
+            dest_pos = editop.dest_pos;
+        }
+
+        // Merge consecutive edits of the same kind into one opcode.
+        let (src_start, dest_start) = (src_pos, dest_pos);
+        while idx < editops.len()
+            && editops[idx].op == editop.op
+            && editops[idx].src_pos == src_pos
+            && editops[idx].dest_pos == dest_pos
+        {
+            match editop.op {
+                EditOp::Replace => { src_pos += 1; dest_pos += 1; }
+                EditOp::Delete  => { src_pos += 1; }
+                EditOp::Insert  => { dest_pos += 1; }
+            }
+            idx += 1;
+        }
+        opcodes.push(Opcode {
+            tag: Some(editop.op),
+            src_start,
+            src_end: src_pos,
+            dest_start,
+            dest_end: dest_pos,
+        });
+    }
+
+    if src_pos < len1 || dest_pos < len2 {
+        opcodes.push(Opcode {
+            tag: None,
+            src_start: src_pos,
+            src_end: len1,
+            dest_start: dest_pos,
+            dest_end: len2,
+        });
+    }
+    opcodes
+}
+
+
+/// Applies an edit script to `src`, taking inserted and replacing units from
+/// `dest`. Applying the full script from `get_weighted_levenshtein_editops`
+/// reproduces `dest`; a subset of it applies only those edits. Fails if the
+/// edits are out of order or out of bounds.
+pub fn apply_editops<T: Clone>(editops: &[Editop], src: &[T], dest: &[T]) -> Result<Vec<T>, String> {
+    let mut result: Vec<T> = Vec::with_capacity(dest.len());
+    let mut src_pos = 0;
+
+    for editop in editops {
+        if editop.src_pos < src_pos || editop.src_pos > src.len() {
+            return Err(format!("edit operation at source position {} is out of order or out of bounds", editop.src_pos));
+        }
+        let needs_dest = editop.op != EditOp::Delete;
+        if needs_dest && editop.dest_pos >= dest.len() {
+            return Err(format!("edit operation at destination position {} is out of bounds", editop.dest_pos));
+        }
+        let needs_src = editop.op != EditOp::Insert;
+        if needs_src && editop.src_pos >= src.len() {
+            return Err(format!("edit operation at source position {} is out of bounds", editop.src_pos));
+        }
+
+        result.extend_from_slice(&src[src_pos..editop.src_pos]);
+        src_pos = editop.src_pos;
+
+        match editop.op {
+            EditOp::Replace => {
+                result.push(dest[editop.dest_pos].clone());
+                src_pos += 1;
+            }
+            EditOp::Insert => result.push(dest[editop.dest_pos].clone()),
+            EditOp::Delete => src_pos += 1,
+        }
+    }
+    result.extend_from_slice(&src[src_pos..]);
+    Ok(result)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_weighted_levenshtein_distance;
+    use crate::strategies::string;
+    use proptest::prelude::*;
+
+    fn dp_distance(str1: &[u8], str2: &[u8], deletion_cost: usize, insertion_cost: usize, substitution_cost: usize) -> usize {
+        let mut row: Vec<usize> = (0..=str2.len()).map(|jdx| jdx * insertion_cost).collect();
+        for (idx, c1) in str1.iter().enumerate() {
+            let mut diagonal = row[0];
+            row[0] = (idx + 1) * deletion_cost;
+            for (jdx, c2) in str2.iter().enumerate() {
+                let above = row[jdx + 1];
+                let sub_cost = if c1 == c2 { 0 } else { substitution_cost };
+                row[jdx + 1] = (above + deletion_cost).min(row[jdx] + insertion_cost).min(diagonal + sub_cost);
+                diagonal = above;
+            }
+        }
+        row[str2.len()]
+    }
+
+    fn costs() -> impl Strategy<Value = (usize, usize, usize)> {
+        (1usize..5, 1usize..5, 1usize..5)
+    }
+
+    #[test]
+    fn single_word() {
+        assert_eq!(get_levenshtein_distance(b"kitten", b"sitting"), 3);
+        assert_eq!(get_levenshtein_distance(b"sitting", b"kitten"), 3);
+        assert_eq!(get_levenshtein_distance(b"", b"abc"), 3);
+        assert_eq!(get_levenshtein_distance(b"flaw", b"lawn"), 2);
+        assert_eq!(get_levenshtein_distance(b"abc", b"abc"), 0);
+    }
+
+    proptest! {
+        // long enough to span several 64 unit blocks and their carries
+        #[test]
+        fn blocked_matches_dp(str1 in string(b"abcd", 0..300), str2 in string(b"abcd", 0..300)) {
+            prop_assert_eq!(get_levenshtein_distance(&str1, &str2), dp_distance(&str1, &str2, 1, 1, 1));
+        }
+
+        #[test]
+        fn banded_matches_dp(
+            str1 in string(b"abcd", 0..40),
+            str2 in string(b"abcd", 0..40),
+            (deletion_cost, insertion_cost, substitution_cost) in costs(),
+            max_distance in prop::sample::select(vec![0, 1, 2, 5, 10, 30, usize::MAX]),
+        ) {
+            let distance = dp_distance(&str1, &str2, deletion_cost, insertion_cost, substitution_cost);
+            let expected = if distance <= max_distance { distance } else { max_distance + 1 };
+            let banded = banded_weighted_levenshtein_distance(
+                &str1, &str2, deletion_cost, insertion_cost, substitution_cost, max_distance,
+            );
+            prop_assert_eq!(banded, expected);
+        }
+
+        #[test]
+        fn cost_table_matches_dp(
+            str1 in string(b"abcd", 0..30),
+            str2 in string(b"abcd", 0..30),
+            (deletion_cost, insertion_cost, substitution_cost) in costs(),
+        ) {
+            let costs = CostTable::new(deletion_cost as f64, insertion_cost as f64, substitution_cost as f64).unwrap();
+            let distance = dp_distance(&str1, &str2, deletion_cost, insertion_cost, substitution_cost);
+            prop_assert_eq!(get_cost_table_levenshtein_distance(&str1, &str2, &costs, f64::INFINITY), distance as f64);
+            if distance > 0 {
+                prop_assert_eq!(get_cost_table_levenshtein_distance(&str1, &str2, &costs, 0.5), 1.5);
+            }
+        }
+
+        #[test]
+        fn editops_match_dp(
+            str1 in string(b"abcd", 0..30),
+            str2 in string(b"abcd", 0..30),
+            (deletion_cost, insertion_cost, substitution_cost) in costs(),
+        ) {
+            let editops = get_weighted_levenshtein_editops(&str1, &str2, deletion_cost, insertion_cost, substitution_cost);
+            prop_assert_eq!(
+                editops_cost(&editops, deletion_cost, insertion_cost, substitution_cost),
+                dp_distance(&str1, &str2, deletion_cost, insertion_cost, substitution_cost),
+            );
+            prop_assert_eq!(apply_editops(&editops, &str1, &str2).unwrap(), str2);
+        }
+    }
+
+    #[test]
+    fn weighted_dispatches_to_unit_cost_kernel() {
+        let str1 = "the quick brown fox jumps over the lazy dog, again and again and again".as_bytes();
+        let str2 = "the quick brown cat jumped over a lazy dog again, and again & again".as_bytes();
+
+        assert_eq!(get_weighted_levenshtein_distance(str1, str2, 1, 1, 1), dp_distance(str1, str2, 1, 1, 1));
+    }
+
+    #[test]
+    fn cost_table_weights() {
+        let keyboard = CostTable::new(1.0, 1.0, 1.0).unwrap().keyboard(0.5).unwrap();
+        assert_eq!(get_cost_table_levenshtein_distance(b"hello", b"jello", &keyboard, f64::INFINITY), 0.5);
+        assert_eq!(get_cost_table_levenshtein_distance(b"hello", b"mello", &keyboard, f64::INFINITY), 1.0);
+        assert_eq!(get_cost_table_levenshtein_distance(b"", b"abc", &keyboard, f64::INFINITY), 3.0);
+        assert_eq!(get_cost_table_levenshtein_distance(b"abc", b"abc", &keyboard, f64::INFINITY), 0.0);
+
+        let mut costs = CostTable::new(1.0, 1.0, 1.0).unwrap().ocr(0.25).unwrap();
+        costs.set_deletion(" ", 0.1).unwrap();
+        let chars = |s: &str| s.chars().collect::<Vec<char>>();
+        let distance = get_cost_table_levenshtein_distance(&chars("INV 1O0l"), &chars("INV10Ol"), &costs, f64::INFINITY);
+        assert!((distance - 0.6).abs() < 1e-9);
+    }
+
+    fn editops_cost(editops: &[Editop], deletion_cost: usize, insertion_cost: usize, substitution_cost: usize) -> usize {
+        editops.iter().map(|editop| match editop.op {
+            EditOp::Replace => substitution_cost,
+            EditOp::Insert  => insertion_cost,
+            EditOp::Delete  => deletion_cost,
+        }).sum()
+    }
+
+    #[test]
+    fn editops_reproduce_destination() {
+        let editops = get_weighted_levenshtein_editops(b"kitten", b"sitting", 1, 1, 1);
+        assert_eq!(editops, vec![
+            Editop { op: EditOp::Replace, src_pos: 0, dest_pos: 0 },
+            Editop { op: EditOp::Replace, src_pos: 4, dest_pos: 4 },
+            Editop { op: EditOp::Insert,  src_pos: 6, dest_pos: 6 },
+        ]);
+        assert_eq!(apply_editops(&editops, b"kitten", b"sitting").unwrap(), b"sitting".to_vec());
+    }
+
+    #[test]
+    fn editops_with_huge_costs() {
+        let half = 1 << (usize::BITS - 1);
+        for (str1, str2) in [(&b"abc"[..], &b"xyz"[..]), (b"kitten", b"sitting"), (b"", b"ab"), (b"ab", b"")] {
+            let editops = get_weighted_levenshtein_editops(str1, str2, half, half, half);
+            assert_eq!(apply_editops(&editops, str1, str2).unwrap(), str2.to_vec());
+        }
+    }
+
+    #[test]
+    fn opcodes_cover_both_strings() {
+        let editops = get_weighted_levenshtein_editops(b"qabxcd", b"abycdf", 1, 1, 1);
+        let opcodes = editops_to_opcodes(&editops, 6, 6);
+        let tags: Vec<&str> = opcodes.iter().map(|opcode| opcode.tag_str()).collect();
+
+        assert_eq!(tags, vec!["delete", "equal", "replace", "equal", "insert"]);
+        assert_eq!(opcodes[0], Opcode { tag: Some(EditOp::Delete), src_start: 0, src_end: 1, dest_start: 0, dest_end: 0 });
+        assert_eq!(opcodes[4], Opcode { tag: Some(EditOp::Insert), src_start: 6, src_end: 6, dest_start: 5, dest_end: 6 });
+
+        assert_eq!(editops_to_opcodes(&[], 3, 3), vec![
+            Opcode { tag: None, src_start: 0, src_end: 3, dest_start: 0, dest_end: 3 },
+        ]);
+    }
+
+    #[test]
+    fn apply_rejects_invalid_editops() {
+        let editops = [
+            Editop { op: EditOp::Delete, src_pos: 2, dest_pos: 0 },
+            Editop { op: EditOp::Delete, src_pos: 1, dest_pos: 0 },
+        ];
+        assert!(apply_editops(&editops, b"abc", b"").is_err());
+
+        let editops = [Editop { op: EditOp::Insert, src_pos: 0, dest_pos: 3 }];
+        assert!(apply_editops(&editops, b"abc", b"xyz").is_err());
+    }
+}
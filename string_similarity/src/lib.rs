@@ -1,170 +1,119 @@
+//! String similarity metrics over sequences of bytes, chars or grapheme
+//! clusters. The kernels are plain Rust; the Python module is built on top
+//! of them with the `python` feature, which maturin turns on through
+//! `pyproject.toml`. Without it the crate has no pyo3 dependency.
+
 use std::cmp;
 use std::collections::HashSet;
-
-use pyo3::prelude::*;
-use pyo3::types::{ PyString, PyList };
-
-use rayon::prelude::*;
-
-
-#[pyfunction]
-#[pyo3(signature = (str1, str2, max_prefix_length=4, scaling_factor=0.1))]
-fn jaro_winkler_similarity(
-    _py: Python, 
-    str1: Option<&PyString>, 
-    str2: Option<&PyString>,
-    max_prefix_length: Option<i32>,
-    scaling_factor: Option<f32>,
-    ) -> PyResult<f32> {
-
-    // if str1 or str2 is None, return 0
-    if str1.is_none() || str2.is_none() {
-        return Ok(0.0);
-    }
-
-    Ok(get_jaro_winkler_similarity(
-            &str1.unwrap().to_string().as_bytes().to_vec(),
-            &str2.unwrap().to_string().as_bytes().to_vec(),
-            max_prefix_length.unwrap_or(4) as usize,
-            scaling_factor.unwrap_or(0.1),
-            ))
-}
-
-#[pyfunction]
-#[pyo3(signature = (str1_list, str2_list, max_prefix_length=4, scaling_factor=0.1))]
-fn jaro_winkler_similarity_batched(
-    _py: Python, 
-    str1_list: Option<&PyList>, 
-    str2_list: Option<&PyList>,
-    max_prefix_length: Option<i32>,
-    scaling_factor: Option<f32>,
-    ) -> PyResult<Vec<f32>> {
-
-    // if str1 or str2 is None, return 0
-    if str1_list.is_none() || str2_list.is_none() {
-        return Err(pyo3::exceptions::PyValueError::new_err("str1_list and str2_list must be lists"));
-    }
-    let str1_vec: Vec<String> = str1_list.unwrap().iter().map(|py_string| py_string.to_string()).collect();
-    let str2_vec: Vec<String> = str2_list.unwrap().iter().map(|py_string| py_string.to_string()).collect();
-    
-    if str1_vec.len() != str2_vec.len() {
-        return Err(pyo3::exceptions::PyValueError::new_err("str1_list and str2_list must be of the same length"));
-    }
-
-    if str1_vec.len() == 0 {
-        return Ok(vec![]);
-    }
-
-    // Use _py.allow_threads() to allow the GIL to be released
-    let jw_sims: Vec<f32> = _py.allow_threads(|| {
-        str1_vec.par_iter().zip(str2_vec.par_iter()).map(|(str1, str2)| {
-            get_jaro_winkler_similarity(
-                &str1.as_bytes().to_vec(),
-                &str2.as_bytes().to_vec(),
-                max_prefix_length.unwrap_or(4) as usize,
-                scaling_factor.unwrap_or(0.1),
-            )
-        }).collect()
-    });
-    Ok(jw_sims)
+use std::hash::Hash;
+
+pub mod costs;
+pub mod damerau;
+pub mod dedupe;
+pub mod fuzz;
+pub mod index;
+pub mod lcs;
+pub mod levenshtein;
+pub mod metric;
+pub mod phonetic;
+pub mod preprocess;
+pub mod process;
+#[cfg(feature = "python")]
+mod python;
+pub mod qgram;
+pub mod token;
+pub mod unit;
+
+pub use metric::{ DamerauLevenshtein, Distance, Indel, JaroWinkler, Lcs, Levenshtein, Metric, Osa, Similarity };
+
+
+pub fn get_jaro_winkler_similarity<T: Eq>(
+    str1: &[T], 
+    str2: &[T],
+    max_prefix_length: usize,
+    scaling_factor: f32,
+    ) -> f32 {
+    get_jaro_winkler_similarity_with_cutoff(str1, str2, max_prefix_length, scaling_factor, 0.0)
 }
 
 
-#[pyfunction]
-#[pyo3(signature = (str1, str2, deletion_cost=1, insertion_cost=1, substitution_cost=1))]
-fn weighted_levenshtein_distance(
-    _py: Python, 
-    str1: Option<&PyString>, 
-    str2: Option<&PyString>,
-    deletion_cost: Option<i32>,
-    insertion_cost: Option<i32>,
-    substitution_cost: Option<i32>,
-    ) -> PyResult<usize> {
-
-    // if str1 or str2 is None, return 0
-    if str1.is_none() || str2.is_none() {
-        return Ok(0);
+/// Checks that jaro winkler parameters keep the similarity within `[0, 1]`,
+/// which needs a finite, non negative `scaling_factor` whose boost over a
+/// full prefix, `max_prefix_length * scaling_factor`, is at most 1.
+pub fn check_jaro_winkler(max_prefix_length: usize, scaling_factor: f32) -> Result<(), String> {
+    if !(scaling_factor.is_finite() && scaling_factor >= 0.0) || max_prefix_length as f32 * scaling_factor > 1.0 {
+        return Err(format!(
+            "scaling_factor must be at least 0 and at most 1 / max_prefix_length, got {} with a max_prefix_length of {}",
+            scaling_factor,
+            max_prefix_length,
+        ));
     }
-
-    Ok(get_weighted_levenshtein_distance(
-            &str1.unwrap().to_string().as_bytes().to_vec(),
-            &str2.unwrap().to_string().as_bytes().to_vec(),
-            deletion_cost.unwrap_or(1) as usize,
-            insertion_cost.unwrap_or(1) as usize,
-            substitution_cost.unwrap_or(1) as usize,
-            )) 
+    Ok(())
 }
 
 
-#[pyfunction]
-#[pyo3(signature = (str1, str2))]
-fn jaccard_similarity(
-    _py: Python, 
-    str1: Option<&PyString>, 
-    str2: Option<&PyString>,
-    ) -> PyResult<f32> {
+/// Upper bound on the jaro winkler similarity given at most `n_matches`
+/// matching characters, no transpositions and a full length prefix.
+fn jaro_winkler_upper_bound(
+    n_matches: usize,
+    len1: usize,
+    len2: usize,
+    max_prefix_length: usize,
+    scaling_factor: f32,
+    ) -> f32 {
+    let prefix = cmp::min(max_prefix_length, cmp::min(cmp::min(len1, len2), n_matches)) as f32;
 
-    // if str1 or str2 is None, return 0
-    if str1.is_none() || str2.is_none() {
-        return Ok(0.0);
+    // The winkler boost is only monotonic in the jaro similarity if it can't exceed 1.
+    if prefix * scaling_factor > 1.0 {
+        return f32::INFINITY;
     }
-
-    Ok(get_jaccard_similarity(
-            &str1.unwrap().to_string().as_bytes().to_vec(),
-            &str2.unwrap().to_string().as_bytes().to_vec(),
-            )) 
-
+    let sim = ((n_matches as f32 / (len1 as f32)) + (n_matches as f32 / (len2 as f32)) + 1.0) / 3.0;
+    sim + (prefix * scaling_factor * (1.0 - sim))
 }
 
 
-
-#[pymodule]
-fn string_sim_metrics(_py: Python, m: &PyModule) -> PyResult<()> {
-    m.add_function(wrap_pyfunction!(jaro_winkler_similarity, m)?)?;
-    m.add_function(wrap_pyfunction!(weighted_levenshtein_distance, m)?)?;
-    m.add_function(wrap_pyfunction!(jaccard_similarity, m)?)?;
-    m.add_function(wrap_pyfunction!(jaro_winkler_similarity_batched, m)?)?;
-    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
-    m.add("__author__", env!("CARGO_PKG_AUTHORS"))?;
-    m.add("__description__", env!("CARGO_PKG_DESCRIPTION"))?;
-    Ok(())
-}
-
-
-
-pub fn get_jaro_winkler_similarity(
-    str1: &Vec<u8>, 
-    str2: &Vec<u8>,
+/// Same as `get_jaro_winkler_similarity` but returns 0 as soon as the
+/// similarity is known to be below `score_cutoff`.
+pub fn get_jaro_winkler_similarity_with_cutoff<T: Eq>(
+    str1: &[T], 
+    str2: &[T],
     max_prefix_length: usize,
     scaling_factor: f32,
+    score_cutoff: f32,
     ) -> f32 {
     let len1 = str1.len();
     let len2 = str2.len();
 
+    // Identical strings, including two empty ones, are a perfect match.
+    if str1 == str2 {
+        return if score_cutoff <= 1.0 { 1.0 } else { 0.0 };
+    }
+
     if len1 == 0 || len2 == 0 {
         return 0.0;
     }
 
-    if str1 == str2 {
-        return 1.0;
+    let upper_bound = jaro_winkler_upper_bound(cmp::min(len1, len2), len1, len2, max_prefix_length, scaling_factor);
+    if upper_bound < score_cutoff {
+        return 0.0;
     }
 
-    let search_range = (cmp::max(len1, len2) / 2) - 1;
+    let search_range = (cmp::max(len1, len2) / 2).saturating_sub(1);
 
     let mut n_matches = 0;
 
-    let mut hash1: Vec<u8> = vec![0; len1 + (len1 % 32)];
-    let mut hash2: Vec<u8> = vec![0; len2 + (len2 % 32)];
+    let mut hash1: Vec<bool> = vec![false; len1];
+    let mut hash2: Vec<bool> = vec![false; len2];
 
 
     for i in 0..len1 {
-        let start = cmp::max(0, i as i32 - search_range as i32) as usize;
+        let start = i.saturating_sub(search_range);
         let end   = cmp::min(len2, i + search_range + 1);
 
         for j in start..end {
-            if (str1[i] == str2[j]) && (hash2[j] == 0) {
-                hash1[i] = 1;
-                hash2[j] = 1;
+            if (str1[i] == str2[j]) && !hash2[j] {
+                hash1[i] = true;
+                hash2[j] = true;
                 n_matches += 1;
                 break;
             }
@@ -176,12 +125,17 @@ pub fn get_jaro_winkler_similarity(
         return 0.0;
     }
 
+    let upper_bound = jaro_winkler_upper_bound(n_matches, len1, len2, max_prefix_length, scaling_factor);
+    if upper_bound < score_cutoff {
+        return 0.0;
+    }
+
     let mut n_transpositions: f32 = 0.0;
     let mut idx = 0;
 
     for i in 0..len1 {
-        if hash1[i] == 1 {
-            while hash2[idx] == 0 {
+        if hash1[i] {
+            while !hash2[idx] {
                 idx += 1;
             }
             if str1[i] != str2[idx] {
@@ -205,58 +159,134 @@ pub fn get_jaro_winkler_similarity(
             break;
         }
     }
-    return sim + (prefix * scaling_factor * (1.0 - sim));
+    let jw_sim = sim + (prefix * scaling_factor * (1.0 - sim));
+    if jw_sim < score_cutoff {
+        return 0.0;
+    }
+    jw_sim
+}
 
 
+pub fn get_weighted_levenshtein_distance<T: Eq + Hash>(
+    str1: &[T], 
+    str2: &[T],
+    deletion_cost: usize,
+    insertion_cost: usize,
+    substitution_cost: usize
+    ) -> usize {
+    get_weighted_levenshtein_distance_with_cutoff(
+        str1,
+        str2,
+        deletion_cost,
+        insertion_cost,
+        substitution_cost,
+        usize::MAX,
+        )
 }
 
 
-pub fn get_weighted_levenshtein_distance(
-    str1: &Vec<u8>, 
-    str2: &Vec<u8>,
+/// Same as `get_weighted_levenshtein_distance` but returns `max_distance + 1`
+/// as soon as the distance is known to exceed `max_distance`.
+pub fn get_weighted_levenshtein_distance_with_cutoff<T: Eq + Hash>(
+    str1: &[T], 
+    str2: &[T],
     deletion_cost: usize,
     insertion_cost: usize,
-    substitution_cost: usize
+    substitution_cost: usize,
+    max_distance: usize,
     ) -> usize {
     let len1 = str1.len();
     let len2 = str2.len();
+    let exceeded = max_distance.saturating_add(1);
 
-    if len1 == 0 || len2 == 0 {
+    if str1 == str2 {
         return 0;
     }
 
-    if str1 == str2 {
-        return 1;
+    // Every length difference has to be paid for with insertions or deletions,
+    // which is the whole distance if either string is empty.
+    let lower_bound = if len1 > len2 {
+        (len1 - len2).saturating_mul(deletion_cost)
+    } else {
+        (len2 - len1).saturating_mul(insertion_cost)
+    };
+    if lower_bound > max_distance {
+        return exceeded;
+    }
+    if len1 == 0 || len2 == 0 {
+        return lower_bound;
     }
 
-    let mut table: Vec<Vec<usize>> = vec![vec![0; len2 + 1]; len1 + 1];
+    if deletion_cost == 1 && insertion_cost == 1 && substitution_cost == 1 {
+        return cmp::min(levenshtein::get_levenshtein_distance(str1, str2), exceeded);
+    }
 
-    for idx in 1..(len1 + 1) {
-        table[idx][0] = table[idx - 1][0] + deletion_cost;
+    // A substitution is never cheaper than a deletion and an insertion.
+    if deletion_cost == 1 && insertion_cost == 1 && substitution_cost >= 2 {
+        return cmp::min(lcs::get_indel_distance(str1, str2), exceeded);
     }
 
-    for idx in 1..(len2 + 1) {
-        table[0][idx] = table[0][idx - 1] + insertion_cost;
+    levenshtein::banded_weighted_levenshtein_distance(
+        str1,
+        str2,
+        deletion_cost,
+        insertion_cost,
+        substitution_cost,
+        max_distance,
+        )
+}
+
+
+/// The original jaro winkler semantics, which score two empty strings 0
+/// rather than 1. Kept for `legacy_semantics=True`.
+pub fn get_legacy_jaro_winkler_similarity<T: Eq>(
+    str1: &[T], 
+    str2: &[T],
+    max_prefix_length: usize,
+    scaling_factor: f32,
+    score_cutoff: f32,
+    ) -> f32 {
+    if str1.is_empty() || str2.is_empty() {
+        return 0.0;
     }
+    get_jaro_winkler_similarity_with_cutoff(str1, str2, max_prefix_length, scaling_factor, score_cutoff)
+}
 
-    for (idx, c1) in str1.iter().enumerate() {
-        for (jdx, c2) in str2.iter().enumerate() {
-            let sub_cost = if c1 == c2 { 0 } else { substitution_cost };
-            table[idx + 1][jdx + 1] = cmp::min(
-                cmp::min(
-                    table[idx][jdx + 1] + deletion_cost, 
-                    table[idx + 1][jdx] + insertion_cost
-                    ), 
-                table[idx][jdx] + sub_cost
-                );
-        }
+
+/// The original weighted levenshtein semantics, which return 0 if either
+/// string is empty and 1 for identical strings. Kept for
+/// `legacy_semantics=True`.
+pub fn get_legacy_weighted_levenshtein_distance<T: Eq + Hash>(
+    str1: &[T], 
+    str2: &[T],
+    deletion_cost: usize,
+    insertion_cost: usize,
+    substitution_cost: usize,
+    max_distance: usize,
+    ) -> usize {
+    if str1.is_empty() || str2.is_empty() {
+        return 0;
     }
-    return table[len1][len2];
+    if str1 == str2 {
+        return cmp::min(1, max_distance.saturating_add(1));
+    }
+    get_weighted_levenshtein_distance_with_cutoff(
+        str1,
+        str2,
+        deletion_cost,
+        insertion_cost,
+        substitution_cost,
+        max_distance,
+        )
 }
 
-pub fn get_jaccard_similarity(
-    str1: &Vec<u8>,
-    str2: &Vec<u8>,
+/// The original Jaccard over sets of single units. The union is taken as
+/// `len1 + len2 - intersection` using the string lengths, so repeated units
+/// lower the score. Kept for `legacy=True`; see `qgram::QGram` for the set
+/// and multiset versions.
+pub fn get_jaccard_similarity<T: Eq + Hash>(
+    str1: &[T],
+    str2: &[T],
     ) -> f32 {
     let len1 = str1.len();
     let len2 = str2.len();
@@ -269,19 +299,35 @@ pub fn get_jaccard_similarity(
         return 1.0;
     }
 
-    let hashset1: HashSet<u8> = str1.iter().cloned().collect();
-    let hashset2: HashSet<u8> = str2.iter().cloned().collect();
+    let hashset1: HashSet<&T> = str1.iter().collect();
+    let hashset2: HashSet<&T> = str2.iter().collect();
 
     let intersection = hashset1.intersection(&hashset2).count();
     let union = len1 + len2 - intersection;
-    return (intersection as f32) / (union as f32);
+    (intersection as f32) / (union as f32)
+}
+
+
+/// Random strings for the tests comparing the kernels against textbook
+/// implementations.
+#[cfg(test)]
+mod strategies {
+    use std::ops::Range;
+
+    use proptest::prelude::*;
 
+    /// Strings over `alphabet` with a length in `len`. A small alphabet
+    /// makes matches, repeats and transpositions common.
+    pub fn string(alphabet: &[u8], len: Range<usize>) -> impl Strategy<Value = Vec<u8>> {
+        prop::collection::vec(prop::sample::select(alphabet.to_vec()), len)
+    }
 }
 
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::unit::{ Unit, with_units };
 
     #[test]
     fn no_error() {
@@ -295,4 +341,273 @@ mod tests {
         assert!(similarity >= 0.0);
     }
 
+    #[test]
+    fn unit_aware_levenshtein() {
+        let lev = |unit: Unit, str1: &str, str2: &str| {
+            with_units!(unit, str1, str2, |a, b| get_weighted_levenshtein_distance(a, b, 1, 1, 1))
+        };
+
+        assert_eq!(lev(Unit::Byte, "café", "cafe"), 2);
+        assert_eq!(lev(Unit::Char, "café", "cafe"), 1);
+        assert_eq!(lev(Unit::Grapheme, "café", "cafe"), 1);
+
+        // Decomposed "e\u{301}" is two chars but a single grapheme.
+        assert_eq!(lev(Unit::Char, "cafe\u{301}", "café"), 2);
+        assert_eq!(lev(Unit::Grapheme, "cafe\u{301}", "café"), 1);
+
+        assert_eq!(lev(Unit::Char, "東京都", "京都府"), 2);
+    }
+
+    #[test]
+    fn unit_aware_similarity() {
+        let jw = with_units!(Unit::Char, "東京都", "東京府", |a, b| get_jaro_winkler_similarity(a, b, 4, 0.1));
+        let jaccard = with_units!(Unit::Char, "東京都", "東京府", |a, b| get_jaccard_similarity(a, b));
+
+        assert!((jw - 0.822_222).abs() < 1e-5);
+        assert!((jaccard - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn cutoff_kernels_agree() {
+        let pairs = [("kitten", "sitting"), ("martha", "marhta"), ("abc", "xyz"), ("a", "abcdef")];
+
+        for (str1, str2) in pairs {
+            let (a, b) = (str1.as_bytes(), str2.as_bytes());
+
+            let jw = get_jaro_winkler_similarity(a, b, 4, 0.1);
+            for cutoff in [0.0, 0.5, 0.8, 0.95] {
+                let expected = if jw >= cutoff { jw } else { 0.0 };
+                assert_eq!(get_jaro_winkler_similarity_with_cutoff(a, b, 4, 0.1, cutoff), expected);
+            }
+
+            let lev = get_weighted_levenshtein_distance(a, b, 1, 2, 1);
+            for max_distance in 0..8 {
+                let expected = if lev <= max_distance { lev } else { max_distance + 1 };
+                assert_eq!(get_weighted_levenshtein_distance_with_cutoff(a, b, 1, 2, 1, max_distance), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn cutoff_saturates_huge_costs() {
+        let huge = usize::MAX / 2;
+        assert_eq!(get_weighted_levenshtein_distance_with_cutoff(b"abcd", b"a", huge, 1, 1, 10), 11);
+        assert_eq!(get_weighted_levenshtein_distance_with_cutoff(b"a", b"abcd", 1, usize::MAX, 1, 100), 101);
+        assert_eq!(get_weighted_levenshtein_distance_with_cutoff(b"ab", b"ba", 2, 3, usize::MAX, usize::MAX), 5);
+        assert_eq!(get_weighted_levenshtein_distance_with_cutoff(b"abc", b"xbc", usize::MAX, usize::MAX, 1, 5), 1);
+        // too large to count, but never less than a single edit
+        let half = 1 << (usize::BITS - 1);
+        assert_eq!(get_weighted_levenshtein_distance(b"abc", b"xyz", half, half, half), usize::MAX);
+        assert_eq!(get_weighted_levenshtein_distance(b"abc", b"xbc", half, half, half), half);
+        assert_eq!(get_weighted_levenshtein_distance(b"abc", b"abcd", half, half, half), half);
+    }
+
+    #[test]
+    fn parse_unit() {
+        assert_eq!("byte".parse::<Unit>(), Ok(Unit::Byte));
+        assert_eq!("char".parse::<Unit>(), Ok(Unit::Char));
+        assert_eq!("grapheme".parse::<Unit>(), Ok(Unit::Grapheme));
+        assert!("word".parse::<Unit>().is_err());
+    }
+
+    #[test]
+    fn edge_semantics() {
+        assert_eq!(get_weighted_levenshtein_distance(b"", b"abc", 1, 2, 1), 6);
+        assert_eq!(get_weighted_levenshtein_distance(b"abc", b"", 3, 1, 1), 9);
+        assert_eq!(get_weighted_levenshtein_distance(b"", b"", 1, 1, 1), 0);
+        assert_eq!(get_weighted_levenshtein_distance(b"abc", b"abc", 1, 1, 1), 0);
+        assert_eq!(get_weighted_levenshtein_distance_with_cutoff(b"", b"abcdef", 1, 1, 1, 2), 3);
+
+        assert_eq!(get_jaro_winkler_similarity(b"", b"", 4, 0.1), 1.0);
+        assert_eq!(get_jaro_winkler_similarity(b"", b"a", 4, 0.1), 0.0);
+        assert!(check_jaro_winkler(4, 0.25).is_ok());
+        assert!(check_jaro_winkler(4, 0.3).is_err());
+        assert!(check_jaro_winkler(4, -0.1).is_err());
+        assert!(check_jaro_winkler(0, f32::NAN).is_err());
+        assert_eq!(get_jaro_winkler_similarity(b"a", b"b", 4, 0.1), 0.0);
+        assert_eq!(get_jaro_winkler_similarity(b"a", b"ab", 4, 0.1), get_jaro_winkler_similarity(b"ab", b"a", 4, 0.1));
+
+        assert_eq!(get_legacy_weighted_levenshtein_distance(b"", b"abc", 1, 1, 1, usize::MAX), 0);
+        assert_eq!(get_legacy_weighted_levenshtein_distance(b"abc", b"abc", 1, 1, 1, usize::MAX), 1);
+        assert_eq!(get_legacy_weighted_levenshtein_distance(b"abc", b"abc", 1, 1, 1, 0), 1);
+        assert_eq!(get_legacy_jaro_winkler_similarity(b"", b"", 4, 0.1, 0.0), 0.0);
+    }
+
+
+    /// Property tests of the distance functions against textbook
+    /// implementations, run on strings over a small alphabet so that matches,
+    /// repeats and transpositions are common.
+    mod properties {
+        use super::*;
+        use crate::damerau::{ get_damerau_levenshtein_distance, get_osa_distance };
+        use crate::lcs::{ get_indel_distance, get_lcs_length };
+        use proptest::prelude::*;
+
+        fn reference_levenshtein(a: &[u8], b: &[u8], deletion_cost: usize, insertion_cost: usize, substitution_cost: usize) -> usize {
+            let mut table = vec![vec![0; b.len() + 1]; a.len() + 1];
+            for (idx, row) in table.iter_mut().enumerate() {
+                row[0] = idx * deletion_cost;
+            }
+            for (jdx, cell) in table[0].iter_mut().enumerate() {
+                *cell = jdx * insertion_cost;
+            }
+            for idx in 1..=a.len() {
+                for jdx in 1..=b.len() {
+                    let sub_cost = if a[idx - 1] == b[jdx - 1] { 0 } else { substitution_cost };
+                    table[idx][jdx] = (table[idx - 1][jdx] + deletion_cost)
+                        .min(table[idx][jdx - 1] + insertion_cost)
+                        .min(table[idx - 1][jdx - 1] + sub_cost);
+                }
+            }
+            table[a.len()][b.len()]
+        }
+
+        fn reference_lcs(a: &[u8], b: &[u8]) -> usize {
+            let mut table = vec![vec![0; b.len() + 1]; a.len() + 1];
+            for idx in 1..=a.len() {
+                for jdx in 1..=b.len() {
+                    table[idx][jdx] = if a[idx - 1] == b[jdx - 1] {
+                        table[idx - 1][jdx - 1] + 1
+                    } else {
+                        table[idx - 1][jdx].max(table[idx][jdx - 1])
+                    };
+                }
+            }
+            table[a.len()][b.len()]
+        }
+
+        /// Jaro winkler as described by Winkler (1990): characters match if
+        /// equal and no further apart than half the longer length minus one,
+        /// and half the matches that are out of order count as transpositions.
+        fn reference_jaro_winkler(a: &[u8], b: &[u8], max_prefix_length: usize, scaling_factor: f64) -> f64 {
+            if a == b {
+                return 1.0;
+            }
+            if a.is_empty() || b.is_empty() {
+                return 0.0;
+            }
+            let window = (a.len().max(b.len()) / 2).saturating_sub(1);
+            let mut used = vec![false; b.len()];
+            let mut matches_a = Vec::new();
+            for (idx, &c) in a.iter().enumerate() {
+                let lo = idx.saturating_sub(window);
+                let hi = (idx + window + 1).min(b.len());
+                if let Some(jdx) = (lo..hi).find(|&jdx| !used[jdx] && b[jdx] == c) {
+                    used[jdx] = true;
+                    matches_a.push(c);
+                }
+            }
+            if matches_a.is_empty() {
+                return 0.0;
+            }
+            let matches_b: Vec<u8> = b.iter().zip(used.iter()).filter(|(_, &used)| used).map(|(&c, _)| c).collect();
+            let m = matches_a.len() as f64;
+            let t = (matches_a.iter().zip(matches_b.iter()).filter(|(x, y)| x != y).count() / 2) as f64;
+            let jaro = (m / a.len() as f64 + m / b.len() as f64 + (m - t) / m) / 3.0;
+            let prefix = a.iter().zip(b.iter()).take(max_prefix_length).take_while(|(x, y)| x == y).count() as f64;
+            jaro + prefix * scaling_factor * (1.0 - jaro)
+        }
+
+        fn string() -> impl Strategy<Value = Vec<u8>> {
+            crate::strategies::string(b"abcd", 0..12)
+        }
+
+        fn costs() -> impl Strategy<Value = (usize, usize, usize)> {
+            (1usize..4, 1usize..4, 1usize..5)
+        }
+
+        proptest! {
+            #[test]
+            fn levenshtein_matches_reference(a in string(), b in string(), (del, ins, sub) in costs()) {
+                prop_assert_eq!(get_weighted_levenshtein_distance(&a, &b, del, ins, sub), reference_levenshtein(&a, &b, del, ins, sub));
+            }
+
+            #[test]
+            fn levenshtein_cutoff(a in string(), b in string(), (del, ins, sub) in costs(), max_distance in 0usize..10) {
+                let distance = reference_levenshtein(&a, &b, del, ins, sub);
+                let expected = if distance <= max_distance { distance } else { max_distance + 1 };
+                prop_assert_eq!(get_weighted_levenshtein_distance_with_cutoff(&a, &b, del, ins, sub, max_distance), expected);
+            }
+
+            #[test]
+            fn levenshtein_identity(a in string(), (del, ins, sub) in costs()) {
+                prop_assert_eq!(get_weighted_levenshtein_distance(&a, &a, del, ins, sub), 0);
+                prop_assert_eq!(get_weighted_levenshtein_distance(&a, b"", del, ins, sub), a.len() * del);
+                prop_assert_eq!(get_weighted_levenshtein_distance(b"", &a, del, ins, sub), a.len() * ins);
+            }
+
+            #[test]
+            fn levenshtein_symmetry(a in string(), b in string(), (del, ins, sub) in costs()) {
+                // Swapping the strings swaps the roles of insertions and deletions.
+                prop_assert_eq!(
+                    get_weighted_levenshtein_distance(&a, &b, del, ins, sub),
+                    get_weighted_levenshtein_distance(&b, &a, ins, del, sub)
+                );
+            }
+
+            #[test]
+            fn levenshtein_triangle(a in string(), b in string(), c in string(), (del, ins, sub) in costs()) {
+                let d = |x: &[u8], y: &[u8]| get_weighted_levenshtein_distance(x, y, del, ins, sub);
+                prop_assert!(d(&a, &c) <= d(&a, &b) + d(&b, &c));
+            }
+
+            #[test]
+            fn legacy_levenshtein(a in string(), b in string(), (del, ins, sub) in costs()) {
+                let legacy = get_legacy_weighted_levenshtein_distance(&a, &b, del, ins, sub, usize::MAX);
+                let expected = if a.is_empty() || b.is_empty() {
+                    0
+                } else if a == b {
+                    1
+                } else {
+                    get_weighted_levenshtein_distance(&a, &b, del, ins, sub)
+                };
+                prop_assert_eq!(legacy, expected);
+            }
+
+            #[test]
+            fn jaro_winkler_matches_reference(a in string(), b in string(), max_prefix_length in 0usize..5) {
+                let similarity = get_jaro_winkler_similarity(&a, &b, max_prefix_length, 0.1) as f64;
+                prop_assert!((similarity - reference_jaro_winkler(&a, &b, max_prefix_length, 0.1)).abs() < 1e-5);
+            }
+
+            #[test]
+            fn jaro_winkler_bounds(a in string(), b in string()) {
+                let similarity = get_jaro_winkler_similarity(&a, &b, 4, 0.1);
+                prop_assert!((0.0..=1.0).contains(&similarity));
+                prop_assert_eq!(get_jaro_winkler_similarity(&a, &a, 4, 0.1), 1.0);
+            }
+
+            #[test]
+            fn jaro_winkler_symmetry(a in string(), b in string()) {
+                let forward = get_jaro_winkler_similarity(&a, &b, 4, 0.1);
+                let backward = get_jaro_winkler_similarity(&b, &a, 4, 0.1);
+                prop_assert!((forward - backward).abs() < 1e-6);
+            }
+
+            #[test]
+            fn indel_is_a_metric(a in string(), b in string(), c in string()) {
+                prop_assert_eq!(get_lcs_length(&a, &b), reference_lcs(&a, &b));
+                prop_assert_eq!(get_indel_distance(&a, &b), a.len() + b.len() - 2 * reference_lcs(&a, &b));
+                prop_assert_eq!(get_indel_distance(&a, &a), 0);
+                prop_assert_eq!(get_indel_distance(&a, &b), get_indel_distance(&b, &a));
+                prop_assert!(get_indel_distance(&a, &c) <= get_indel_distance(&a, &b) + get_indel_distance(&b, &c));
+            }
+
+            #[test]
+            fn damerau_levenshtein_is_a_metric(a in string(), b in string(), c in string()) {
+                let d = |x: &[u8], y: &[u8]| get_damerau_levenshtein_distance(x, y, 1, 1, 1, 1);
+                prop_assert_eq!(d(&a, &a), 0);
+                prop_assert_eq!(d(&a, &b), d(&b, &a));
+                prop_assert!(d(&a, &c) <= d(&a, &b) + d(&b, &c));
+                prop_assert!(d(&a, &b) <= get_osa_distance(&a, &b, 1, 1, 1, 1));
+                prop_assert!(get_osa_distance(&a, &b, 1, 1, 1, 1) <= reference_levenshtein(&a, &b, 1, 1, 1));
+            }
+
+            #[test]
+            fn osa_symmetry(a in string(), b in string()) {
+                prop_assert_eq!(get_osa_distance(&a, &a, 1, 1, 1, 1), 0);
+                prop_assert_eq!(get_osa_distance(&a, &b, 1, 1, 1, 1), get_osa_distance(&b, &a, 1, 1, 1, 1));
+            }
+        }
+    }
 }
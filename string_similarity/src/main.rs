@@ -1,3 +1,470 @@
-fn main() {
-    println!("Hello, world!");
+//! `strsim`, fuzzy matching of CSV, TSV and JSON lines files from the command
+//! line:
+//!
+//! ```text
+//! strsim match --metric jw --threshold 0.9 left.csv right.csv --left-col name --right-col name
+//! ```
+//!
+//! The right file is loaded as the dictionary to match against. The left file
+//! is streamed in batches, each of which is scored against the whole
+//! dictionary in parallel, and the matched pairs are written as they are
+//! found.
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{ self, BufRead, BufReader, BufWriter, Read, Write };
+use std::path::{ Path, PathBuf };
+use std::process::ExitCode;
+
+use clap::{ Args, Parser, Subcommand, ValueEnum };
+
+use string_sim_metrics::fuzz::Fuzz;
+use string_sim_metrics::preprocess::Preprocessor;
+use string_sim_metrics::process::{ extract_many, EditDistance, Scorer };
+use string_sim_metrics::qgram::{ QGram, SetMeasure };
+use string_sim_metrics::unit::Unit;
+use string_sim_metrics::{ DamerauLevenshtein, Indel, JaroWinkler, Lcs, Levenshtein, Osa };
+
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+
+#[derive(Parser)]
+#[command(name = "strsim", version, about = "Fuzzy string matching of CSV, TSV and JSON lines files")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Scores every row of LEFT against every row of RIGHT and writes the
+    /// pairs that pass --threshold.
+    Match(MatchArgs),
+}
+
+#[derive(Args)]
+struct MatchArgs {
+    /// File to stream, or `-` for stdin.
+    left: PathBuf,
+    /// File to match against, which is loaded into memory.
+    right: PathBuf,
+    /// Metric to score with, such as jw, levenshtein, lev_sim, osa,
+    /// damerau_levenshtein, indel, lcs, jaccard, sorensen_dice, ratio,
+    /// token_sort_ratio, token_set_ratio or wratio.
+    #[arg(long, default_value = "jw")]
+    metric: String,
+    /// Lowest similarity, or highest distance, of a pair to write.
+    #[arg(long)]
+    threshold: Option<f32>,
+    /// Column of LEFT to match. Defaults to the first column of delimited
+    /// files and is required for JSON lines.
+    #[arg(long)]
+    left_col: Option<String>,
+    /// Column of RIGHT to match, as --left-col.
+    #[arg(long)]
+    right_col: Option<String>,
+    /// Delimited files have no header row, so their first row is matched
+    /// too and --left-col and --right-col are column numbers counting from 0.
+    #[arg(long)]
+    no_header: bool,
+    /// Unit strings are split into: byte, char or grapheme.
+    #[arg(long, default_value = "byte")]
+    unit: Unit,
+    /// Comma separated normalization steps (case_fold, strip_accents,
+    /// strip_punctuation, normalize_whitespace) or `default` for all.
+    #[arg(long)]
+    processor: Option<String>,
+    /// Size of the grams of jaccard and sorensen_dice.
+    #[arg(long, default_value_t = 2)]
+    q: usize,
+    /// Writes at most this many matches per row of LEFT, best first.
+    #[arg(long)]
+    limit: Option<usize>,
+    /// Format of LEFT and RIGHT, guessed from their extensions by default.
+    #[arg(long, value_enum)]
+    input_format: Option<Format>,
+    /// Format of the output.
+    #[arg(long, value_enum, default_value_t = Format::Csv)]
+    format: Format,
+    /// File to write the matches to instead of stdout.
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+    /// Rows of LEFT scored at a time.
+    #[arg(long, default_value_t = 4096)]
+    batch_size: usize,
+    /// Number of threads, every core by default.
+    #[arg(long)]
+    threads: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Csv,
+    Tsv,
+    Jsonl,
+}
+
+impl Format {
+    /// `.tsv` and `.tab` files are TSV, `.jsonl` and `.ndjson` files JSON
+    /// lines and everything else CSV.
+    fn from_path(path: &Path) -> Format {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("tsv" | "tab") => Format::Tsv,
+            Some("jsonl" | "ndjson") => Format::Jsonl,
+            _ => Format::Csv,
+        }
+    }
+
+    fn delimiter(&self) -> u8 {
+        match self {
+            Format::Tsv => b'\t',
+            _ => b',',
+        }
+    }
+}
+
+
+/// The scorer for a metric name. Edit distances use unit costs, and
+/// `lev_sim` and friends are their normalized similarities.
+fn parse_metric(name: &str, q: usize) -> std::result::Result<Scorer, String> {
+    let unit_costs = EditDistance::WeightedLevenshtein { deletion_cost: 1, insertion_cost: 1, substitution_cost: 1 };
+    let scorer = match name {
+        "jw" | "jaro_winkler" => JaroWinkler::new().into(),
+        "lev" | "levenshtein" => Levenshtein::new().into(),
+        "lev_sim" | "levenshtein_similarity" => Scorer::NormalizedSimilarity(unit_costs),
+        "osa" => Osa::new().into(),
+        "dl" | "damerau_levenshtein" => DamerauLevenshtein::new().into(),
+        "indel" => Indel.into(),
+        "indel_sim" | "indel_similarity" => Scorer::NormalizedSimilarity(EditDistance::Indel),
+        "lcs" => Lcs.into(),
+        "jaccard" => QGram::new(SetMeasure::Jaccard).q(q).into(),
+        "dice" | "sorensen_dice" => QGram::new(SetMeasure::SorensenDice).q(q).into(),
+        "ratio" => Fuzz::Ratio.into(),
+        "partial_ratio" => Fuzz::PartialRatio.into(),
+        "token_sort_ratio" => Fuzz::TokenSortRatio.into(),
+        "token_set_ratio" => Fuzz::TokenSetRatio.into(),
+        "wratio" | "WRatio" => Fuzz::WRatio.into(),
+        _ => return Err(format!("unknown metric '{}'", name)),
+    };
+    Ok(scorer)
+}
+
+/// A comma separated list of preprocessing steps.
+fn parse_processor(steps: &str) -> std::result::Result<Preprocessor, String> {
+    steps.split(',').try_fold(Preprocessor::default(), |processor, step| {
+        Ok(processor.union(&step.trim().parse::<Preprocessor>()?))
+    })
+}
+
+
+/// The values of one column of a CSV, TSV or JSON lines input, read a batch
+/// at a time. Missing and null values are `None`.
+enum Rows {
+    Delimited {
+        records: csv::StringRecordsIntoIter<Box<dyn Read>>,
+        column: usize,
+    },
+    Jsonl {
+        lines: io::Lines<BufReader<Box<dyn Read>>>,
+        column: String,
+        line: usize,
+    },
+}
+
+impl Rows {
+    fn new(input: Box<dyn Read>, format: Format, column: Option<&str>, header: bool) -> Result<Rows> {
+        match format {
+            Format::Csv | Format::Tsv => {
+                let mut reader = csv::ReaderBuilder::new()
+                    .delimiter(format.delimiter())
+                    .flexible(true)
+                    .has_headers(header)
+                    .from_reader(input);
+                let column = match column {
+                    Some(column) if header => reader
+                        .headers()?
+                        .iter()
+                        .position(|header| header == column)
+                        .ok_or_else(|| format!("no column named '{}'", column))?,
+                    Some(column) => column
+                        .parse()
+                        .map_err(|_| format!("files without a header have no column named '{}', use its number", column))?,
+                    None => 0,
+                };
+                Ok(Rows::Delimited { records: reader.into_records(), column })
+            }
+            Format::Jsonl => {
+                let column = column.ok_or("JSON lines inputs need --left-col and --right-col")?;
+                Ok(Rows::Jsonl { lines: BufReader::new(input).lines(), column: column.to_string(), line: 0 })
+            }
+        }
+    }
+
+    fn open(path: &Path, format: Option<Format>, column: Option<&str>, header: bool) -> Result<Rows> {
+        let input: Box<dyn Read> = if path == Path::new("-") {
+            Box::new(io::stdin())
+        } else {
+            Box::new(File::open(path).map_err(|err| format!("{}: {}", path.display(), err))?)
+        };
+        Rows::new(input, format.unwrap_or_else(|| Format::from_path(path)), column, header)
+    }
+
+    /// Up to `size` values, fewer only at the end of the input.
+    fn next_batch(&mut self, size: usize) -> Result<Vec<Option<String>>> {
+        let mut batch = Vec::with_capacity(size.min(4096));
+        while batch.len() < size {
+            match self {
+                Rows::Delimited { records, column } => match records.next() {
+                    Some(record) => batch.push(record?.get(*column).map(str::to_string)),
+                    None => break,
+                },
+                Rows::Jsonl { lines, column, line } => {
+                    let text = match lines.next() {
+                        Some(text) => text?,
+                        None => break,
+                    };
+                    *line += 1;
+                    if text.trim().is_empty() {
+                        continue;
+                    }
+                    let value: serde_json::Value = serde_json::from_str(&text)
+                        .map_err(|err| format!("line {}: {}", line, err))?;
+                    batch.push(match value.get(column.as_str()) {
+                        Some(serde_json::Value::String(string)) => Some(string.clone()),
+                        Some(serde_json::Value::Null) | None => None,
+                        Some(other) => Some(other.to_string()),
+                    });
+                }
+            }
+        }
+        Ok(batch)
+    }
+}
+
+
+/// Writes matched pairs as CSV, TSV or JSON lines.
+enum Output {
+    Delimited(Box<csv::Writer<Box<dyn Write>>>),
+    Jsonl(BufWriter<Box<dyn Write>>),
+}
+
+impl Output {
+    fn new(output: Box<dyn Write>, format: Format) -> Result<Output> {
+        match format {
+            Format::Csv | Format::Tsv => {
+                let mut writer = csv::WriterBuilder::new().delimiter(format.delimiter()).from_writer(output);
+                writer.write_record(["left_index", "left", "right_index", "right", "score"])?;
+                Ok(Output::Delimited(Box::new(writer)))
+            }
+            Format::Jsonl => Ok(Output::Jsonl(BufWriter::new(output))),
+        }
+    }
+
+    fn write(&mut self, left: (usize, &str), right: (usize, &str), score: f32, integer: bool) -> Result<()> {
+        match self {
+            Output::Delimited(writer) => {
+                let score = if integer { (score as u64).to_string() } else { score.to_string() };
+                writer.write_record([&left.0.to_string(), left.1, &right.0.to_string(), right.1, &score])?;
+            }
+            Output::Jsonl(writer) => {
+                let score = if integer { serde_json::json!(score as u64) } else { serde_json::json!(score) };
+                let pair = serde_json::json!({
+                    "left_index": left.0,
+                    "left": left.1,
+                    "right_index": right.0,
+                    "right": right.1,
+                    "score": score,
+                });
+                writeln!(writer, "{}", pair)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        match self {
+            Output::Delimited(writer) => writer.flush()?,
+            Output::Jsonl(writer) => writer.flush()?,
+        }
+        Ok(())
+    }
+}
+
+
+/// The present values of a batch with their row index, as given and
+/// processed.
+fn prepare(batch: Vec<Option<String>>, offset: usize, processor: &Preprocessor) -> (Vec<(usize, String)>, Vec<String>) {
+    batch
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, value)| value.map(|value| (offset + index, value)))
+        .map(|(index, value)| {
+            let processed = processor.apply(&value).into_owned();
+            ((index, value), processed)
+        })
+        .unzip()
+}
+
+/// Streams `left` against every row of `right`, returning the number of
+/// pairs written.
+#[allow(clippy::too_many_arguments)]
+fn match_rows(
+    mut left: Rows,
+    mut right: Rows,
+    output: &mut Output,
+    scorer: &Scorer,
+    unit: Unit,
+    processor: &Preprocessor,
+    limit: Option<usize>,
+    threshold: Option<f32>,
+    batch_size: usize,
+    ) -> Result<usize> {
+    let (right_rows, right_keys) = prepare(right.next_batch(usize::MAX)?, 0, processor);
+    let batch_size = batch_size.max(1);
+
+    let mut offset = 0;
+    let mut n_pairs = 0;
+    loop {
+        let batch = left.next_batch(batch_size)?;
+        if batch.is_empty() {
+            break;
+        }
+        let n_rows = batch.len();
+        let (left_rows, left_keys) = prepare(batch, offset, processor);
+
+        let matches = extract_many(&left_keys, &right_keys, scorer, unit, limit, threshold);
+        for ((left_index, left_value), matches) in left_rows.iter().zip(matches) {
+            for (choice, score) in matches {
+                let (right_index, right_value) = &right_rows[choice];
+                output.write((*left_index, left_value), (*right_index, right_value), score, scorer.is_integer())?;
+                n_pairs += 1;
+            }
+        }
+        offset += n_rows;
+    }
+    output.flush()?;
+    Ok(n_pairs)
+}
+
+fn run_match(args: MatchArgs) -> Result<()> {
+    let scorer = parse_metric(&args.metric, args.q)?;
+    scorer.check_unit(args.unit)?;
+    let processor = match &args.processor {
+        Some(steps) => parse_processor(steps)?,
+        None => Preprocessor::default(),
+    };
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new().num_threads(threads).build_global()?;
+    }
+
+    let left = Rows::open(&args.left, args.input_format, args.left_col.as_deref(), !args.no_header)?;
+    let right = Rows::open(&args.right, args.input_format, args.right_col.as_deref(), !args.no_header)?;
+    let output: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(File::create(path).map_err(|err| format!("{}: {}", path.display(), err))?),
+        None => Box::new(io::stdout().lock()),
+    };
+    let mut output = Output::new(output, args.format)?;
+
+    let n_pairs = match_rows(left, right, &mut output, &scorer, args.unit, &processor, args.limit, args.threshold, args.batch_size)?;
+    if args.output.is_some() {
+        eprintln!("{} matches", n_pairs);
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let result = match Cli::parse().command {
+        Command::Match(args) => run_match(args),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("strsim: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+    use std::sync::{ Arc, Mutex };
+
+    /// A writer the test can read back once `Output` is done with it.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn rows(text: &str, format: Format, column: Option<&str>) -> Rows {
+        Rows::new(Box::new(Cursor::new(text.to_string().into_bytes())), format, column, true).unwrap()
+    }
+
+    fn headerless(text: &str, column: Option<&str>) -> Result<Rows> {
+        Rows::new(Box::new(Cursor::new(text.to_string().into_bytes())), Format::Csv, column, false)
+    }
+
+    fn run(left: Rows, right: Rows, scorer: &Scorer, format: Format, threshold: Option<f32>) -> String {
+        let shared = Shared::default();
+        let mut output = Output::new(Box::new(shared.clone()), format).unwrap();
+        match_rows(left, right, &mut output, scorer, Unit::Byte, &Preprocessor::default(), None, threshold, 2).unwrap();
+        drop(output);
+        let bytes = shared.0.lock().unwrap().clone();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn matches_delimited_files() {
+        let left = rows("id,name\n1,martha\n2,dwayne\n3,\n4,dixon\n", Format::Csv, Some("name"));
+        let right = rows("name\tcity\nmarhta\tparis\nduane\trome\ndicksonx\toslo\n", Format::Tsv, Some("name"));
+        let scorer = parse_metric("lev", 2).unwrap();
+
+        let written = run(left, right, &scorer, Format::Csv, Some(2.0));
+        assert_eq!(written, "left_index,left,right_index,right,score\n0,martha,0,marhta,2\n1,dwayne,1,duane,2\n");
+
+        // without a header the first row is a value too
+        let left = headerless("martha\nzed\n", None).unwrap();
+        let right = headerless("1,marhta\n2,zed\n", Some("1")).unwrap();
+        let written = run(left, right, &parse_metric("jw", 2).unwrap(), Format::Csv, Some(0.9));
+        assert_eq!(written, "left_index,left,right_index,right,score\n0,martha,0,marhta,0.96111107\n1,zed,1,zed,1\n");
+        assert!(headerless("martha\n", Some("name")).is_err());
+    }
+
+    #[test]
+    fn matches_json_lines() {
+        let left = rows("{\"name\": \"martha\"}\n\n{\"name\": null}\n{\"other\": 1}\n", Format::Jsonl, Some("name"));
+        let right = rows("name\nmarhta\nmartha\n", Format::Csv, None);
+        let scorer = parse_metric("jw", 2).unwrap();
+
+        let written = run(left, right, &scorer, Format::Jsonl, Some(0.99));
+        assert_eq!(written, "{\"left\":\"martha\",\"left_index\":0,\"right\":\"martha\",\"right_index\":1,\"score\":1.0}\n");
+        assert!(Rows::new(Box::new(Cursor::new(Vec::new())), Format::Jsonl, None, true).is_err());
+    }
+
+    #[test]
+    fn parses_options() {
+        assert!(parse_metric("jw", 2).unwrap().passes(0.95, Some(0.9)));
+        assert!(parse_metric("levenshtein", 2).unwrap().is_distance());
+        assert!(parse_metric("soundex", 2).is_err());
+
+        assert_eq!(parse_processor("case_fold, strip_accents").unwrap().apply("Café"), "cafe");
+        assert_eq!(parse_processor("default").unwrap(), Preprocessor::all());
+        assert!(parse_processor("lower").is_err());
+
+        assert_eq!(Format::from_path(Path::new("names.tsv")), Format::Tsv);
+        assert_eq!(Format::from_path(Path::new("names.jsonl")), Format::Jsonl);
+        assert_eq!(Format::from_path(Path::new("names.txt")), Format::Csv);
+    }
 }
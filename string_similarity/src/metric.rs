@@ -0,0 +1,435 @@
+//! The Rust API: metrics are values built with builder style parameters and
+//! scored through the `Similarity` and `Distance` traits, or through
+//! `Metric` where either kind will do. Every metric converts into a
+//! `process::Scorer` for `process::cdist` and `process::extract`.
+//!
+//! ```
+//! use string_sim_metrics::metric::{ Distance, JaroWinkler, Levenshtein, Similarity };
+//! use string_sim_metrics::unit::Unit;
+//!
+//! let jw = JaroWinkler::new().scaling_factor(0.2);
+//! assert!(jw.similarity(b"martha", b"marhta") > 0.9);
+//!
+//! let lev = Levenshtein::new().substitution_cost(2);
+//! assert_eq!(lev.distance_str("kitten", "sitting", Unit::Char), 5);
+//! ```
+
+use crate::fuzz::Fuzz;
+use crate::lcs::get_lcs_similarity;
+use crate::process::{ EditDistance, Scorer };
+use crate::qgram::QGram;
+use crate::token::TokenSimilarity;
+use crate::unit::{ Symbol, Unit, with_units };
+use crate::{ get_jaro_winkler_similarity, get_legacy_jaro_winkler_similarity };
+
+
+/// A metric where higher scores mean closer strings.
+pub trait Similarity {
+    fn similarity<T: Symbol>(&self, str1: &[T], str2: &[T]) -> f32;
+
+    /// The similarity of two strings split into `unit`s.
+    fn similarity_str(&self, str1: &str, str2: &str, unit: Unit) -> f32 {
+        with_units!(unit, str1, str2, |a, b| self.similarity(a, b))
+    }
+}
+
+/// A metric counting the cost of the edits between two strings.
+pub trait Distance {
+    fn distance<T: Symbol>(&self, str1: &[T], str2: &[T]) -> usize;
+
+    /// The largest distance two strings of these lengths can have.
+    fn max_distance(&self, len1: usize, len2: usize) -> usize;
+
+    /// The distance divided by `max_distance`, in `[0, 1]`.
+    fn normalized_distance<T: Symbol>(&self, str1: &[T], str2: &[T]) -> f32 {
+        let max_distance = self.max_distance(str1.len(), str2.len());
+        if max_distance == 0 {
+            return 0.0;
+        }
+        self.distance(str1, str2) as f32 / max_distance as f32
+    }
+
+    /// `1 - normalized_distance`, in `[0, 1]`.
+    fn normalized_similarity<T: Symbol>(&self, str1: &[T], str2: &[T]) -> f32 {
+        1.0 - self.normalized_distance(str1, str2)
+    }
+
+    /// The distance between two strings split into `unit`s.
+    fn distance_str(&self, str1: &str, str2: &str, unit: Unit) -> usize {
+        with_units!(unit, str1, str2, |a, b| self.distance(a, b))
+    }
+}
+
+/// Any metric, for code that ranks strings without caring which kind it is.
+pub trait Metric {
+    /// The similarity, or the distance as a float.
+    fn score<T: Symbol>(&self, str1: &[T], str2: &[T]) -> f32;
+
+    /// Whether lower scores mean closer strings.
+    fn is_distance(&self) -> bool;
+
+    /// The score of two strings split into `unit`s.
+    fn score_str(&self, str1: &str, str2: &str, unit: Unit) -> f32 {
+        with_units!(unit, str1, str2, |a, b| self.score(a, b))
+    }
+}
+
+
+/// Jaro winkler similarity. Defaults to a `max_prefix_length` of 4 and a
+/// `scaling_factor` of 0.1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JaroWinkler {
+    max_prefix_length: usize,
+    scaling_factor: f32,
+    legacy_semantics: bool,
+}
+
+impl Default for JaroWinkler {
+    fn default() -> JaroWinkler {
+        JaroWinkler { max_prefix_length: 4, scaling_factor: 0.1, legacy_semantics: false }
+    }
+}
+
+impl JaroWinkler {
+    pub fn new() -> JaroWinkler {
+        JaroWinkler::default()
+    }
+
+    pub fn max_prefix_length(mut self, max_prefix_length: usize) -> JaroWinkler {
+        self.max_prefix_length = max_prefix_length;
+        self
+    }
+
+    pub fn scaling_factor(mut self, scaling_factor: f32) -> JaroWinkler {
+        self.scaling_factor = scaling_factor;
+        self
+    }
+
+    /// Scores two empty strings 0, as the original implementation did.
+    pub fn legacy_semantics(mut self, legacy_semantics: bool) -> JaroWinkler {
+        self.legacy_semantics = legacy_semantics;
+        self
+    }
+}
+
+impl Similarity for JaroWinkler {
+    fn similarity<T: Symbol>(&self, str1: &[T], str2: &[T]) -> f32 {
+        if self.legacy_semantics {
+            get_legacy_jaro_winkler_similarity(str1, str2, self.max_prefix_length, self.scaling_factor, 0.0)
+        } else {
+            get_jaro_winkler_similarity(str1, str2, self.max_prefix_length, self.scaling_factor)
+        }
+    }
+}
+
+impl From<JaroWinkler> for Scorer {
+    fn from(jw: JaroWinkler) -> Scorer {
+        let JaroWinkler { max_prefix_length, scaling_factor, legacy_semantics } = jw;
+        if legacy_semantics {
+            Scorer::LegacyJaroWinkler { max_prefix_length, scaling_factor }
+        } else {
+            Scorer::JaroWinkler { max_prefix_length, scaling_factor }
+        }
+    }
+}
+
+
+/// Weighted levenshtein distance. Every cost defaults to 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Levenshtein {
+    deletion_cost: usize,
+    insertion_cost: usize,
+    substitution_cost: usize,
+    legacy_semantics: bool,
+}
+
+impl Default for Levenshtein {
+    fn default() -> Levenshtein {
+        Levenshtein { deletion_cost: 1, insertion_cost: 1, substitution_cost: 1, legacy_semantics: false }
+    }
+}
+
+impl Levenshtein {
+    pub fn new() -> Levenshtein {
+        Levenshtein::default()
+    }
+
+    pub fn deletion_cost(mut self, deletion_cost: usize) -> Levenshtein {
+        self.deletion_cost = deletion_cost;
+        self
+    }
+
+    pub fn insertion_cost(mut self, insertion_cost: usize) -> Levenshtein {
+        self.insertion_cost = insertion_cost;
+        self
+    }
+
+    pub fn substitution_cost(mut self, substitution_cost: usize) -> Levenshtein {
+        self.substitution_cost = substitution_cost;
+        self
+    }
+
+    /// Returns 0 if either string is empty and 1 for identical strings, as
+    /// the original implementation did.
+    pub fn legacy_semantics(mut self, legacy_semantics: bool) -> Levenshtein {
+        self.legacy_semantics = legacy_semantics;
+        self
+    }
+
+    fn edit_distance(&self) -> EditDistance {
+        let Levenshtein { deletion_cost, insertion_cost, substitution_cost, legacy_semantics } = *self;
+        if legacy_semantics {
+            EditDistance::LegacyWeightedLevenshtein { deletion_cost, insertion_cost, substitution_cost }
+        } else {
+            EditDistance::WeightedLevenshtein { deletion_cost, insertion_cost, substitution_cost }
+        }
+    }
+}
+
+
+/// Optimal string alignment distance, which allows transposing adjacent
+/// units but not editing a substring more than once. Every cost defaults
+/// to 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Osa {
+    deletion_cost: usize,
+    insertion_cost: usize,
+    substitution_cost: usize,
+    transposition_cost: usize,
+}
+
+/// Unrestricted Damerau-Levenshtein distance. Every cost defaults to 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DamerauLevenshtein {
+    deletion_cost: usize,
+    insertion_cost: usize,
+    substitution_cost: usize,
+    transposition_cost: usize,
+}
+
+macro_rules! transposition_builder {
+    ($name:ident, $variant:ident) => {
+        impl Default for $name {
+            fn default() -> $name {
+                $name { deletion_cost: 1, insertion_cost: 1, substitution_cost: 1, transposition_cost: 1 }
+            }
+        }
+
+        impl $name {
+            pub fn new() -> $name {
+                $name::default()
+            }
+
+            pub fn deletion_cost(mut self, deletion_cost: usize) -> $name {
+                self.deletion_cost = deletion_cost;
+                self
+            }
+
+            pub fn insertion_cost(mut self, insertion_cost: usize) -> $name {
+                self.insertion_cost = insertion_cost;
+                self
+            }
+
+            pub fn substitution_cost(mut self, substitution_cost: usize) -> $name {
+                self.substitution_cost = substitution_cost;
+                self
+            }
+
+            pub fn transposition_cost(mut self, transposition_cost: usize) -> $name {
+                self.transposition_cost = transposition_cost;
+                self
+            }
+
+            fn edit_distance(&self) -> EditDistance {
+                let $name { deletion_cost, insertion_cost, substitution_cost, transposition_cost } = *self;
+                EditDistance::$variant { deletion_cost, insertion_cost, substitution_cost, transposition_cost }
+            }
+        }
+    };
+}
+
+transposition_builder!(Osa, Osa);
+transposition_builder!(DamerauLevenshtein, DamerauLevenshtein);
+
+
+/// Indel distance, the number of insertions and deletions needed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Indel;
+
+impl Indel {
+    fn edit_distance(&self) -> EditDistance {
+        EditDistance::Indel
+    }
+}
+
+
+/// Implements `Distance` and `Metric` for a builder with an `edit_distance`
+/// method, deferring to the same kernels as `Scorer`.
+macro_rules! edit_distance_metric {
+    ($($name:ident),+) => {
+        $(
+            impl Distance for $name {
+                fn distance<T: Symbol>(&self, str1: &[T], str2: &[T]) -> usize {
+                    self.edit_distance().distance(str1, str2, usize::MAX)
+                }
+
+                fn max_distance(&self, len1: usize, len2: usize) -> usize {
+                    self.edit_distance().max_distance(len1, len2)
+                }
+
+                fn normalized_distance<T: Symbol>(&self, str1: &[T], str2: &[T]) -> f32 {
+                    self.edit_distance().normalized_distance(str1, str2, 1.0)
+                }
+            }
+
+            impl Metric for $name {
+                fn score<T: Symbol>(&self, str1: &[T], str2: &[T]) -> f32 {
+                    self.distance(str1, str2) as f32
+                }
+
+                fn is_distance(&self) -> bool {
+                    true
+                }
+            }
+
+            impl From<$name> for Scorer {
+                fn from(distance: $name) -> Scorer {
+                    Scorer::Distance(distance.edit_distance())
+                }
+            }
+        )+
+    };
+}
+
+edit_distance_metric!(Levenshtein, Osa, DamerauLevenshtein, Indel);
+
+
+/// Longest common subsequence similarity, `lcs / max(len1, len2)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Lcs;
+
+impl Similarity for Lcs {
+    fn similarity<T: Symbol>(&self, str1: &[T], str2: &[T]) -> f32 {
+        get_lcs_similarity(str1, str2)
+    }
+}
+
+impl From<Lcs> for Scorer {
+    fn from(_: Lcs) -> Scorer {
+        Scorer::LcsSimilarity
+    }
+}
+
+impl Similarity for QGram {
+    fn similarity<T: Symbol>(&self, str1: &[T], str2: &[T]) -> f32 {
+        QGram::similarity(self, str1, str2)
+    }
+}
+
+impl From<QGram> for Scorer {
+    fn from(qgram: QGram) -> Scorer {
+        Scorer::QGram(qgram)
+    }
+}
+
+impl Similarity for Fuzz {
+    fn similarity<T: Symbol>(&self, str1: &[T], str2: &[T]) -> f32 {
+        self.score(str1, str2)
+    }
+}
+
+impl From<Fuzz> for Scorer {
+    fn from(fuzz: Fuzz) -> Scorer {
+        Scorer::Fuzz(fuzz)
+    }
+}
+
+impl Similarity for TokenSimilarity {
+    fn similarity<T: Symbol>(&self, str1: &[T], str2: &[T]) -> f32 {
+        TokenSimilarity::similarity(self, str1, str2)
+    }
+}
+
+impl From<TokenSimilarity> for Scorer {
+    fn from(token: TokenSimilarity) -> Scorer {
+        Scorer::Token(token)
+    }
+}
+
+macro_rules! similarity_metric {
+    ($($name:ident),+) => {
+        $(
+            impl Metric for $name {
+                fn score<T: Symbol>(&self, str1: &[T], str2: &[T]) -> f32 {
+                    Similarity::similarity(self, str1, str2)
+                }
+
+                fn is_distance(&self) -> bool {
+                    false
+                }
+            }
+        )+
+    };
+}
+
+similarity_metric!(JaroWinkler, Lcs, QGram, Fuzz, TokenSimilarity);
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::damerau::{ get_damerau_levenshtein_distance, get_osa_distance };
+    use crate::get_weighted_levenshtein_distance;
+    use crate::process::cdist;
+    use crate::qgram::SetMeasure;
+
+    #[test]
+    fn builders_match_kernels() {
+        let jw = JaroWinkler::new().max_prefix_length(2).scaling_factor(0.2);
+        assert_eq!(jw.similarity(b"martha", b"marhta"), get_jaro_winkler_similarity(b"martha", b"marhta", 2, 0.2));
+        assert_eq!(JaroWinkler::new().similarity::<u8>(b"", b""), 1.0);
+        assert_eq!(JaroWinkler::new().legacy_semantics(true).similarity::<u8>(b"", b""), 0.0);
+
+        let lev = Levenshtein::new().insertion_cost(2).substitution_cost(3);
+        assert_eq!(lev.distance(b"kitten", b"sitting"), get_weighted_levenshtein_distance(b"kitten", b"sitting", 1, 2, 3));
+        assert_eq!(lev.max_distance(6, 7), 20);
+
+        let osa = Osa::new().transposition_cost(2);
+        assert_eq!(osa.distance(b"ca", b"abc"), get_osa_distance(b"ca", b"abc", 1, 1, 1, 2));
+        let dl = DamerauLevenshtein::new();
+        assert_eq!(dl.distance(b"ca", b"abc"), get_damerau_levenshtein_distance(b"ca", b"abc", 1, 1, 1, 1));
+
+        assert_eq!(Indel.distance(b"kitten", b"sitting"), 5);
+        assert_eq!(Indel.normalized_similarity(b"kitten", b"sitting"), 1.0 - 5.0 / 13.0);
+        assert_eq!(Lcs.similarity(b"kitten", b"sitting"), 4.0 / 7.0);
+        assert_eq!(Fuzz::Ratio.similarity(b"kitten", b"sitting"), Fuzz::Ratio.score(b"kitten", b"sitting"));
+    }
+
+    #[test]
+    fn units_and_metrics() {
+        assert_eq!(Levenshtein::new().distance_str("café", "cafe", Unit::Byte), 2);
+        assert_eq!(Levenshtein::new().distance_str("café", "cafe", Unit::Char), 1);
+
+        fn best<M: Metric>(metric: &M, query: &str, choices: &[&str]) -> usize {
+            let scores: Vec<f32> = choices.iter().map(|choice| metric.score_str(query, choice, Unit::Char)).collect();
+            let better = |a: f32, b: f32| if metric.is_distance() { a < b } else { a > b };
+            (1..scores.len()).fold(0, |best, idx| if better(scores[idx], scores[best]) { idx } else { best })
+        }
+        let choices = ["sitting", "kitchen", "kittens"];
+        assert_eq!(best(&JaroWinkler::new(), "kitten", &choices), 2);
+        assert_eq!(best(&Levenshtein::new(), "kitten", &choices), 2);
+        assert_eq!(best(&QGram::new(SetMeasure::Jaccard).q(2), "kitten", &choices), 2);
+    }
+
+    #[test]
+    fn metrics_convert_to_scorers() {
+        let queries = ["kitten"];
+        let choices = ["sitting", "mitten"];
+        let scorer = Scorer::from(Levenshtein::new().substitution_cost(2));
+        assert!(scorer.is_distance());
+        assert_eq!(cdist(&queries, &choices, &scorer, Unit::Byte, None), vec![5.0, 2.0]);
+
+        let scorer = Scorer::from(JaroWinkler::new());
+        assert_eq!(cdist(&queries, &choices, &scorer, Unit::Byte, None)[1], JaroWinkler::new().similarity(b"kitten", b"mitten"));
+    }
+}
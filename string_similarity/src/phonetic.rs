@@ -0,0 +1,994 @@
+use std::fmt;
+use std::str::FromStr;
+
+
+/// A phonetic encoding, used to compare names by how they sound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhoneticAlgorithm {
+    Soundex,
+    RefinedSoundex,
+    Metaphone,
+    DoubleMetaphone,
+    Nysiis,
+}
+
+impl FromStr for PhoneticAlgorithm {
+    type Err = String;
+
+    fn from_str(algorithm: &str) -> Result<PhoneticAlgorithm, String> {
+        match algorithm {
+            "soundex"          => Ok(PhoneticAlgorithm::Soundex),
+            "refined_soundex"  => Ok(PhoneticAlgorithm::RefinedSoundex),
+            "metaphone"        => Ok(PhoneticAlgorithm::Metaphone),
+            "double_metaphone" => Ok(PhoneticAlgorithm::DoubleMetaphone),
+            "nysiis"           => Ok(PhoneticAlgorithm::Nysiis),
+            _ => Err(format!(
+                "algorithm must be one of 'soundex', 'refined_soundex', 'metaphone', 'double_metaphone' \
+                 or 'nysiis', got '{}'",
+                algorithm,
+            )),
+        }
+    }
+}
+
+impl fmt::Display for PhoneticAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            PhoneticAlgorithm::Soundex         => "soundex",
+            PhoneticAlgorithm::RefinedSoundex  => "refined_soundex",
+            PhoneticAlgorithm::Metaphone       => "metaphone",
+            PhoneticAlgorithm::DoubleMetaphone => "double_metaphone",
+            PhoneticAlgorithm::Nysiis          => "nysiis",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl PhoneticAlgorithm {
+    /// The keys of `string`. Every algorithm gives one key except double
+    /// metaphone, which gives its primary and alternate keys.
+    pub fn keys(&self, string: &str) -> Vec<String> {
+        match self {
+            PhoneticAlgorithm::Soundex => vec![soundex(string)],
+            PhoneticAlgorithm::RefinedSoundex => vec![refined_soundex(string)],
+            PhoneticAlgorithm::Metaphone => vec![metaphone(string)],
+            PhoneticAlgorithm::DoubleMetaphone => {
+                let (primary, alternate) = double_metaphone(string);
+                vec![primary, alternate]
+            }
+            PhoneticAlgorithm::Nysiis => vec![nysiis(string)],
+        }
+    }
+
+    /// Whether the two strings share a non empty key. For double metaphone
+    /// either key of one string may match either key of the other.
+    pub fn is_match(&self, str1: &str, str2: &str) -> bool {
+        let keys2 = self.keys(str2);
+        self.keys(str1).iter().any(|key| !key.is_empty() && keys2.contains(key))
+    }
+}
+
+
+/// The ASCII letters of `string`, uppercased. Every other character is
+/// dropped, so accented names should be passed through `strip_accents` first.
+fn letters(string: &str) -> Vec<u8> {
+    string.bytes().filter(u8::is_ascii_alphabetic).map(|c| c.to_ascii_uppercase()).collect()
+}
+
+fn is_vowel(c: u8) -> bool {
+    matches!(c, b'A' | b'E' | b'I' | b'O' | b'U')
+}
+
+
+/// American Soundex: the first letter followed by three digits for the
+/// consonant groups that follow. Adjacent letters with the same digit are
+/// coded once, also when separated by H or W, but not when separated by a
+/// vowel.
+pub fn soundex(string: &str) -> String {
+    fn code(c: u8) -> u8 {
+        match c {
+            b'B' | b'F' | b'P' | b'V' => b'1',
+            b'C' | b'G' | b'J' | b'K' | b'Q' | b'S' | b'X' | b'Z' => b'2',
+            b'D' | b'T' => b'3',
+            b'L' => b'4',
+            b'M' | b'N' => b'5',
+            b'R' => b'6',
+            _ => b'0',
+        }
+    }
+
+    let letters = letters(string);
+    let first = match letters.first() {
+        Some(first) => *first,
+        None => return String::new(),
+    };
+
+    let mut key = vec![first];
+    let mut last = code(first);
+    for &c in &letters[1..] {
+        if c == b'H' || c == b'W' {
+            continue;
+        }
+        let digit = code(c);
+        if digit != b'0' && digit != last {
+            key.push(digit);
+        }
+        last = digit;
+    }
+    key.resize(4, b'0');
+    String::from_utf8(key).unwrap()
+}
+
+
+/// Refined Soundex as in Apache Commons Codec: the first letter followed by
+/// one digit per letter from a finer set of groups, with vowels coded as 0
+/// and repeated digits collapsed. The key is not truncated.
+pub fn refined_soundex(string: &str) -> String {
+    const CODES: &[u8; 26] = b"01360240043788015936020505";
+
+    let letters = letters(string);
+    let first = match letters.first() {
+        Some(first) => *first,
+        None => return String::new(),
+    };
+
+    let mut key = vec![first];
+    let mut last = None;
+    for &c in &letters {
+        let digit = CODES[(c - b'A') as usize];
+        if last != Some(digit) {
+            key.push(digit);
+            last = Some(digit);
+        }
+    }
+    String::from_utf8(key).unwrap()
+}
+
+
+/// Lawrence Philips' original Metaphone. The key is not truncated.
+pub fn metaphone(string: &str) -> String {
+    let mut word = letters(string);
+    if word.starts_with(b"KN") || word.starts_with(b"GN") || word.starts_with(b"PN")
+        || word.starts_with(b"AE") || word.starts_with(b"WR") {
+        word.remove(0);
+    }
+    if word.first() == Some(&b'X') {
+        word[0] = b'S';
+    } else if word.starts_with(b"WH") {
+        word.remove(1);
+    }
+
+    let len = word.len();
+    let at = |idx: usize| -> u8 { word.get(idx).copied().unwrap_or(0) };
+    let mut key = Vec::with_capacity(len);
+    let mut idx = 0;
+
+    while idx < len {
+        let c = word[idx];
+        let prev = if idx > 0 { word[idx - 1] } else { 0 };
+        let next = at(idx + 1);
+        let after_next = at(idx + 2);
+
+        if c == prev && c != b'C' {
+            idx += 1;
+            continue;
+        }
+
+        match c {
+            b'A' | b'E' | b'I' | b'O' | b'U' => {
+                if idx == 0 {
+                    key.push(c);
+                }
+            }
+            b'B' => {
+                // Silent in a final "MB", as in "dumb".
+                if !(prev == b'M' && idx + 1 == len) {
+                    key.push(b'B');
+                }
+            }
+            b'C' => {
+                if next == b'I' && after_next == b'A' {
+                    key.push(b'X');
+                } else if next == b'H' {
+                    key.push(if prev == b'S' { b'K' } else { b'X' });
+                    idx += 1;
+                } else if matches!(next, b'I' | b'E' | b'Y') {
+                    // Silent in "SCI", "SCE" and "SCY".
+                    if prev != b'S' {
+                        key.push(b'S');
+                    }
+                } else {
+                    key.push(b'K');
+                }
+            }
+            b'D' => {
+                if next == b'G' && matches!(after_next, b'E' | b'I' | b'Y') {
+                    key.push(b'J');
+                    idx += 1;
+                } else {
+                    key.push(b'T');
+                }
+            }
+            b'G' => {
+                if next == b'H' && idx + 2 < len && !is_vowel(after_next) {
+                    // Silent, as in "night".
+                    idx += 1;
+                } else if next == b'N' && (idx + 2 == len || (after_next == b'E' && at(idx + 3) == b'D' && idx + 4 == len)) {
+                    // Silent in a final "GN" or "GNED".
+                } else if matches!(next, b'I' | b'E' | b'Y') && prev != b'G' {
+                    key.push(b'J');
+                } else {
+                    key.push(b'K');
+                    if next == b'H' {
+                        idx += 1;
+                    }
+                }
+            }
+            b'H' => {
+                if is_vowel(next) && !matches!(prev, b'C' | b'G' | b'P' | b'S' | b'T') {
+                    key.push(b'H');
+                }
+            }
+            b'K' => {
+                if prev != b'C' {
+                    key.push(b'K');
+                }
+            }
+            b'P' => {
+                if next == b'H' {
+                    key.push(b'F');
+                    idx += 1;
+                } else {
+                    key.push(b'P');
+                }
+            }
+            b'Q' => key.push(b'K'),
+            b'S' => {
+                if next == b'H' {
+                    key.push(b'X');
+                    idx += 1;
+                } else if next == b'I' && matches!(after_next, b'O' | b'A') {
+                    key.push(b'X');
+                } else {
+                    key.push(b'S');
+                }
+            }
+            b'T' => {
+                if next == b'I' && matches!(after_next, b'O' | b'A') {
+                    key.push(b'X');
+                } else if next == b'H' {
+                    key.push(b'0');
+                    idx += 1;
+                } else if !(next == b'C' && after_next == b'H') {
+                    key.push(b'T');
+                }
+            }
+            b'V' => key.push(b'F'),
+            b'W' | b'Y' => {
+                if is_vowel(next) {
+                    key.push(c);
+                }
+            }
+            b'X' => key.extend_from_slice(b"KS"),
+            b'Z' => key.push(b'S'),
+            _ => key.push(c),
+        }
+        idx += 1;
+    }
+    String::from_utf8(key).unwrap()
+}
+
+
+/// The New York State Identification and Intelligence System code. The
+/// name is translated in place, so rules that look at the previous letter see
+/// it after translation. The key is not truncated to the traditional six
+/// letters.
+pub fn nysiis(string: &str) -> String {
+    let mut name = letters(string);
+    if name.is_empty() {
+        return String::new();
+    }
+
+    let replace_prefix = |name: &mut Vec<u8>, prefix: &[u8], replacement: &[u8]| -> bool {
+        if name.starts_with(prefix) {
+            name.splice(..prefix.len(), replacement.iter().copied());
+            return true;
+        }
+        false
+    };
+    let _ = replace_prefix(&mut name, b"MAC", b"MCC")
+        || replace_prefix(&mut name, b"KN", b"NN")
+        || replace_prefix(&mut name, b"K", b"C")
+        || replace_prefix(&mut name, b"PH", b"FF")
+        || replace_prefix(&mut name, b"PF", b"FF")
+        || replace_prefix(&mut name, b"SCH", b"SSS");
+
+    if name.ends_with(b"EE") || name.ends_with(b"IE") {
+        name.truncate(name.len() - 2);
+        name.push(b'Y');
+    } else if [&b"DT"[..], b"RT", b"RD", b"NT", b"ND"].iter().any(|suffix| name.ends_with(suffix)) {
+        name.truncate(name.len() - 2);
+        name.push(b'D');
+    }
+
+    let len = name.len();
+    let mut key = vec![name[0]];
+    for idx in 1..len {
+        let prev = name[idx - 1];
+        let next = name.get(idx + 1).copied().unwrap_or(0);
+
+        match name[idx] {
+            b'E' if next == b'V' => {
+                name[idx] = b'A';
+                name[idx + 1] = b'F';
+            }
+            b'A' | b'E' | b'I' | b'O' | b'U' => name[idx] = b'A',
+            b'Q' => name[idx] = b'G',
+            b'Z' => name[idx] = b'S',
+            b'M' => name[idx] = b'N',
+            b'K' => name[idx] = if next == b'N' { b'N' } else { b'C' },
+            b'S' if next == b'C' && name.get(idx + 2) == Some(&b'H') => {
+                name[idx + 1] = b'S';
+                name[idx + 2] = b'S';
+            }
+            b'P' if next == b'H' => {
+                name[idx] = b'F';
+                name[idx + 1] = b'F';
+            }
+            b'H' if !is_vowel(prev) || !is_vowel(next) => name[idx] = prev,
+            b'W' if is_vowel(prev) => name[idx] = prev,
+            _ => {}
+        }
+
+        if key.last() != Some(&name[idx]) {
+            key.push(name[idx]);
+        }
+    }
+
+    if key.len() > 1 && key.last() == Some(&b'S') {
+        key.pop();
+    }
+    if key.ends_with(b"AY") {
+        key.truncate(key.len() - 2);
+        key.push(b'Y');
+    }
+    if key.len() > 1 && key.last() == Some(&b'A') {
+        key.pop();
+    }
+    String::from_utf8(key).unwrap()
+}
+
+
+/// The letters of a name for double metaphone, uppercased and padded with
+/// spaces so that rules can look a few letters past the end.
+struct Word {
+    chars: Vec<char>,
+    len: usize,
+    slavo_germanic: bool,
+}
+
+impl Word {
+    const PADDING: usize = 5;
+
+    fn new(string: &str) -> Word {
+        let mut chars: Vec<char> = string.trim().chars().flat_map(char::to_uppercase).collect();
+        let len = chars.len();
+        let upper: String = chars.iter().collect();
+        let slavo_germanic = upper.contains('W') || upper.contains('K') || upper.contains("CZ") || upper.contains("WITZ");
+        chars.extend(std::iter::repeat_n(' ', Word::PADDING));
+        Word { chars, len, slavo_germanic }
+    }
+
+    fn at(&self, idx: isize) -> char {
+        if idx < 0 {
+            return '\0';
+        }
+        self.chars.get(idx as usize).copied().unwrap_or('\0')
+    }
+
+    fn is_vowel(&self, idx: isize) -> bool {
+        matches!(self.at(idx), 'A' | 'E' | 'I' | 'O' | 'U' | 'Y')
+    }
+
+    /// Whether the `len` letters from `start` are one of `options`.
+    fn string_at(&self, start: isize, len: usize, options: &[&str]) -> bool {
+        if start < 0 || start as usize + len > self.chars.len() {
+            return false;
+        }
+        let start = start as usize;
+        options.iter().any(|option| option.chars().eq(self.chars[start..start + len].iter().copied()))
+    }
+}
+
+
+/// The primary and alternate keys built up by double metaphone.
+#[derive(Default)]
+struct Keys {
+    primary: String,
+    alternate: String,
+}
+
+impl Keys {
+    fn add(&mut self, main: &str, alt: &str) {
+        self.primary.push_str(main);
+        self.alternate.push_str(alt);
+    }
+}
+
+
+/// Lawrence Philips' Double Metaphone, returning a primary and an alternate
+/// key of up to four characters. The alternate key differs from the primary
+/// one for names whose pronunciation depends on their origin, e.g.
+/// "Schmidt" gives "XMT" and "SMT".
+pub fn double_metaphone(string: &str) -> (String, String) {
+    const MAX_LENGTH: usize = 4;
+
+    let word = Word::new(string);
+    let length = word.len as isize;
+    let last = length - 1;
+    let mut keys = Keys::default();
+
+    let mut current: isize = 0;
+    if word.string_at(0, 2, &["GN", "KN", "PN", "WR", "PS"]) {
+        current += 1;
+    }
+    // An initial X is pronounced Z, as in "Xavier".
+    if word.at(0) == 'X' {
+        keys.add("S", "S");
+        current += 1;
+    }
+
+    // Doubled letters are coded once.
+    let skip_double = |current: isize, c: char| if word.at(current + 1) == c { 2 } else { 1 };
+
+    while current < length {
+        if keys.primary.len() >= MAX_LENGTH && keys.alternate.len() >= MAX_LENGTH {
+            break;
+        }
+
+        match word.at(current) {
+            'A' | 'E' | 'I' | 'O' | 'U' | 'Y' => {
+                // Every initial vowel maps to A.
+                if current == 0 {
+                    keys.add("A", "A");
+                }
+                current += 1;
+            }
+            'B' => {
+                // "-mb", as in "dumb", is handled by M.
+                keys.add("P", "P");
+                current += skip_double(current, 'B');
+            }
+            'Ç' => {
+                keys.add("S", "S");
+                current += 1;
+            }
+            'C' => current += double_metaphone_c(&word, current, &mut keys),
+            'D' => {
+                if word.string_at(current, 2, &["DG"]) {
+                    if word.string_at(current + 2, 1, &["I", "E", "Y"]) {
+                        // "edge"
+                        keys.add("J", "J");
+                        current += 3;
+                    } else {
+                        // "edgar"
+                        keys.add("TK", "TK");
+                        current += 2;
+                    }
+                } else if word.string_at(current, 2, &["DT", "DD"]) {
+                    keys.add("T", "T");
+                    current += 2;
+                } else {
+                    keys.add("T", "T");
+                    current += 1;
+                }
+            }
+            'F' => {
+                keys.add("F", "F");
+                current += skip_double(current, 'F');
+            }
+            'G' => current += double_metaphone_g(&word, current, &mut keys),
+            'H' => {
+                // Only kept if first or between two vowels.
+                if (current == 0 || word.is_vowel(current - 1)) && word.is_vowel(current + 1) {
+                    keys.add("H", "H");
+                    current += 2;
+                } else {
+                    current += 1;
+                }
+            }
+            'J' => current += double_metaphone_j(&word, current, last, &mut keys),
+            'K' => {
+                keys.add("K", "K");
+                current += skip_double(current, 'K');
+            }
+            'L' => {
+                if word.at(current + 1) == 'L' {
+                    // Spanish, as in "cabrillo" or "gallegos".
+                    if (current == length - 3 && word.string_at(current - 1, 4, &["ILLO", "ILLA", "ALLE"]))
+                        || ((word.string_at(last - 1, 2, &["AS", "OS"]) || word.string_at(last, 1, &["A", "O"]))
+                            && word.string_at(current - 1, 4, &["ALLE"])) {
+                        keys.add("L", "");
+                        current += 2;
+                        continue;
+                    }
+                    current += 2;
+                } else {
+                    current += 1;
+                }
+                keys.add("L", "L");
+            }
+            'M' => {
+                if (word.string_at(current - 1, 3, &["UMB"])
+                    && (current + 1 == last || word.string_at(current + 2, 2, &["ER"])))
+                    || word.at(current + 1) == 'M' {
+                    current += 2;
+                } else {
+                    current += 1;
+                }
+                keys.add("M", "M");
+            }
+            'N' => {
+                keys.add("N", "N");
+                current += skip_double(current, 'N');
+            }
+            'Ñ' => {
+                keys.add("N", "N");
+                current += 1;
+            }
+            'P' => {
+                if word.at(current + 1) == 'H' {
+                    keys.add("F", "F");
+                    current += 2;
+                } else {
+                    // Also "campbell" and "raspberry".
+                    current += if word.string_at(current + 1, 1, &["P", "B"]) { 2 } else { 1 };
+                    keys.add("P", "P");
+                }
+            }
+            'Q' => {
+                keys.add("K", "K");
+                current += skip_double(current, 'Q');
+            }
+            'R' => {
+                // French, as in "rogier", but not "hochmeier".
+                if current == last && !word.slavo_germanic
+                    && word.string_at(current - 2, 2, &["IE"])
+                    && !word.string_at(current - 4, 2, &["ME", "MA"]) {
+                    keys.add("", "R");
+                } else {
+                    keys.add("R", "R");
+                }
+                current += skip_double(current, 'R');
+            }
+            'S' => current += double_metaphone_s(&word, current, last, &mut keys),
+            'T' => {
+                if word.string_at(current, 4, &["TION"]) || word.string_at(current, 3, &["TIA", "TCH"]) {
+                    keys.add("X", "X");
+                    current += 3;
+                } else if word.string_at(current, 2, &["TH"]) || word.string_at(current, 3, &["TTH"]) {
+                    // "thomas", "thames" or germanic.
+                    if word.string_at(current + 2, 2, &["OM", "AM"])
+                        || word.string_at(0, 4, &["VAN ", "VON "])
+                        || word.string_at(0, 3, &["SCH"]) {
+                        keys.add("T", "T");
+                    } else {
+                        keys.add("0", "T");
+                    }
+                    current += 2;
+                } else {
+                    current += if word.string_at(current + 1, 1, &["T", "D"]) { 2 } else { 1 };
+                    keys.add("T", "T");
+                }
+            }
+            'V' => {
+                keys.add("F", "F");
+                current += skip_double(current, 'V');
+            }
+            'W' => {
+                if word.string_at(current, 2, &["WR"]) {
+                    keys.add("R", "R");
+                    current += 2;
+                    continue;
+                }
+                if current == 0 && (word.is_vowel(current + 1) || word.string_at(current, 2, &["WH"])) {
+                    // "Wasserman" should match "Vasserman", "Uomo" should match "Womo".
+                    if word.is_vowel(current + 1) {
+                        keys.add("A", "F");
+                    } else {
+                        keys.add("A", "A");
+                    }
+                }
+                // "Arnow" should match "Arnoff".
+                if (current == last && word.is_vowel(current - 1))
+                    || word.string_at(current - 1, 5, &["EWSKI", "EWSKY", "OWSKI", "OWSKY"])
+                    || word.string_at(0, 3, &["SCH"]) {
+                    keys.add("", "F");
+                    current += 1;
+                } else if word.string_at(current, 4, &["WICZ", "WITZ"]) {
+                    // Polish, as in "filipowicz".
+                    keys.add("TS", "FX");
+                    current += 4;
+                } else {
+                    current += 1;
+                }
+            }
+            'X' => {
+                // French, as in "breaux".
+                if !(current == last
+                    && (word.string_at(current - 3, 3, &["IAU", "EAU"]) || word.string_at(current - 2, 2, &["AU", "OU"]))) {
+                    keys.add("KS", "KS");
+                }
+                current += if word.string_at(current + 1, 1, &["C", "X"]) { 2 } else { 1 };
+            }
+            'Z' => {
+                if word.at(current + 1) == 'H' {
+                    // Chinese pinyin, as in "zhao".
+                    keys.add("J", "J");
+                    current += 2;
+                } else {
+                    if word.string_at(current + 1, 2, &["ZO", "ZI", "ZA"])
+                        || (word.slavo_germanic && current > 0 && word.at(current - 1) != 'T') {
+                        keys.add("S", "TS");
+                    } else {
+                        keys.add("S", "S");
+                    }
+                    current += skip_double(current, 'Z');
+                }
+            }
+            _ => current += 1,
+        }
+    }
+
+    keys.primary.truncate(MAX_LENGTH);
+    keys.alternate.truncate(MAX_LENGTH);
+    (keys.primary, keys.alternate)
+}
+
+
+/// Double metaphone rules for C. Returns how many letters were coded.
+fn double_metaphone_c(word: &Word, current: isize, keys: &mut Keys) -> isize {
+    // Germanic, as in "bacher" and "macher".
+    if current > 1 && !word.is_vowel(current - 2)
+        && word.string_at(current - 1, 3, &["ACH"])
+        && word.at(current + 2) != 'I'
+        && (word.at(current + 2) != 'E' || word.string_at(current - 2, 6, &["BACHER", "MACHER"])) {
+        keys.add("K", "K");
+        return 2;
+    }
+    if current == 0 && word.string_at(current, 6, &["CAESAR"]) {
+        keys.add("S", "S");
+        return 2;
+    }
+    // Italian, as in "chianti".
+    if word.string_at(current, 4, &["CHIA"]) {
+        keys.add("K", "K");
+        return 2;
+    }
+    if word.string_at(current, 2, &["CH"]) {
+        // "michael"
+        if current > 0 && word.string_at(current, 4, &["CHAE"]) {
+            keys.add("K", "X");
+            return 2;
+        }
+        // Greek roots, as in "chemistry" and "chorus".
+        if current == 0
+            && (word.string_at(current + 1, 5, &["HARAC", "HARIS"])
+                || word.string_at(current + 1, 3, &["HOR", "HYM", "HIA", "HEM"]))
+            && !word.string_at(0, 5, &["CHORE"]) {
+            keys.add("K", "K");
+            return 2;
+        }
+        // Germanic, greek or otherwise "ch" for "kh", as in "architect" and
+        // "wachtler" but not "arch" or "tichner".
+        if word.string_at(0, 4, &["VAN ", "VON "]) || word.string_at(0, 3, &["SCH"])
+            || word.string_at(current - 2, 6, &["ORCHES", "ARCHIT", "ORCHID"])
+            || word.string_at(current + 2, 1, &["T", "S"])
+            || ((word.string_at(current - 1, 1, &["A", "O", "U", "E"]) || current == 0)
+                && word.string_at(current + 2, 1, &["L", "R", "N", "M", "B", "H", "F", "V", "W", " "])) {
+            keys.add("K", "K");
+        } else if current > 0 {
+            // "McHugh"
+            if word.string_at(0, 2, &["MC"]) {
+                keys.add("K", "K");
+            } else {
+                keys.add("X", "K");
+            }
+        } else {
+            keys.add("X", "X");
+        }
+        return 2;
+    }
+    // "czerny"
+    if word.string_at(current, 2, &["CZ"]) && !word.string_at(current - 2, 4, &["WICZ"]) {
+        keys.add("S", "X");
+        return 2;
+    }
+    // "focaccia"
+    if word.string_at(current + 1, 3, &["CIA"]) {
+        keys.add("X", "X");
+        return 3;
+    }
+    // A double C, but not as in "McClellan".
+    if word.string_at(current, 2, &["CC"]) && !(current == 1 && word.at(0) == 'M') {
+        // "bellocchio" but not "bacchus".
+        if word.string_at(current + 2, 1, &["I", "E", "H"]) && !word.string_at(current + 2, 2, &["HU"]) {
+            // "accident", "accede" and "succeed", against italian "bacci".
+            if (current == 1 && word.at(current - 1) == 'A') || word.string_at(current - 1, 5, &["UCCEE", "UCCES"]) {
+                keys.add("KS", "KS");
+            } else {
+                keys.add("X", "X");
+            }
+            return 3;
+        }
+        keys.add("K", "K");
+        return 2;
+    }
+    if word.string_at(current, 2, &["CK", "CG", "CQ"]) {
+        keys.add("K", "K");
+        return 2;
+    }
+    if word.string_at(current, 2, &["CI", "CE", "CY"]) {
+        // Italian against english.
+        if word.string_at(current, 3, &["CIO", "CIE", "CIA"]) {
+            keys.add("S", "X");
+        } else {
+            keys.add("S", "S");
+        }
+        return 2;
+    }
+
+    keys.add("K", "K");
+    // "mac caffrey" and "mac gregor"
+    if word.string_at(current + 1, 2, &[" C", " Q", " G"]) {
+        3
+    } else if word.string_at(current + 1, 1, &["C", "K", "Q"]) && !word.string_at(current + 1, 2, &["CE", "CI"]) {
+        2
+    } else {
+        1
+    }
+}
+
+
+/// Double metaphone rules for G. Returns how many letters were coded.
+fn double_metaphone_g(word: &Word, current: isize, keys: &mut Keys) -> isize {
+    if word.at(current + 1) == 'H' {
+        if current > 0 && !word.is_vowel(current - 1) {
+            keys.add("K", "K");
+            return 2;
+        }
+        // "ghislane" and "ghiradelli"
+        if current == 0 {
+            if word.at(current + 2) == 'I' {
+                keys.add("J", "J");
+            } else {
+                keys.add("K", "K");
+            }
+            return 2;
+        }
+        // Parker's rule, as in "hugh", "bough" and "broughton".
+        if (current > 1 && word.string_at(current - 2, 1, &["B", "H", "D"]))
+            || (current > 2 && word.string_at(current - 3, 1, &["B", "H", "D"]))
+            || (current > 3 && word.string_at(current - 4, 1, &["B", "H"])) {
+            return 2;
+        }
+        // "laugh", "McLaughlin", "cough" and "rough".
+        if current > 2 && word.at(current - 1) == 'U' && word.string_at(current - 3, 1, &["C", "G", "L", "R", "T"]) {
+            keys.add("F", "F");
+        } else if current > 0 && word.at(current - 1) != 'I' {
+            keys.add("K", "K");
+        }
+        return 2;
+    }
+    if word.at(current + 1) == 'N' {
+        if current == 1 && word.is_vowel(0) && !word.slavo_germanic {
+            keys.add("KN", "N");
+        } else if !word.string_at(current + 2, 2, &["EY"]) && word.at(current + 1) != 'Y' && !word.slavo_germanic {
+            // Not as in "cagney".
+            keys.add("N", "KN");
+        } else {
+            keys.add("KN", "KN");
+        }
+        return 2;
+    }
+    // "tagliaro"
+    if word.string_at(current + 1, 2, &["LI"]) && !word.slavo_germanic {
+        keys.add("KL", "L");
+        return 2;
+    }
+    // "-ges-", "-gep-", "-gel-" and "-gie-" at the start.
+    if current == 0
+        && (word.at(current + 1) == 'Y'
+            || word.string_at(current + 1, 2, &["ES", "EP", "EB", "EL", "EY", "IB", "IL", "IN", "IE", "EI", "ER"])) {
+        keys.add("K", "J");
+        return 2;
+    }
+    // "-ger-" and "-gy-"
+    if (word.string_at(current + 1, 2, &["ER"]) || word.at(current + 1) == 'Y')
+        && !word.string_at(0, 6, &["DANGER", "RANGER", "MANGER"])
+        && !word.string_at(current - 1, 1, &["E", "I"])
+        && !word.string_at(current - 1, 3, &["RGY", "OGY"]) {
+        keys.add("K", "J");
+        return 2;
+    }
+    // Italian, as in "biaggi".
+    if word.string_at(current + 1, 1, &["E", "I", "Y"]) || word.string_at(current - 1, 4, &["AGGI", "OGGI"]) {
+        if word.string_at(0, 4, &["VAN ", "VON "]) || word.string_at(0, 3, &["SCH"]) || word.string_at(current + 1, 2, &["ET"]) {
+            keys.add("K", "K");
+        } else if word.string_at(current + 1, 4, &["IER "]) {
+            // Always soft with a french ending.
+            keys.add("J", "J");
+        } else {
+            keys.add("J", "K");
+        }
+        return 2;
+    }
+
+    keys.add("K", "K");
+    if word.at(current + 1) == 'G' { 2 } else { 1 }
+}
+
+
+/// Double metaphone rules for J. Returns how many letters were coded.
+fn double_metaphone_j(word: &Word, current: isize, last: isize, keys: &mut Keys) -> isize {
+    // Spanish, as in "jose" and "san jacinto".
+    if word.string_at(current, 4, &["JOSE"]) || word.string_at(0, 4, &["SAN "]) {
+        if (current == 0 && word.at(current + 4) == ' ') || word.string_at(0, 4, &["SAN "]) {
+            keys.add("H", "H");
+        } else {
+            keys.add("J", "H");
+        }
+        return 1;
+    }
+
+    if current == 0 {
+        // "Yankelovich" against "Jankelowicz".
+        keys.add("J", "A");
+    } else if word.is_vowel(current - 1) && !word.slavo_germanic && matches!(word.at(current + 1), 'A' | 'O') {
+        // Spanish, as in "bajador".
+        keys.add("J", "H");
+    } else if current == last {
+        keys.add("J", "");
+    } else if !word.string_at(current + 1, 1, &["L", "T", "K", "S", "N", "M", "B", "Z"])
+        && !word.string_at(current - 1, 1, &["S", "K", "L"]) {
+        keys.add("J", "J");
+    }
+    if word.at(current + 1) == 'J' { 2 } else { 1 }
+}
+
+
+/// Double metaphone rules for S. Returns how many letters were coded.
+fn double_metaphone_s(word: &Word, current: isize, last: isize, keys: &mut Keys) -> isize {
+    // Silent in "island", "isle" and "carlysle".
+    if word.string_at(current - 1, 3, &["ISL", "YSL"]) {
+        return 1;
+    }
+    if current == 0 && word.string_at(current, 5, &["SUGAR"]) {
+        keys.add("X", "S");
+        return 1;
+    }
+    if word.string_at(current, 2, &["SH"]) {
+        // Germanic
+        if word.string_at(current + 1, 4, &["HEIM", "HOEK", "HOLM", "HOLZ"]) {
+            keys.add("S", "S");
+        } else {
+            keys.add("X", "X");
+        }
+        return 2;
+    }
+    // Italian and armenian.
+    if word.string_at(current, 3, &["SIO", "SIA"]) || word.string_at(current, 4, &["SIAN"]) {
+        if word.slavo_germanic {
+            keys.add("S", "S");
+        } else {
+            keys.add("S", "X");
+        }
+        return 3;
+    }
+    // German and anglicisations, so "smith" matches "schmidt" and "snider"
+    // matches "schneider". Also slavic "-sz-".
+    if (current == 0 && word.string_at(current + 1, 1, &["M", "N", "L", "W"])) || word.string_at(current + 1, 1, &["Z"]) {
+        keys.add("S", "X");
+        return if word.string_at(current + 1, 1, &["Z"]) { 2 } else { 1 };
+    }
+    if word.string_at(current, 2, &["SC"]) {
+        // Schlesinger's rule.
+        if word.at(current + 2) == 'H' {
+            // Dutch, as in "school" and "schooner", or "schermerhorn".
+            if word.string_at(current + 3, 2, &["OO", "ER", "EN", "UY", "ED", "EM"]) {
+                if word.string_at(current + 3, 2, &["ER", "EN"]) {
+                    keys.add("X", "SK");
+                } else {
+                    keys.add("SK", "SK");
+                }
+            } else if current == 0 && !word.is_vowel(3) && word.at(3) != 'W' {
+                keys.add("X", "S");
+            } else {
+                keys.add("X", "X");
+            }
+            return 3;
+        }
+        if word.string_at(current + 2, 1, &["I", "E", "Y"]) {
+            keys.add("S", "S");
+        } else {
+            keys.add("SK", "SK");
+        }
+        return 3;
+    }
+
+    // French, as in "resnais" and "artois".
+    if current == last && word.string_at(current - 2, 2, &["AI", "OI"]) {
+        keys.add("", "S");
+    } else {
+        keys.add("S", "S");
+    }
+    if word.string_at(current + 1, 1, &["S", "Z"]) { 2 } else { 1 }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair(primary: &str, alternate: &str) -> (String, String) {
+        (primary.to_string(), alternate.to_string())
+    }
+
+    #[test]
+    fn soundex_keys() {
+        assert_eq!(soundex("Robert"), "R163");
+        assert_eq!(soundex("Rupert"), "R163");
+        assert_eq!(soundex("Ashcraft"), "A261");
+        assert_eq!(soundex("Tymczak"), "T522");
+        assert_eq!(soundex("Pfister"), "P236");
+        assert_eq!(soundex("Lee"), "L000");
+        assert_eq!(soundex("  "), "");
+
+        assert_eq!(refined_soundex("Braz"), "B1905");
+        assert_eq!(refined_soundex("testing"), "T6036084");
+        assert_eq!(refined_soundex("TESTING"), refined_soundex("testing"));
+    }
+
+    #[test]
+    fn metaphone_keys() {
+        assert_eq!(metaphone("Thompson"), "0MPSN");
+        assert_eq!(metaphone("Knight"), "NT");
+        assert_eq!(metaphone("Dumb"), "TM");
+        assert_eq!(metaphone("Xavier"), "SFR");
+        assert_eq!(metaphone("Phillip"), "FLP");
+        assert_eq!(metaphone("Schmidt"), "SKMTT");
+        assert_eq!(metaphone("Edge"), "EJ");
+    }
+
+    #[test]
+    fn nysiis_keys() {
+        assert_eq!(nysiis("Brown"), "BRAN");
+        assert_eq!(nysiis("Knight"), "NAGT");
+        assert_eq!(nysiis("Macintosh"), "MCANT");
+        assert_eq!(nysiis("Phillipson"), "FALAPSAN");
+        assert_eq!(nysiis(""), "");
+    }
+
+    #[test]
+    fn double_metaphone_keys() {
+        assert_eq!(double_metaphone("Thompson"), pair("TMPS", "TMPS"));
+        assert_eq!(double_metaphone("Schmidt"), pair("XMT", "SMT"));
+        assert_eq!(double_metaphone("Smith"), pair("SM0", "XMT"));
+        assert_eq!(double_metaphone("Jose"), pair("HS", "HS"));
+        assert_eq!(double_metaphone("Xavier"), pair("SF", "SFR"));
+        assert_eq!(double_metaphone("Catherine"), pair("K0RN", "KTRN"));
+        assert_eq!(double_metaphone("Knight"), pair("NT", "NT"));
+        assert_eq!(double_metaphone("Michael"), pair("MKL", "MXL"));
+        assert_eq!(double_metaphone(""), pair("", ""));
+    }
+
+    #[test]
+    fn matching() {
+        assert!(PhoneticAlgorithm::Soundex.is_match("Robert", "Rupert"));
+        assert!(!PhoneticAlgorithm::Soundex.is_match("Robert", "Rubin"));
+        assert!(PhoneticAlgorithm::DoubleMetaphone.is_match("Smith", "Schmidt"));
+        assert!(!PhoneticAlgorithm::Nysiis.is_match("", ""));
+        assert_eq!("double_metaphone".parse::<PhoneticAlgorithm>(), Ok(PhoneticAlgorithm::DoubleMetaphone));
+        assert!("caverphone".parse::<PhoneticAlgorithm>().is_err());
+    }
+}
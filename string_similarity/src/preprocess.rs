@@ -0,0 +1,126 @@
+use std::borrow::Cow;
+use std::str::FromStr;
+
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+
+
+/// Normalization applied to every string before it is scored. Steps run in
+/// the order of the fields, and a preprocessor with no steps leaves strings
+/// untouched without copying them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Preprocessor {
+    /// Unicode full case folding, so "Straße" and "STRASSE" are equal.
+    pub case_fold: bool,
+    /// NFKD decomposition with combining marks removed, so "café" becomes
+    /// "cafe" and "ﬁ" becomes "fi".
+    pub strip_accents: bool,
+    /// Replaces every character that is neither alphanumeric nor whitespace
+    /// with a space, as fuzzywuzzy does.
+    pub strip_punctuation: bool,
+    /// Collapses runs of whitespace into a single space and trims both ends.
+    pub normalize_whitespace: bool,
+}
+
+impl Preprocessor {
+    /// Every step, roughly fuzzywuzzy's `full_process`.
+    pub fn all() -> Preprocessor {
+        Preprocessor {
+            case_fold: true,
+            strip_accents: true,
+            strip_punctuation: true,
+            normalize_whitespace: true,
+        }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == Preprocessor::default()
+    }
+
+    /// Adds the steps of `other` to this preprocessor.
+    pub fn union(&self, other: &Preprocessor) -> Preprocessor {
+        Preprocessor {
+            case_fold: self.case_fold || other.case_fold,
+            strip_accents: self.strip_accents || other.strip_accents,
+            strip_punctuation: self.strip_punctuation || other.strip_punctuation,
+            normalize_whitespace: self.normalize_whitespace || other.normalize_whitespace,
+        }
+    }
+
+    pub fn apply<'a>(&self, string: &'a str) -> Cow<'a, str> {
+        if self.is_identity() {
+            return Cow::Borrowed(string);
+        }
+
+        let mut processed = string.to_string();
+        if self.case_fold {
+            processed = caseless::default_case_fold_str(&processed);
+        }
+        if self.strip_accents {
+            // Recomposing afterwards keeps e.g. Hangul syllables, which NFKD
+            // splits into letters rather than combining marks, intact.
+            processed = processed.nfkd().filter(|c| !is_combining_mark(*c)).nfc().collect();
+        }
+        if self.strip_punctuation {
+            processed = processed
+                .chars()
+                .map(|c| if c.is_alphanumeric() || c.is_whitespace() { c } else { ' ' })
+                .collect();
+        }
+        if self.normalize_whitespace {
+            processed = processed.split_whitespace().collect::<Vec<&str>>().join(" ");
+        }
+        Cow::Owned(processed)
+    }
+}
+
+impl FromStr for Preprocessor {
+    type Err = String;
+
+    /// A single step by name, or `"default"` for all of them.
+    fn from_str(step: &str) -> Result<Preprocessor, String> {
+        let none = Preprocessor::default();
+        match step {
+            "default" => Ok(Preprocessor::all()),
+            "case_fold" => Ok(Preprocessor { case_fold: true, ..none }),
+            "strip_accents" => Ok(Preprocessor { strip_accents: true, ..none }),
+            "strip_punctuation" => Ok(Preprocessor { strip_punctuation: true, ..none }),
+            "normalize_whitespace" => Ok(Preprocessor { normalize_whitespace: true, ..none }),
+            _ => Err(format!(
+                "processor steps must be 'default', 'case_fold', 'strip_accents', 'strip_punctuation' \
+                 or 'normalize_whitespace', got '{}'",
+                step,
+            )),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(name: &str) -> Preprocessor {
+        name.parse().unwrap()
+    }
+
+    #[test]
+    fn single_steps() {
+        assert_eq!(step("case_fold").apply("Straße ΣΊΣΥΦΟΣ"), "strasse σίσυφοσ");
+        assert_eq!(step("strip_accents").apply("Crème Brûlée ﬁ 한국"), "Creme Brulee fi 한국");
+        assert_eq!(step("strip_punctuation").apply("O'Neil, Jr."), "O Neil  Jr ");
+        assert_eq!(step("normalize_whitespace").apply("  a \t b\n"), "a b");
+        assert!("lowercase".parse::<Preprocessor>().is_err());
+    }
+
+    #[test]
+    fn pipeline() {
+        let processor = Preprocessor::all();
+        assert_eq!(processor.apply("  Café-Crème, S.A. "), "cafe creme s a");
+        assert_eq!(processor.apply("ÉCOLE"), processor.apply("ecole"));
+
+        let identity = Preprocessor::default();
+        assert!(matches!(identity.apply("Café"), Cow::Borrowed("Café")));
+        assert_eq!(step("case_fold").union(&step("normalize_whitespace")).apply(" A  B "), "a b");
+    }
+}
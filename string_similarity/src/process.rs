@@ -0,0 +1,642 @@
+use std::cmp::{ self, Ordering, Reverse };
+use std::collections::BinaryHeap;
+use std::hash::Hash;
+
+use rayon::prelude::*;
+
+use crate::costs::CostTable;
+use crate::damerau::{ get_damerau_levenshtein_distance, get_osa_distance };
+use crate::lcs::{ get_indel_distance, get_lcs_similarity };
+use crate::fuzz::Fuzz;
+use crate::levenshtein::get_cost_table_levenshtein_distance;
+use crate::preprocess::Preprocessor;
+use crate::qgram::QGram;
+use crate::token::TokenSimilarity;
+use crate::unit::{ Symbol, Unit, with_unit_batches };
+use crate::{
+    get_jaccard_similarity,
+    get_jaro_winkler_similarity_with_cutoff,
+    get_legacy_jaro_winkler_similarity,
+    get_legacy_weighted_levenshtein_distance,
+    get_weighted_levenshtein_distance_with_cutoff,
+};
+
+
+/// An edit distance together with its costs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditDistance {
+    WeightedLevenshtein {
+        deletion_cost: usize,
+        insertion_cost: usize,
+        substitution_cost: usize,
+    },
+    /// Weighted levenshtein returning 0 if either string is empty and 1 for
+    /// identical strings, as the original implementation did.
+    LegacyWeightedLevenshtein {
+        deletion_cost: usize,
+        insertion_cost: usize,
+        substitution_cost: usize,
+    },
+    Osa {
+        deletion_cost: usize,
+        insertion_cost: usize,
+        substitution_cost: usize,
+        transposition_cost: usize,
+    },
+    DamerauLevenshtein {
+        deletion_cost: usize,
+        insertion_cost: usize,
+        substitution_cost: usize,
+        transposition_cost: usize,
+    },
+    Indel,
+}
+
+impl EditDistance {
+    /// The distance between `str1` and `str2`, or `max_distance + 1` if it
+    /// is known to exceed `max_distance`.
+    pub fn distance<T: Eq + Hash>(&self, str1: &[T], str2: &[T], max_distance: usize) -> usize {
+        match *self {
+            EditDistance::WeightedLevenshtein { deletion_cost, insertion_cost, substitution_cost } => {
+                get_weighted_levenshtein_distance_with_cutoff(
+                    str1,
+                    str2,
+                    deletion_cost,
+                    insertion_cost,
+                    substitution_cost,
+                    max_distance,
+                    )
+            }
+            EditDistance::LegacyWeightedLevenshtein { deletion_cost, insertion_cost, substitution_cost } => {
+                get_legacy_weighted_levenshtein_distance(
+                    str1,
+                    str2,
+                    deletion_cost,
+                    insertion_cost,
+                    substitution_cost,
+                    max_distance,
+                    )
+            }
+            EditDistance::Osa { deletion_cost, insertion_cost, substitution_cost, transposition_cost } => {
+                let distance = get_osa_distance(
+                    str1,
+                    str2,
+                    deletion_cost,
+                    insertion_cost,
+                    substitution_cost,
+                    transposition_cost,
+                    );
+                cmp::min(distance, max_distance.saturating_add(1))
+            }
+            EditDistance::DamerauLevenshtein { deletion_cost, insertion_cost, substitution_cost, transposition_cost } => {
+                let distance = get_damerau_levenshtein_distance(
+                    str1,
+                    str2,
+                    deletion_cost,
+                    insertion_cost,
+                    substitution_cost,
+                    transposition_cost,
+                    );
+                cmp::min(distance, max_distance.saturating_add(1))
+            }
+            EditDistance::Indel => {
+                cmp::min(get_indel_distance(str1, str2), max_distance.saturating_add(1))
+            }
+        }
+    }
+
+    /// The largest distance two strings of these lengths can have, which is
+    /// either deleting and inserting everything or substituting the shorter
+    /// string and inserting or deleting the rest. Transpositions can only
+    /// lower the distance, so they don't change the maximum. Saturates at
+    /// `usize::MAX` like the distances themselves.
+    pub fn max_distance(&self, len1: usize, len2: usize) -> usize {
+        let (deletion_cost, insertion_cost, substitution_cost) = match *self {
+            EditDistance::WeightedLevenshtein { deletion_cost, insertion_cost, substitution_cost }
+            | EditDistance::LegacyWeightedLevenshtein { deletion_cost, insertion_cost, substitution_cost }
+            | EditDistance::Osa { deletion_cost, insertion_cost, substitution_cost, .. }
+            | EditDistance::DamerauLevenshtein { deletion_cost, insertion_cost, substitution_cost, .. } => {
+                (deletion_cost, insertion_cost, substitution_cost)
+            }
+            EditDistance::Indel => return len1 + len2,
+        };
+
+        let replace_all = len1.saturating_mul(deletion_cost).saturating_add(len2.saturating_mul(insertion_cost));
+        let substitute_shorter = if len1 >= len2 {
+            len2.saturating_mul(substitution_cost).saturating_add((len1 - len2).saturating_mul(deletion_cost))
+        } else {
+            len1.saturating_mul(substitution_cost).saturating_add((len2 - len1).saturating_mul(insertion_cost))
+        };
+        cmp::min(replace_all, substitute_shorter)
+    }
+
+    /// The distance divided by `max_distance`, in `[0, 1]`. Returns 1 as soon
+    /// as it is known to exceed `score_cutoff`.
+    pub fn normalized_distance<T: Eq + Hash>(&self, str1: &[T], str2: &[T], score_cutoff: f32) -> f32 {
+        let max_distance = self.max_distance(str1.len(), str2.len());
+        if max_distance == 0 {
+            return 0.0;
+        }
+        if str1 == str2 {
+            return 0.0;
+        }
+        if str1.is_empty() || str2.is_empty() {
+            return 1.0;
+        }
+
+        let cutoff_distance = (score_cutoff.clamp(0.0, 1.0) * max_distance as f32).ceil() as usize;
+        let distance = self.distance(str1, str2, cutoff_distance);
+        if distance > cutoff_distance {
+            return 1.0;
+        }
+        distance as f32 / max_distance as f32
+    }
+
+    /// `1 - normalized_distance`, in `[0, 1]`. Returns 0 as soon as it is
+    /// known to be below `score_cutoff`.
+    pub fn normalized_similarity<T: Eq + Hash>(&self, str1: &[T], str2: &[T], score_cutoff: f32) -> f32 {
+        let similarity = 1.0 - self.normalized_distance(str1, str2, 1.0 - score_cutoff);
+        if similarity < score_cutoff {
+            return 0.0;
+        }
+        similarity
+    }
+}
+
+
+/// A metric together with its parameters, so that many strings can be scored
+/// the same way without threading every parameter through the batch helpers.
+#[derive(Debug, Clone)]
+pub enum Scorer {
+    JaroWinkler {
+        max_prefix_length: usize,
+        scaling_factor: f32,
+    },
+    /// Jaro winkler scoring two empty strings 0, as the original
+    /// implementation did.
+    LegacyJaroWinkler {
+        max_prefix_length: usize,
+        scaling_factor: f32,
+    },
+    /// The original Jaccard over sets of single units, which divides by
+    /// `len1 + len2 - intersection` rather than the size of the union.
+    LegacyJaccard,
+    QGram(QGram),
+    LcsSimilarity,
+    Distance(EditDistance),
+    /// Weighted levenshtein with per unit or fractional costs, which gives
+    /// fractional distances.
+    CostDistance(CostTable),
+    NormalizedDistance(EditDistance),
+    NormalizedSimilarity(EditDistance),
+    Fuzz(Fuzz),
+    Token(TokenSimilarity),
+}
+
+impl Scorer {
+    /// Distances are better when lower, similarities when higher.
+    pub fn is_distance(&self) -> bool {
+        matches!(self, Scorer::Distance(_) | Scorer::CostDistance(_) | Scorer::NormalizedDistance(_))
+    }
+
+    /// Raw edit distances are whole numbers, every other score is a float.
+    pub fn is_integer(&self) -> bool {
+        matches!(self, Scorer::Distance(_))
+    }
+
+    /// The processing applied when none is asked for, which is none at all
+    /// except for the fuzz ratios that process by default.
+    pub fn default_processor(&self) -> Preprocessor {
+        match self {
+            Scorer::Fuzz(fuzz) => fuzz.default_processor(),
+            _ => Preprocessor::default(),
+        }
+    }
+
+    /// Checks that the scorer can compare strings split into `unit`s.
+    pub fn check_unit(&self, unit: Unit) -> Result<(), String> {
+        match self {
+            Scorer::CostDistance(costs) => costs.check_unit(unit),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether `score` is good enough to be kept under `score_cutoff`.
+    pub fn passes(&self, score: f32, score_cutoff: Option<f32>) -> bool {
+        match score_cutoff {
+            None => true,
+            Some(cutoff) if self.is_distance() => score <= cutoff,
+            Some(cutoff) => score >= cutoff,
+        }
+    }
+
+    /// The value written for pairs that don't pass `score_cutoff`: 0 for
+    /// similarities, 1 for normalized distances and `score_cutoff + 1` for
+    /// distances, rounded down first for whole number distances.
+    pub fn rejected(&self, score_cutoff: f32) -> f32 {
+        match self {
+            Scorer::Distance(_) => score_cutoff.floor() + 1.0,
+            Scorer::CostDistance(_) => score_cutoff + 1.0,
+            Scorer::NormalizedDistance(_) => 1.0,
+            _ => 0.0,
+        }
+    }
+
+    /// The score of a pair with a missing string: 0, except for normalized
+    /// distances, which are 1 as for strings with nothing in common.
+    pub fn none_score(&self) -> f32 {
+        match self {
+            Scorer::NormalizedDistance(_) => 1.0,
+            _ => 0.0,
+        }
+    }
+
+    /// Scores a pair, exiting early where the kernel supports it once the
+    /// pair is known to miss `score_cutoff`. Distances are returned as whole
+    /// numbers.
+    pub fn score<T: Symbol>(&self, str1: &[T], str2: &[T], score_cutoff: Option<f32>) -> f32 {
+        let score = match *self {
+            Scorer::JaroWinkler { max_prefix_length, scaling_factor } => {
+                get_jaro_winkler_similarity_with_cutoff(
+                    str1,
+                    str2,
+                    max_prefix_length,
+                    scaling_factor,
+                    score_cutoff.unwrap_or(0.0),
+                    )
+            }
+            Scorer::LegacyJaroWinkler { max_prefix_length, scaling_factor } => {
+                get_legacy_jaro_winkler_similarity(
+                    str1,
+                    str2,
+                    max_prefix_length,
+                    scaling_factor,
+                    score_cutoff.unwrap_or(0.0),
+                    )
+            }
+            Scorer::LegacyJaccard => get_jaccard_similarity(str1, str2),
+            Scorer::QGram(qgram) => qgram.similarity(str1, str2),
+            Scorer::LcsSimilarity => get_lcs_similarity(str1, str2),
+            Scorer::Distance(distance) => {
+                let max_distance = score_cutoff.map_or(usize::MAX, |cutoff| cutoff.max(0.0) as usize);
+                distance.distance(str1, str2, max_distance) as f32
+            }
+            Scorer::CostDistance(ref costs) => {
+                let max_distance = score_cutoff.map_or(f64::INFINITY, f64::from);
+                get_cost_table_levenshtein_distance(str1, str2, costs, max_distance) as f32
+            }
+            Scorer::NormalizedDistance(distance) => {
+                distance.normalized_distance(str1, str2, score_cutoff.unwrap_or(1.0))
+            }
+            Scorer::NormalizedSimilarity(distance) => {
+                distance.normalized_similarity(str1, str2, score_cutoff.unwrap_or(0.0))
+            }
+            Scorer::Fuzz(fuzz) => fuzz.score(str1, str2),
+            Scorer::Token(ref token) => token.similarity(str1, str2),
+        };
+
+        match score_cutoff {
+            Some(cutoff) if !self.passes(score, score_cutoff) => self.rejected(cutoff),
+            _ => score,
+        }
+    }
+}
+
+
+/// Scores every query against every choice, returning a row major
+/// `queries.len() x choices.len()` matrix. Each string is split into `unit`s
+/// once and the rows are computed in parallel.
+pub fn cdist<S: AsRef<str>>(
+    queries: &[S],
+    choices: &[S],
+    scorer: &Scorer,
+    unit: Unit,
+    score_cutoff: Option<f32>,
+    ) -> Vec<f32> {
+    let mut matrix = vec![0.0; queries.len() * choices.len()];
+    cdist_into(queries, choices, scorer, unit, score_cutoff, &mut matrix, |_, _, score| score);
+    matrix
+}
+
+/// `cdist` writing `cell(query, choice, score)` for every pair straight into
+/// `matrix`, which must hold `queries.len() x choices.len()` cells. This
+/// lets callers fill a buffer of their own element type without building
+/// the `f32` matrix first.
+pub fn cdist_into<S: AsRef<str>, C: Send>(
+    queries: &[S],
+    choices: &[S],
+    scorer: &Scorer,
+    unit: Unit,
+    score_cutoff: Option<f32>,
+    matrix: &mut [C],
+    cell: impl Fn(usize, usize, f32) -> C + Sync,
+    ) {
+    assert_eq!(matrix.len(), queries.len() * choices.len(), "the matrix must have a cell for every pair");
+    with_unit_batches!(unit, [queries => queries, choices => choices], {
+        cdist_units(&queries, &choices, scorer, score_cutoff, matrix, &cell)
+    })
+}
+
+fn cdist_units<T: Symbol, Q: AsRef<[T]> + Sync, C: Send>(
+    queries: &[Q],
+    choices: &[Q],
+    scorer: &Scorer,
+    score_cutoff: Option<f32>,
+    matrix: &mut [C],
+    cell: &(impl Fn(usize, usize, f32) -> C + Sync),
+    ) {
+    if choices.is_empty() {
+        return;
+    }
+
+    matrix.par_chunks_mut(choices.len()).zip(queries.par_iter()).enumerate().for_each(|(query_index, (row, query))| {
+        for (choice_index, (value, choice)) in row.iter_mut().zip(choices.iter()).enumerate() {
+            *value = cell(query_index, choice_index, scorer.score(query.as_ref(), choice.as_ref(), score_cutoff));
+        }
+    });
+}
+
+
+
+/// A scored choice ordered so that better matches compare greater. Ties are
+/// broken in favour of the choice that comes first.
+#[derive(Debug, Clone, Copy)]
+struct Ranked {
+    key: f32,
+    index: usize,
+}
+
+impl Ranked {
+    fn new(scorer: &Scorer, score: f32, index: usize) -> Ranked {
+        let key = if scorer.is_distance() { -score } else { score };
+        Ranked { key, index }
+    }
+}
+
+impl PartialEq for Ranked {
+    fn eq(&self, other: &Ranked) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Ranked {}
+
+impl PartialOrd for Ranked {
+    fn partial_cmp(&self, other: &Ranked) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Ranked {
+    fn cmp(&self, other: &Ranked) -> Ordering {
+        self.key.total_cmp(&other.key).then_with(|| other.index.cmp(&self.index))
+    }
+}
+
+
+/// Keeps the `limit` best candidates seen so far, with the worst on top.
+fn push_bounded(heap: &mut BinaryHeap<Reverse<Ranked>>, candidate: Ranked, limit: usize) {
+    if heap.len() < limit {
+        heap.push(Reverse(candidate));
+    } else if let Some(Reverse(worst)) = heap.peek() {
+        if candidate > *worst {
+            heap.pop();
+            heap.push(Reverse(candidate));
+        }
+    }
+}
+
+
+/// Finds the `limit` choices that best match `query`, returned as
+/// `(index, score)` pairs sorted from best to worst. Choices that don't pass
+/// `score_cutoff` are dropped and a `limit` of `None` keeps every choice.
+/// Choices are scanned in parallel, each thread keeping its own top-k heap.
+pub fn extract<S: AsRef<str> + Sync>(
+    query: &str,
+    choices: &[S],
+    scorer: &Scorer,
+    unit: Unit,
+    limit: Option<usize>,
+    score_cutoff: Option<f32>,
+    ) -> Vec<(usize, f32)> {
+    let limit = limit.unwrap_or(choices.len());
+    if limit == 0 {
+        return vec![];
+    }
+
+    let query = [query];
+    let best = with_unit_batches!(unit, [query => query, choices => choices], {
+        best_choices(&query[0], &choices, scorer, limit, score_cutoff)
+    });
+
+    sorted_matches(best, scorer)
+}
+
+
+/// `extract` for many queries at once, returning the matches of every query
+/// in order. The choices are split into `unit`s once and the queries are
+/// scored in parallel, each scanning the choices on its own thread.
+pub fn extract_many<Q: AsRef<str> + Sync, S: AsRef<str> + Sync>(
+    queries: &[Q],
+    choices: &[S],
+    scorer: &Scorer,
+    unit: Unit,
+    limit: Option<usize>,
+    score_cutoff: Option<f32>,
+    ) -> Vec<Vec<(usize, f32)>> {
+    let limit = limit.unwrap_or(choices.len());
+    if limit == 0 {
+        return vec![vec![]; queries.len()];
+    }
+
+    with_unit_batches!(unit, [queries => queries, choices => choices], {
+        queries.par_iter().map(|query| {
+            let mut best = BinaryHeap::new();
+            for (index, choice) in choices.iter().enumerate() {
+                let score = scorer.score(query.as_ref(), choice.as_ref(), score_cutoff);
+                if scorer.passes(score, score_cutoff) {
+                    push_bounded(&mut best, Ranked::new(scorer, score, index), limit);
+                }
+            }
+            sorted_matches(best, scorer)
+        }).collect()
+    })
+}
+
+
+/// The candidates of a top-k heap as `(index, score)` pairs, best first.
+fn sorted_matches(best: BinaryHeap<Reverse<Ranked>>, scorer: &Scorer) -> Vec<(usize, f32)> {
+    best.into_sorted_vec()
+        .into_iter()
+        .map(|Reverse(ranked)| (ranked.index, if scorer.is_distance() { -ranked.key } else { ranked.key }))
+        .collect()
+}
+
+
+fn best_choices<T: Symbol, C: AsRef<[T]> + Sync>(
+    query: &C,
+    choices: &[C],
+    scorer: &Scorer,
+    limit: usize,
+    score_cutoff: Option<f32>,
+    ) -> BinaryHeap<Reverse<Ranked>> {
+    choices
+        .par_iter()
+        .enumerate()
+        .fold(BinaryHeap::new, |mut heap, (index, choice)| {
+            let score = scorer.score(query.as_ref(), choice.as_ref(), score_cutoff);
+            if scorer.passes(score, score_cutoff) {
+                push_bounded(&mut heap, Ranked::new(scorer, score, index), limit);
+            }
+            heap
+        })
+        .reduce(BinaryHeap::new, |mut heap, other| {
+            for Reverse(candidate) in other {
+                push_bounded(&mut heap, candidate, limit);
+            }
+            heap
+        })
+}
+
+
+/// The single best match for `query`, if any choice passes `score_cutoff`.
+pub fn extract_one<S: AsRef<str> + Sync>(
+    query: &str,
+    choices: &[S],
+    scorer: &Scorer,
+    unit: Unit,
+    score_cutoff: Option<f32>,
+    ) -> Option<(usize, f32)> {
+    extract(query, choices, scorer, unit, Some(1), score_cutoff).into_iter().next()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cdist_matches_pairwise_scores() {
+        let queries = ["martha", "dwayne", "dixon"];
+        let choices = ["marhta", "duane", "dicksonx", "martha"];
+        let scorer = Scorer::JaroWinkler { max_prefix_length: 4, scaling_factor: 0.1 };
+
+        let matrix = cdist(&queries, &choices, &scorer, Unit::Byte, None);
+        assert_eq!(matrix.len(), 12);
+
+        for (i, query) in queries.iter().enumerate() {
+            for (j, choice) in choices.iter().enumerate() {
+                let expected = crate::get_jaro_winkler_similarity(query.as_bytes(), choice.as_bytes(), 4, 0.1);
+                assert_eq!(matrix[i * choices.len() + j], expected);
+            }
+        }
+    }
+
+    #[test]
+    fn cdist_score_cutoff() {
+        let queries = ["kitten", "sitting"];
+        let choices = ["sittin", "kitchen", "mitten"];
+
+        let jw = Scorer::JaroWinkler { max_prefix_length: 4, scaling_factor: 0.1 };
+        let matrix = cdist(&queries, &choices, &jw, Unit::Byte, Some(0.85));
+        assert!(matrix.iter().all(|&score| score == 0.0 || score >= 0.85));
+
+        let lev = Scorer::Distance(EditDistance::WeightedLevenshtein { deletion_cost: 1, insertion_cost: 1, substitution_cost: 1 });
+        let matrix = cdist(&queries, &choices, &lev, Unit::Byte, Some(2.0));
+        assert_eq!(matrix, vec![2.0, 2.0, 1.0, 1.0, 3.0, 3.0]);
+
+        // cdist_into writes cells of any type, knowing where they go
+        let mut cells = vec![(0, 0, 0); 6];
+        cdist_into(&queries, &choices, &lev, Unit::Byte, Some(2.0), &mut cells, |query, choice, distance| {
+            (query, choice, distance as u32)
+        });
+        assert_eq!(cells, vec![(0, 0, 2), (0, 1, 2), (0, 2, 1), (1, 0, 1), (1, 1, 3), (1, 2, 3)]);
+    }
+
+    #[test]
+    fn normalized_edit_distances() {
+        let lev = EditDistance::WeightedLevenshtein { deletion_cost: 1, insertion_cost: 1, substitution_cost: 2 };
+
+        assert_eq!(lev.max_distance(6, 7), 13);
+        assert_eq!(lev.normalized_distance(b"kitten", b"sitting", 1.0), 5.0 / 13.0);
+        assert_eq!(lev.normalized_similarity(b"kitten", b"sitting", 0.0), 1.0 - 5.0 / 13.0);
+        assert_eq!(lev.normalized_similarity(b"kitten", b"sitting", 0.9), 0.0);
+        assert_eq!(lev.normalized_distance(b"kitten", b"sitting", 0.1), 1.0);
+
+        assert_eq!(lev.normalized_distance(b"abc", b"abc", 1.0), 0.0);
+        assert_eq!(lev.normalized_distance(b"", b"abc", 1.0), 1.0);
+        assert_eq!(lev.normalized_distance(b"", b"", 1.0), 0.0);
+
+        // The maximum accounts for transpositions never being worse than the alternatives.
+        let osa = EditDistance::Osa { deletion_cost: 1, insertion_cost: 1, substitution_cost: 1, transposition_cost: 1 };
+        assert_eq!(osa.normalized_similarity(b"teh", b"the", 0.0), 1.0 - 1.0 / 3.0);
+
+        // Huge costs saturate rather than overflow, and the result stays in [0, 1].
+        let half = 1 << (usize::BITS - 1);
+        let huge = EditDistance::WeightedLevenshtein { deletion_cost: half, insertion_cost: half, substitution_cost: 1 };
+        assert_eq!(huge.max_distance(2, 4), usize::MAX);
+        let normalized = huge.normalized_distance(b"ab", b"cdef", 1.0);
+        assert!((0.0..=1.0).contains(&normalized));
+        assert_eq!(huge.normalized_distance(b"ab", b"cdab", 1.0), 1.0);
+    }
+
+    #[test]
+    fn fuzz_default_processing() {
+        // the single pair functions process with the ratio's own default and
+        // the batched ones with the scorer's, which must agree
+        let all = [
+            Fuzz::Ratio, Fuzz::PartialRatio, Fuzz::TokenSortRatio, Fuzz::TokenSetRatio, Fuzz::TokenRatio,
+            Fuzz::PartialTokenSortRatio, Fuzz::PartialTokenSetRatio, Fuzz::PartialTokenRatio, Fuzz::WRatio,
+        ];
+        for fuzz in all {
+            let scorer = Scorer::Fuzz(fuzz);
+            for (query, choice) in [("New York", "new york"), ("this is a test", "this is a test!")] {
+                let direct = fuzz.default_processor();
+                let direct = fuzz.score(direct.apply(query).as_bytes(), direct.apply(choice).as_bytes());
+                let processor = scorer.default_processor();
+                let choices = [processor.apply(choice)];
+                let batched = extract(&processor.apply(query), &choices, &scorer, Unit::Byte, None, None);
+                assert_eq!(batched, vec![(0, direct)]);
+            }
+        }
+
+        assert!(Fuzz::Ratio.default_processor().is_identity());
+        assert!(Fuzz::PartialRatio.default_processor().is_identity());
+        assert_eq!(Fuzz::WRatio.default_processor(), Preprocessor::all());
+        assert_eq!(Fuzz::TokenSortRatio.default_processor(), Preprocessor::all());
+        assert!(Scorer::JaroWinkler { max_prefix_length: 4, scaling_factor: 0.1 }.default_processor().is_identity());
+    }
+
+    #[test]
+    fn extract_top_k() {
+        let choices = ["mitten", "sittin", "kitchen", "bitten", "kitten "];
+
+        let jw = Scorer::JaroWinkler { max_prefix_length: 4, scaling_factor: 0.1 };
+        let best = extract("kitten", &choices, &jw, Unit::Byte, Some(2), None);
+        assert_eq!(best.iter().map(|(index, _)| *index).collect::<Vec<usize>>(), vec![4, 2]);
+        assert!(best[0].1 >= best[1].1);
+
+        // Ties keep the order of the choices.
+        let lev = Scorer::Distance(EditDistance::WeightedLevenshtein { deletion_cost: 1, insertion_cost: 1, substitution_cost: 1 });
+        let best = extract("kitten", &choices, &lev, Unit::Byte, None, Some(1.0));
+        assert_eq!(best, vec![(0, 1.0), (3, 1.0), (4, 1.0)]);
+
+        assert_eq!(extract_one("kitten", &choices, &lev, Unit::Byte, Some(0.0)), None);
+        assert_eq!(extract_one("kitten", &choices, &lev, Unit::Byte, None), Some((0, 1.0)));
+    }
+
+    #[test]
+    fn extract_many_matches_extract() {
+        let queries = ["kitten", "sitting", "café"];
+        let choices = ["mitten", "sittin", "kitchen", "bitten", "cafe"];
+
+        let jw = Scorer::JaroWinkler { max_prefix_length: 4, scaling_factor: 0.1 };
+        for unit in [Unit::Byte, Unit::Char] {
+            let matches = extract_many(&queries, &choices, &jw, unit, Some(2), Some(0.8));
+            for (query, matches) in queries.iter().zip(matches) {
+                assert_eq!(matches, extract(query, &choices, &jw, unit, Some(2), Some(0.8)));
+            }
+        }
+        assert_eq!(extract_many(&queries, &choices, &jw, Unit::Byte, Some(0), None), vec![vec![]; 3]);
+    }
+}
@@ -0,0 +1,2899 @@
+//! The Python bindings, built with the `python` feature.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use pyo3::buffer::{ Element, PyBuffer };
+use pyo3::prelude::*;
+use pyo3::types::{ PyByteArray, PyDict, PyList, PyString, PyTuple };
+
+use rayon::prelude::*;
+
+mod arrays;
+mod index;
+
+use crate::costs::CostTable;
+use crate::dedupe::Blocking;
+use crate::fuzz::Fuzz;
+use crate::phonetic::PhoneticAlgorithm;
+use crate::preprocess::Preprocessor;
+use crate::process::{ self, EditDistance, Scorer };
+use crate::qgram::{ QGram, SetMeasure };
+use crate::token::{ TokenMeasure, TokenSimilarity, Tokenizer };
+use crate::unit::{ Unit, with_units };
+use crate::{ damerau, dedupe as clusters, lcs, levenshtein, phonetic };
+use crate::{
+    check_jaro_winkler,
+    get_jaro_winkler_similarity,
+    get_legacy_jaro_winkler_similarity,
+    get_legacy_weighted_levenshtein_distance,
+    get_weighted_levenshtein_distance_with_cutoff,
+};
+
+
+fn parse_unit(unit: &str) -> PyResult<Unit> {
+    unit.parse::<Unit>().map_err(pyo3::exceptions::PyValueError::new_err)
+}
+
+/// The `processor` argument. Built in steps run in Rust, anything callable
+/// is called with each string and must return a string.
+enum Processor<'py> {
+    Steps(Preprocessor),
+    Callable(&'py PyAny),
+}
+
+/// Accepts `None`, a step name (`"default"` for all of them), a list of step
+/// names or a callable.
+fn parse_processor(processor: Option<&PyAny>) -> PyResult<Processor<'_>> {
+    let processor = match processor {
+        Some(processor) if !processor.is_none() => processor,
+        _ => return Ok(Processor::Steps(Preprocessor::default())),
+    };
+    let parse_step = |step: &PyAny| -> PyResult<Preprocessor> {
+        step.extract::<&str>()?.parse::<Preprocessor>().map_err(pyo3::exceptions::PyValueError::new_err)
+    };
+
+    if processor.is_instance_of::<PyString>()? {
+        return Ok(Processor::Steps(parse_step(processor)?));
+    }
+    if processor.is_instance_of::<PyList>()? || processor.is_instance_of::<PyTuple>()? {
+        let mut steps = Preprocessor::default();
+        for step in processor.iter()? {
+            steps = steps.union(&parse_step(step?)?);
+        }
+        return Ok(Processor::Steps(steps));
+    }
+    if processor.is_callable() {
+        return Ok(Processor::Callable(processor));
+    }
+    Err(pyo3::exceptions::PyTypeError::new_err(
+        "processor must be None, a step name, a list of step names or a callable",
+    ))
+}
+
+/// A `processor` argument whose default depends on the metric, see
+/// `Scorer::default_processor`. Passing `None` turns processing off.
+enum ProcessorArg<'py> {
+    Default,
+    Given(&'py PyAny),
+}
+
+impl<'py> FromPyObject<'py> for ProcessorArg<'py> {
+    fn extract(processor: &'py PyAny) -> PyResult<Self> {
+        Ok(ProcessorArg::Given(processor))
+    }
+}
+
+impl<'py> ProcessorArg<'py> {
+    fn resolve(self, default: Preprocessor) -> PyResult<Processor<'py>> {
+        match self {
+            ProcessorArg::Default => Ok(Processor::Steps(default)),
+            ProcessorArg::Given(processor) => parse_processor(Some(processor)),
+        }
+    }
+}
+
+impl<'py> Processor<'py> {
+    /// Processes a single string argument.
+    fn apply(&self, string: &'py PyAny) -> PyResult<Cow<'py, str>> {
+        match self {
+            Processor::Steps(steps) => Ok(steps.apply(string.extract::<&str>()?)),
+            Processor::Callable(function) => Ok(Cow::Owned(function.call1((string,))?.extract::<String>()?)),
+        }
+    }
+
+    /// Processes an element of a batch argument. Strings are borrowed
+    /// rather than copied where no processing is needed, anything else is
+    /// converted with `str()`.
+    fn apply_item(&self, item: &'py PyAny) -> PyResult<Cow<'py, str>> {
+        match self {
+            Processor::Steps(steps) => match item.downcast::<PyString>() {
+                Ok(string) => Ok(steps.apply(string.to_str()?)),
+                Err(_) => Ok(Cow::Owned(steps.apply(&item.to_string()).into_owned())),
+            },
+            Processor::Callable(function) => Ok(Cow::Owned(function.call1((item,))?.to_string())),
+        }
+    }
+
+    /// Processes a string read out of an array.
+    fn apply_str(&self, string: Cow<'py, str>) -> PyResult<Cow<'py, str>> {
+        match (self, string) {
+            (Processor::Steps(steps), Cow::Borrowed(string)) => Ok(steps.apply(string)),
+            (Processor::Steps(steps), Cow::Owned(string)) => Ok(Cow::Owned(steps.apply(&string).into_owned())),
+            (Processor::Callable(function), string) => Ok(Cow::Owned(function.call1((string.as_ref(),))?.to_string())),
+        }
+    }
+
+    /// Processes the elements of the batch argument `name`, which is a list
+    /// or other iterable, or an array `arrays::read_strings` reads directly.
+    /// A None (or null) is kept as the empty string and marked in the
+    /// returned mask, or raises under `NonePolicy::Raise`.
+    fn apply_all(&self, batch: &'py PyAny, name: &str, none_policy: NonePolicy) -> PyResult<(Vec<Cow<'py, str>>, Vec<bool>)> {
+        let items: Vec<Option<Cow<'py, str>>> = match arrays::read_strings(batch)? {
+            Some(strings) => strings
+                .into_iter()
+                .map(|string| string.map(|string| self.apply_str(string)).transpose())
+                .collect::<PyResult<_>>()?,
+            None => batch
+                .iter()?
+                .map(|item| {
+                    let item = item?;
+                    if item.is_none() { Ok(None) } else { self.apply_item(item).map(Some) }
+                })
+                .collect::<PyResult<_>>()?,
+        };
+
+        let mut strings = Vec::with_capacity(items.len());
+        let mut missing = Vec::with_capacity(items.len());
+        for (index, item) in items.into_iter().enumerate() {
+            match item {
+                Some(string) => {
+                    strings.push(string);
+                    missing.push(false);
+                }
+                None if none_policy == NonePolicy::Raise => {
+                    return Err(none_error(&format!("{}[{}]", name, index)));
+                }
+                None => {
+                    strings.push(Cow::Borrowed(""));
+                    missing.push(true);
+                }
+            }
+        }
+        Ok((strings, missing))
+    }
+}
+
+/// How a `None` string is scored. `Zero` scores 0, except for normalized
+/// distances which are 1 as for strings with nothing in common, even against
+/// another None or the empty string. `Raise` raises a `TypeError` and
+/// `Propagate` returns `None`, or NaN in float batches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NonePolicy {
+    Zero,
+    Raise,
+    Propagate,
+}
+
+fn parse_none_policy(none_policy: &str) -> PyResult<NonePolicy> {
+    match none_policy {
+        "zero"      => Ok(NonePolicy::Zero),
+        "raise"     => Ok(NonePolicy::Raise),
+        "propagate" => Ok(NonePolicy::Propagate),
+        _ => Err(pyo3::exceptions::PyValueError::new_err(format!(
+            "none_policy must be one of 'zero', 'raise' or 'propagate', got '{}'",
+            none_policy,
+        ))),
+    }
+}
+
+fn none_error(name: &str) -> PyErr {
+    pyo3::exceptions::PyTypeError::new_err(format!("{} is None and none_policy is 'raise'", name))
+}
+
+impl NonePolicy {
+    /// Processes a pair of string arguments. Returns `None` if either is
+    /// None and the policy doesn't raise, the caller then returns
+    /// `fallback`.
+    fn pair<'py>(
+        self,
+        processor: &Processor<'py>,
+        str1: Option<&'py PyString>,
+        str2: Option<&'py PyString>,
+        ) -> PyResult<Option<(Cow<'py, str>, Cow<'py, str>)>> {
+        match (str1, str2) {
+            (Some(str1), Some(str2)) => Ok(Some((processor.apply(str1)?, processor.apply(str2)?))),
+            _ if self == NonePolicy::Raise => Err(none_error(if str1.is_none() { "str1" } else { "str2" })),
+            _ => Ok(None),
+        }
+    }
+
+    /// Like `pair`, but under `Zero` a None is processed as the empty
+    /// string. Only for edit operations, which edit a None to or from
+    /// nothing, as scores use `fallback` instead.
+    fn pair_or_empty<'py>(
+        self,
+        processor: &Processor<'py>,
+        str1: Option<&'py PyString>,
+        str2: Option<&'py PyString>,
+        ) -> PyResult<Option<(Cow<'py, str>, Cow<'py, str>)>> {
+        if self != NonePolicy::Zero {
+            return self.pair(processor, str1, str2);
+        }
+        let apply = |string: Option<&'py PyString>| string.map_or(Ok(Cow::Borrowed("")), |string| processor.apply(string));
+        Ok(Some((apply(str1)?, apply(str2)?)))
+    }
+
+    /// The result of a pair with a None, `zero` unless propagating.
+    fn fallback<T>(self, zero: T) -> Option<T> {
+        match self {
+            NonePolicy::Propagate => None,
+            _ => Some(zero),
+        }
+    }
+
+    /// Overwrites the scores of the `missing` pairs with NaN when
+    /// propagating and with `zero` otherwise.
+    fn mask_scores(self, scores: &mut [f32], missing: &[bool], zero: f32) {
+        for (score, &missing) in scores.iter_mut().zip(missing) {
+            *score = self.mask_score(*score, missing, zero);
+        }
+    }
+
+    /// `mask_scores` for a single score.
+    fn mask_score(self, score: f32, missing: bool, zero: f32) -> f32 {
+        match (missing, self) {
+            (false, _) => score,
+            (true, NonePolicy::Propagate) => f32::NAN,
+            (true, _) => zero,
+        }
+    }
+
+    /// Replaces the values of the `missing` pairs with `fallback(zero)`.
+    fn mask_values<T: Clone>(self, values: Vec<T>, missing: &[bool], zero: T) -> Vec<Option<T>> {
+        values.into_iter().zip(missing).map(|(value, &missing)| {
+            if missing { self.fallback(zero.clone()) } else { Some(value) }
+        }).collect()
+    }
+}
+
+/// Processes two equal length batch arguments into strings the GIL can be
+/// released while scoring. Also returns which pairs have a None, see
+/// `Processor::apply_all`, and whether either argument was an array, in
+/// which case results are returned as a NumPy array.
+fn extract_string_pairs<'py>(
+    str1_list: Option<&'py PyAny>, 
+    str2_list: Option<&'py PyAny>,
+    processor: Processor<'py>,
+    none_policy: NonePolicy,
+    ) -> PyResult<StringPairs<'py>> {
+    if str1_list.is_none() || str2_list.is_none() {
+        return Err(pyo3::exceptions::PyValueError::new_err("str1_list and str2_list must be lists or arrays"));
+    }
+    let (str1_list, str2_list) = (str1_list.unwrap(), str2_list.unwrap());
+    let arrays = arrays::is_array(str1_list)? || arrays::is_array(str2_list)?;
+    let (str1_vec, missing1) = processor.apply_all(str1_list, "str1_list", none_policy)?;
+    let (str2_vec, missing2) = processor.apply_all(str2_list, "str2_list", none_policy)?;
+    
+    if str1_vec.len() != str2_vec.len() {
+        return Err(pyo3::exceptions::PyValueError::new_err("str1_list and str2_list must be of the same length"));
+    }
+    let missing = missing1.iter().zip(&missing2).map(|(&missing1, &missing2)| missing1 || missing2).collect();
+    Ok((str1_vec, str2_vec, missing, arrays))
+}
+
+/// Processed strings of two batch arguments, which pairs have a None and
+/// whether either was an array.
+type StringPairs<'py> = (Vec<Cow<'py, str>>, Vec<Cow<'py, str>>, Vec<bool>, bool);
+
+/// A scalar edit cost. Integer costs keep the integer kernels and return
+/// `int` distances, a float cost makes the distance a `float`.
+#[derive(FromPyObject, Debug, Clone, Copy)]
+enum Cost {
+    Int(usize),
+    Float(f64),
+}
+
+impl Cost {
+    fn as_int(self) -> Option<usize> {
+        match self {
+            Cost::Int(cost) => Some(cost),
+            Cost::Float(_) => None,
+        }
+    }
+
+    fn as_float(self) -> f64 {
+        match self {
+            Cost::Int(cost) => cost as f64,
+            Cost::Float(cost) => cost,
+        }
+    }
+}
+
+const LEGACY_COSTS_ERROR: &str = "legacy_semantics only supports integer costs without a cost table";
+
+/// Builds a cost table from the `costs` argument on top of the scalar costs,
+/// or returns `None` if there is no table and every cost is an integer.
+///
+/// `costs` is a preset name (`"keyboard"` or `"ocr"`), a list of them, or a
+/// dict with any of `"preset"`, `"deletion"` and `"insertion"` (dicts of unit
+/// to cost) and `"substitution"`, either a dict of `(from, to)` to cost or a
+/// square matrix (list of lists or NumPy array) indexed by `"alphabet"`.
+/// Presets make their substitutions cost half of `substitution_cost`.
+fn parse_costs(
+    costs: Option<&PyAny>,
+    deletion_cost: Cost,
+    insertion_cost: Cost,
+    substitution_cost: Cost,
+    ) -> PyResult<Option<CostTable>> {
+    let costs = costs.filter(|costs| !costs.is_none());
+    let scalars = [deletion_cost, insertion_cost, substitution_cost];
+    if costs.is_none() && scalars.iter().all(|cost| cost.as_int().is_some()) {
+        return Ok(None);
+    }
+
+    let cost_error = pyo3::exceptions::PyValueError::new_err;
+    let mut table = CostTable::new(deletion_cost.as_float(), insertion_cost.as_float(), substitution_cost.as_float())
+        .map_err(cost_error)?;
+    let preset_cost = substitution_cost.as_float() / 2.0;
+    let costs = match costs {
+        Some(costs) => costs,
+        None => return Ok(Some(table)),
+    };
+    let add_presets = |table: CostTable, presets: &PyAny| -> PyResult<CostTable> {
+        let names: Vec<&str> = if presets.is_instance_of::<PyString>()? {
+            vec![presets.extract()?]
+        } else {
+            presets.extract()?
+        };
+        names.into_iter().try_fold(table, |table, name| match name {
+            "keyboard" => table.keyboard(preset_cost).map_err(cost_error),
+            "ocr" => table.ocr(preset_cost).map_err(cost_error),
+            _ => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "cost presets must be 'keyboard' or 'ocr', got '{}'",
+                name,
+            ))),
+        })
+    };
+
+    let costs: &PyDict = match costs.downcast::<PyDict>() {
+        Ok(costs) => costs,
+        Err(_) => return Ok(Some(add_presets(table, costs)?)),
+    };
+    for key in costs.keys() {
+        let key: &str = key.extract()?;
+        if !["preset", "deletion", "insertion", "substitution", "alphabet"].contains(&key) {
+            return Err(pyo3::exceptions::PyTypeError::new_err(format!("unexpected key '{}' in costs", key)));
+        }
+    }
+
+    if let Some(presets) = costs.get_item("preset") {
+        table = add_presets(table, presets)?;
+    }
+    if let Some(deletion) = costs.get_item("deletion") {
+        for (unit, cost) in deletion.extract::<HashMap<&str, f64>>()? {
+            table.set_deletion(unit, cost).map_err(cost_error)?;
+        }
+    }
+    if let Some(insertion) = costs.get_item("insertion") {
+        for (unit, cost) in insertion.extract::<HashMap<&str, f64>>()? {
+            table.set_insertion(unit, cost).map_err(cost_error)?;
+        }
+    }
+    if let Some(substitution) = costs.get_item("substitution") {
+        if let Ok(substitution) = substitution.downcast::<PyDict>() {
+            for ((from, to), cost) in substitution.extract::<HashMap<(&str, &str), f64>>()? {
+                table.set_substitution(from, to, cost).map_err(cost_error)?;
+            }
+        } else {
+            let alphabet: Vec<String> = match costs.get_item("alphabet") {
+                Some(alphabet) if alphabet.is_instance_of::<PyString>()? => {
+                    alphabet.extract::<&str>()?.chars().map(String::from).collect()
+                }
+                Some(alphabet) => alphabet.extract()?,
+                None => {
+                    return Err(pyo3::exceptions::PyValueError::new_err(
+                        "a substitution matrix needs an alphabet naming its rows and columns",
+                    ));
+                }
+            };
+            let matrix: Vec<Vec<f64>> = substitution.extract()?;
+            if matrix.len() != alphabet.len() || matrix.iter().any(|row| row.len() != alphabet.len()) {
+                return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                    "the substitution matrix must be {0} x {0} to match the alphabet",
+                    alphabet.len(),
+                )));
+            }
+            for (from, row) in alphabet.iter().zip(matrix.iter()) {
+                for (to, cost) in alphabet.iter().zip(row.iter()) {
+                    table.set_substitution(from, to, *cost).map_err(cost_error)?;
+                }
+            }
+        }
+    }
+    Ok(Some(table))
+}
+
+/// Builds a `Scorer` from a metric name and the keyword arguments the
+/// matching pyfunction accepts. Edit distances can be suffixed with
+/// `_normalized_distance` or `_normalized_similarity`.
+fn parse_scorer(metric: &str, params: Option<&PyDict>) -> PyResult<Scorer> {
+    if metric == "monge_elkan" {
+        return parse_monge_elkan(params);
+    }
+    let param = |name: &str| -> PyResult<Option<&PyAny>> {
+        match params {
+            Some(params) => Ok(params.get_item(name)),
+            None => Ok(None),
+        }
+    };
+    let (base, normalization) = match metric.find("_normalized_") {
+        Some(idx) => (&metric[..idx], Some(&metric[idx + "_normalized_".len()..])),
+        None => (metric, None),
+    };
+    let allowed: &[&str] = match base {
+        "jaro_winkler" if normalization.is_none() => &["max_prefix_length", "scaling_factor", "legacy_semantics"],
+        "jaccard" if normalization.is_none() => &["q", "pad", "weighted", "legacy"],
+        "sorensen_dice" | "overlap" if normalization.is_none() => &["q", "pad", "weighted"],
+        "tversky" if normalization.is_none() => &["alpha", "beta", "q", "pad", "weighted"],
+        "token_jaccard" | "token_dice" | "token_cosine" if normalization.is_none() => &["tokenizer", "pattern"],
+        "lcs" if normalization.is_none() => &[],
+        _ if normalization.is_none() && parse_fuzz(base).is_some() => &[],
+        "indel" => &[],
+        "weighted_levenshtein" if normalization.is_none() => {
+            &["deletion_cost", "insertion_cost", "substitution_cost", "costs", "legacy_semantics"]
+        }
+        "weighted_levenshtein" => &["deletion_cost", "insertion_cost", "substitution_cost", "costs"],
+        "osa" | "damerau_levenshtein" => &["deletion_cost", "insertion_cost", "substitution_cost", "transposition_cost"],
+        _ => {
+            return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "metric must be one of 'jaro_winkler', 'jaccard', 'sorensen_dice', 'overlap', 'tversky', 'token_jaccard', \
+                 'token_dice', 'token_cosine', 'monge_elkan', 'lcs', the fuzz ratios such as 'ratio' or \
+                 'WRatio', 'weighted_levenshtein', 'osa', 'damerau_levenshtein' or 'indel', optionally \
+                 suffixed with '_normalized_distance' or '_normalized_similarity' for the distances, got '{}'",
+                metric,
+            )));
+        }
+    };
+    if let Some(params) = params {
+        for key in params.keys() {
+            let key: &str = key.extract()?;
+            if !allowed.contains(&key) {
+                return Err(pyo3::exceptions::PyTypeError::new_err(format!(
+                    "unexpected parameter '{}' for metric '{}'",
+                    key,
+                    metric,
+                )));
+            }
+        }
+    }
+
+    let cost = |name: &str| -> PyResult<usize> { param(name)?.map_or(Ok(1), |x| x.extract()) };
+    let distance = match base {
+        "jaro_winkler" => {
+            let max_prefix_length = param("max_prefix_length")?.map_or(Ok(4), |x| x.extract())?;
+            let scaling_factor = param("scaling_factor")?.map_or(Ok(0.1), |x| x.extract())?;
+            check_jaro_winkler(max_prefix_length, scaling_factor).map_err(pyo3::exceptions::PyValueError::new_err)?;
+            if param("legacy_semantics")?.map_or(Ok(false), |x| x.extract())? {
+                return Ok(Scorer::LegacyJaroWinkler { max_prefix_length, scaling_factor });
+            }
+            return Ok(Scorer::JaroWinkler { max_prefix_length, scaling_factor });
+        }
+        "jaccard" | "sorensen_dice" | "overlap" | "tversky" => {
+            let measure = match base {
+                "jaccard" => SetMeasure::Jaccard,
+                "sorensen_dice" => SetMeasure::SorensenDice,
+                "overlap" => SetMeasure::Overlap,
+                _ => SetMeasure::tversky(
+                    param("alpha")?.map_or(Ok(1.0), |x| x.extract())?,
+                    param("beta")?.map_or(Ok(1.0), |x| x.extract())?,
+                    ).map_err(pyo3::exceptions::PyValueError::new_err)?,
+            };
+            return qgram_scorer(
+                measure,
+                param("q")?.map_or(Ok(1), |x| x.extract())?,
+                param("pad")?.map_or(Ok(false), |x| x.extract())?,
+                param("weighted")?.map_or(Ok(false), |x| x.extract())?,
+                param("legacy")?.map_or(Ok(false), |x| x.extract())?,
+                );
+        }
+        "token_jaccard" | "token_dice" | "token_cosine" => {
+            let measure = match base {
+                "token_jaccard" => SetMeasure::Jaccard,
+                "token_dice" => SetMeasure::SorensenDice,
+                _ => SetMeasure::Cosine,
+            };
+            let tokenizer = parse_tokenizer(
+                param("tokenizer")?.map_or(Ok("whitespace"), |x| x.extract())?,
+                param("pattern")?.map(|x| x.extract()).transpose()?,
+                )?;
+            return Ok(Scorer::Token(TokenSimilarity { tokenizer, measure: TokenMeasure::Set(measure) }));
+        }
+        "lcs" => return Ok(Scorer::LcsSimilarity),
+        _ if parse_fuzz(base).is_some() => return Ok(Scorer::Fuzz(parse_fuzz(base).unwrap())),
+        "indel" => EditDistance::Indel,
+        "weighted_levenshtein" => {
+            let scalar = |name: &str| -> PyResult<Cost> { param(name)?.map_or(Ok(Cost::Int(1)), |x| x.extract()) };
+            let deletion_cost = scalar("deletion_cost")?;
+            let insertion_cost = scalar("insertion_cost")?;
+            let substitution_cost = scalar("substitution_cost")?;
+            let legacy_semantics = param("legacy_semantics")?.map_or(Ok(false), |x| x.extract())?;
+            match parse_costs(param("costs")?, deletion_cost, insertion_cost, substitution_cost)? {
+                Some(_) if legacy_semantics => {
+                    return Err(pyo3::exceptions::PyValueError::new_err(LEGACY_COSTS_ERROR));
+                }
+                Some(costs) if normalization.is_none() => return Ok(Scorer::CostDistance(costs)),
+                Some(_) => {
+                    return Err(pyo3::exceptions::PyValueError::new_err(
+                        "normalized weighted levenshtein only supports integer costs without a cost table",
+                    ));
+                }
+                // Without a table every cost is an integer.
+                None if legacy_semantics => EditDistance::LegacyWeightedLevenshtein {
+                    deletion_cost: deletion_cost.as_int().unwrap(),
+                    insertion_cost: insertion_cost.as_int().unwrap(),
+                    substitution_cost: substitution_cost.as_int().unwrap(),
+                },
+                None => EditDistance::WeightedLevenshtein {
+                    deletion_cost: deletion_cost.as_int().unwrap(),
+                    insertion_cost: insertion_cost.as_int().unwrap(),
+                    substitution_cost: substitution_cost.as_int().unwrap(),
+                },
+            }
+        }
+        "osa" => EditDistance::Osa {
+            deletion_cost: cost("deletion_cost")?,
+            insertion_cost: cost("insertion_cost")?,
+            substitution_cost: cost("substitution_cost")?,
+            transposition_cost: cost("transposition_cost")?,
+        },
+        _ => EditDistance::DamerauLevenshtein {
+            deletion_cost: cost("deletion_cost")?,
+            insertion_cost: cost("insertion_cost")?,
+            substitution_cost: cost("substitution_cost")?,
+            transposition_cost: cost("transposition_cost")?,
+        },
+    };
+
+    match normalization {
+        None => Ok(Scorer::Distance(distance)),
+        Some("distance") => Ok(Scorer::NormalizedDistance(distance)),
+        Some("similarity") => Ok(Scorer::NormalizedSimilarity(distance)),
+        Some(_) => Err(pyo3::exceptions::PyValueError::new_err(format!(
+            "metric '{}' must end in '_normalized_distance' or '_normalized_similarity'",
+            metric,
+        ))),
+    }
+}
+
+/// A q-gram scorer, or the original byte set Jaccard with `legacy`, which
+/// takes no q-gram options.
+fn qgram_scorer(measure: SetMeasure, q: usize, pad: bool, weighted: bool, legacy: bool) -> PyResult<Scorer> {
+    if q == 0 {
+        return Err(pyo3::exceptions::PyValueError::new_err("q must be at least 1"));
+    }
+    if legacy {
+        if q != 1 || pad || weighted {
+            return Err(pyo3::exceptions::PyValueError::new_err("legacy jaccard does not support q, pad or weighted"));
+        }
+        return Ok(Scorer::LegacyJaccard);
+    }
+    Ok(Scorer::QGram(QGram { q, pad, weighted, measure }))
+}
+
+/// `"whitespace"`, `"word_boundary"` or `"regex"`, which needs a `pattern`
+/// whose matches are the tokens.
+fn parse_tokenizer(tokenizer: &str, pattern: Option<&str>) -> PyResult<Tokenizer> {
+    match (tokenizer, pattern) {
+        ("whitespace", None) => Ok(Tokenizer::Whitespace),
+        ("word_boundary", None) => Ok(Tokenizer::WordBoundary),
+        ("regex", Some(pattern)) => regex::Regex::new(pattern)
+            .map(Tokenizer::Regex)
+            .map_err(|err| pyo3::exceptions::PyValueError::new_err(err.to_string())),
+        ("regex", None) => Err(pyo3::exceptions::PyValueError::new_err("tokenizer 'regex' needs a pattern")),
+        ("whitespace" | "word_boundary", Some(_)) => Err(pyo3::exceptions::PyValueError::new_err(
+            "pattern is only used with tokenizer='regex'",
+        )),
+        _ => Err(pyo3::exceptions::PyValueError::new_err(format!(
+            "tokenizer must be one of 'whitespace', 'word_boundary' or 'regex', got '{}'",
+            tokenizer,
+        ))),
+    }
+}
+
+/// Monge-Elkan over `tokenizer` with `inner` as the token scorer. Every
+/// parameter other than `inner`, `tokenizer` and `pattern` is passed on to
+/// `inner`.
+fn parse_monge_elkan(params: Option<&PyDict>) -> PyResult<Scorer> {
+    let mut inner = "jaro_winkler";
+    let mut tokenizer = "whitespace";
+    let mut pattern = None;
+    let inner_params = params.map(|params| params.copy()).transpose()?;
+
+    if let Some(inner_params) = inner_params {
+        if let Some(value) = inner_params.get_item("inner") {
+            inner = value.extract()?;
+            inner_params.del_item("inner")?;
+        }
+        if let Some(value) = inner_params.get_item("tokenizer") {
+            tokenizer = value.extract()?;
+            inner_params.del_item("tokenizer")?;
+        }
+        if let Some(value) = inner_params.get_item("pattern") {
+            pattern = value.extract()?;
+            inner_params.del_item("pattern")?;
+        }
+    }
+
+    let inner_scorer = parse_scorer(inner, inner_params)?;
+    if matches!(inner_scorer, Scorer::Token(_)) {
+        return Err(pyo3::exceptions::PyValueError::new_err(format!(
+            "the inner metric of monge_elkan can't be a token metric, got '{}'",
+            inner,
+        )));
+    }
+    if inner_scorer.is_distance() {
+        return Err(pyo3::exceptions::PyValueError::new_err(format!(
+            "the inner metric of monge_elkan must be a similarity, got '{}'",
+            inner,
+        )));
+    }
+    Ok(Scorer::Token(TokenSimilarity {
+        tokenizer: parse_tokenizer(tokenizer, pattern)?,
+        measure: TokenMeasure::MongeElkan(Box::new(inner_scorer)),
+    }))
+}
+
+/// The fuzz ratio called `name`, accepting fuzzywuzzy's `WRatio` spelling.
+fn parse_fuzz(name: &str) -> Option<Fuzz> {
+    match name {
+        "ratio" => Some(Fuzz::Ratio),
+        "partial_ratio" => Some(Fuzz::PartialRatio),
+        "token_sort_ratio" => Some(Fuzz::TokenSortRatio),
+        "token_set_ratio" => Some(Fuzz::TokenSetRatio),
+        "token_ratio" => Some(Fuzz::TokenRatio),
+        "partial_token_sort_ratio" => Some(Fuzz::PartialTokenSortRatio),
+        "partial_token_set_ratio" => Some(Fuzz::PartialTokenSetRatio),
+        "partial_token_ratio" => Some(Fuzz::PartialTokenRatio),
+        "wratio" | "WRatio" => Some(Fuzz::WRatio),
+        _ => None,
+    }
+}
+
+/// Scores two equal length Python lists pairwise in parallel with `scorer`.
+fn score_pairs_batched<'py>(
+    _py: Python, 
+    str1_list: Option<&'py PyAny>, 
+    str2_list: Option<&'py PyAny>,
+    scorer: Scorer,
+    unit: Unit,
+    processor: Processor<'py>,
+    none_policy: &str,
+    ) -> PyResult<PyObject> {
+    let none_policy = parse_none_policy(none_policy)?;
+    let (str1_vec, str2_vec, missing, arrays) = extract_string_pairs(str1_list, str2_list, processor, none_policy)?;
+
+    let mut scores: Vec<f32> = _py.allow_threads(|| {
+        str1_vec.par_iter().zip(str2_vec.par_iter()).map(|(str1, str2)| {
+            with_units!(unit, str1, str2, |a, b| scorer.score(a, b, None))
+        }).collect()
+    });
+    none_policy.mask_scores(&mut scores, &missing, scorer.none_score());
+    batch_to_py(_py, scores, arrays)
+}
+
+/// Runs `f` on a rayon pool with `workers` threads. Non positive values use
+/// the global pool, which has one thread per core.
+fn with_workers<T: Send, F: FnOnce() -> T + Send>(workers: i64, f: F) -> PyResult<T> {
+    if workers <= 0 {
+        return Ok(f());
+    }
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(workers as usize)
+        .build()
+        .map_err(|err| pyo3::exceptions::PyRuntimeError::new_err(err.to_string()))?;
+    Ok(pool.install(f))
+}
+
+/// Values that can be written into a NumPy array of `DTYPE`.
+trait NumpyElement: Copy {
+    const DTYPE: &'static str;
+    const SIZE: usize;
+
+    fn write(self, bytes: &mut [u8]);
+
+    fn to_f64(self) -> f64;
+}
+
+impl NumpyElement for f32 {
+    const DTYPE: &'static str = "float32";
+    const SIZE: usize = 4;
+
+    fn write(self, bytes: &mut [u8]) {
+        bytes.copy_from_slice(&self.to_ne_bytes());
+    }
+
+    fn to_f64(self) -> f64 {
+        f64::from(self)
+    }
+}
+
+impl NumpyElement for f64 {
+    const DTYPE: &'static str = "float64";
+    const SIZE: usize = 8;
+
+    fn write(self, bytes: &mut [u8]) {
+        bytes.copy_from_slice(&self.to_ne_bytes());
+    }
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+}
+
+impl NumpyElement for u32 {
+    const DTYPE: &'static str = "uint32";
+    const SIZE: usize = 4;
+
+    fn write(self, bytes: &mut [u8]) {
+        bytes.copy_from_slice(&self.to_ne_bytes());
+    }
+
+    fn to_f64(self) -> f64 {
+        f64::from(self)
+    }
+}
+
+impl NumpyElement for usize {
+    const DTYPE: &'static str = "uint64";
+    const SIZE: usize = 8;
+
+    fn write(self, bytes: &mut [u8]) {
+        bytes.copy_from_slice(&(self as u64).to_ne_bytes());
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl NumpyElement for bool {
+    const DTYPE: &'static str = "bool";
+    const SIZE: usize = 1;
+
+    fn write(self, bytes: &mut [u8]) {
+        bytes[0] = u8::from(self);
+    }
+
+    fn to_f64(self) -> f64 {
+        f64::from(u8::from(self))
+    }
+}
+
+/// Wraps `values` in a 1-D NumPy array without going through Python
+/// objects. The array owns a bytearray holding the values.
+fn to_numpy_array<'py, E: NumpyElement>(py: Python<'py>, values: &[E]) -> PyResult<&'py PyAny> {
+    let buffer = PyByteArray::new_with(py, values.len() * E::SIZE, |bytes: &mut [u8]| {
+        for (chunk, value) in bytes.chunks_exact_mut(E::SIZE).zip(values.iter()) {
+            value.write(chunk);
+        }
+        Ok(())
+    })?;
+    py.import("numpy")?.call_method1("frombuffer", (buffer, E::DTYPE))
+}
+
+/// Creates a `shape` NumPy matrix of `E` and fills it in place with `fill`,
+/// which runs with the GIL released. Large results are written once, in
+/// their final dtype, rather than built in a `Vec` and copied over.
+fn numpy_matrix_with<'py, E: NumpyElement + Element + Send>(
+    py: Python<'py>,
+    shape: (usize, usize),
+    fill: impl FnOnce(&mut [E]) -> PyResult<()> + Send,
+    ) -> PyResult<&'py PyAny> {
+    let matrix = py.import("numpy")?.call_method1("empty", (shape, E::DTYPE))?;
+    let len = shape.0 * shape.1;
+    if len == 0 {
+        return Ok(matrix);
+    }
+    let buffer = PyBuffer::<E>::get(matrix)?;
+    if buffer.readonly() || !buffer.is_c_contiguous() || buffer.item_count() != len {
+        return Err(pyo3::exceptions::PyRuntimeError::new_err("numpy.empty returned an unexpected buffer"));
+    }
+    // SAFETY: the array was just created, so nothing else reads or writes
+    // it, and `buffer` keeps its memory exported until the cells have been
+    // written. `PyBuffer::get` checked the item type and alignment.
+    let cells = unsafe { std::slice::from_raw_parts_mut(buffer.buf_ptr() as *mut E, len) };
+    py.allow_threads(|| fill(cells))?;
+    drop(buffer);
+    Ok(matrix)
+}
+
+/// Returns batch results as a NumPy array if the strings came in as arrays
+/// and as a list otherwise.
+fn batch_to_py<E: NumpyElement + IntoPy<PyObject>>(py: Python, values: Vec<E>, arrays: bool) -> PyResult<PyObject> {
+    if arrays {
+        Ok(to_numpy_array(py, &values)?.into_py(py))
+    } else {
+        Ok(values.into_py(py))
+    }
+}
+
+/// `batch_to_py` for results that are `None` where a None was propagated.
+/// As a NumPy array those are float64, with NaN for `None`.
+fn optional_batch_to_py<E: NumpyElement + IntoPy<PyObject>>(
+    py: Python,
+    values: Vec<Option<E>>,
+    arrays: bool,
+    none_policy: NonePolicy,
+    ) -> PyResult<PyObject> {
+    if !arrays {
+        return Ok(values.into_py(py));
+    }
+    if none_policy == NonePolicy::Propagate {
+        let values: Vec<f64> = values.into_iter().map(|value| value.map_or(f64::NAN, E::to_f64)).collect();
+        return batch_to_py(py, values, true);
+    }
+    // without propagation every pair has a value
+    batch_to_py(py, values.into_iter().flatten().collect(), true)
+}
+
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1, str2, max_prefix_length=4, scaling_factor=0.1, unit="byte", processor=None, legacy_semantics=false, none_policy="zero"))]
+fn jaro_winkler_similarity(
+    _py: Python, 
+    str1: Option<&PyString>, 
+    str2: Option<&PyString>,
+    max_prefix_length: Option<i32>,
+    scaling_factor: Option<f32>,
+    unit: &str,
+    processor: Option<&PyAny>,
+    legacy_semantics: bool,
+    none_policy: &str,
+    ) -> PyResult<Option<f32>> {
+    let unit = parse_unit(unit)?;
+    let processor = parse_processor(processor)?;
+    let none_policy = parse_none_policy(none_policy)?;
+    let max_prefix_length = max_prefix_length.unwrap_or(4) as usize;
+    let scaling_factor = scaling_factor.unwrap_or(0.1);
+    check_jaro_winkler(max_prefix_length, scaling_factor).map_err(pyo3::exceptions::PyValueError::new_err)?;
+
+    let (str1, str2) = match none_policy.pair(&processor, str1, str2)? {
+        Some(pair) => pair,
+        None => return Ok(none_policy.fallback(0.0)),
+    };
+
+    Ok(Some(with_units!(unit, &str1, &str2, |a, b| {
+        if legacy_semantics {
+            get_legacy_jaro_winkler_similarity(a, b, max_prefix_length, scaling_factor, 0.0)
+        } else {
+            get_jaro_winkler_similarity(a, b, max_prefix_length, scaling_factor)
+        }
+    })))
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1_list, str2_list, max_prefix_length=4, scaling_factor=0.1, unit="byte", processor=None, legacy_semantics=false, none_policy="zero"))]
+fn jaro_winkler_similarity_batched(
+    _py: Python, 
+    str1_list: Option<&PyAny>, 
+    str2_list: Option<&PyAny>,
+    max_prefix_length: Option<i32>,
+    scaling_factor: Option<f32>,
+    unit: &str,
+    processor: Option<&PyAny>,
+    legacy_semantics: bool,
+    none_policy: &str,
+    ) -> PyResult<PyObject> {
+    let unit = parse_unit(unit)?;
+    let none_policy = parse_none_policy(none_policy)?;
+
+    let (str1_vec, str2_vec, missing, arrays) = extract_string_pairs(str1_list, str2_list, parse_processor(processor)?, none_policy)?;
+
+    let max_prefix_length = max_prefix_length.unwrap_or(4) as usize;
+    let scaling_factor = scaling_factor.unwrap_or(0.1);
+    check_jaro_winkler(max_prefix_length, scaling_factor).map_err(pyo3::exceptions::PyValueError::new_err)?;
+
+    // Use _py.allow_threads() to allow the GIL to be released
+    let mut jw_sims: Vec<f32> = _py.allow_threads(|| {
+        str1_vec.par_iter().zip(str2_vec.par_iter()).map(|(str1, str2)| {
+            with_units!(unit, str1, str2, |a, b| {
+                if legacy_semantics {
+                    get_legacy_jaro_winkler_similarity(a, b, max_prefix_length, scaling_factor, 0.0)
+                } else {
+                    get_jaro_winkler_similarity(a, b, max_prefix_length, scaling_factor)
+                }
+            })
+        }).collect()
+    });
+    none_policy.mask_scores(&mut jw_sims, &missing, 0.0);
+    batch_to_py(_py, jw_sims, arrays)
+}
+
+
+/// Returns an `int` for integer costs and a `float` once any cost is a
+/// float or a cost table is given.
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1, str2, deletion_cost=Cost::Int(1), insertion_cost=Cost::Int(1), substitution_cost=Cost::Int(1), unit="byte", processor=None, max_distance=None, costs=None, legacy_semantics=false, none_policy="zero"))]
+fn weighted_levenshtein_distance(
+    _py: Python, 
+    str1: Option<&PyString>, 
+    str2: Option<&PyString>,
+    deletion_cost: Cost,
+    insertion_cost: Cost,
+    substitution_cost: Cost,
+    unit: &str,
+    processor: Option<&PyAny>,
+    max_distance: Option<f64>,
+    costs: Option<&PyAny>,
+    legacy_semantics: bool,
+    none_policy: &str,
+    ) -> PyResult<PyObject> {
+    let unit = parse_unit(unit)?;
+    let processor = parse_processor(processor)?;
+    let none_policy = parse_none_policy(none_policy)?;
+    let table = parse_costs(costs, deletion_cost, insertion_cost, substitution_cost)?;
+    if legacy_semantics && table.is_some() {
+        return Err(pyo3::exceptions::PyValueError::new_err(LEGACY_COSTS_ERROR));
+    }
+
+    let (str1, str2) = match none_policy.pair(&processor, str1, str2)? {
+        Some(pair) => pair,
+        None if table.is_some() => return Ok(none_policy.fallback(0.0).into_py(_py)),
+        None => return Ok(none_policy.fallback(0).into_py(_py)),
+    };
+
+    if let Some(table) = table {
+        table.check_unit(unit).map_err(pyo3::exceptions::PyValueError::new_err)?;
+        let distance = with_units!(unit, &str1, &str2, |a, b| {
+            levenshtein::get_cost_table_levenshtein_distance(a, b, &table, max_distance.unwrap_or(f64::INFINITY))
+        });
+        return Ok(distance.into_py(_py));
+    }
+
+    let distance = with_units!(unit, &str1, &str2, |a, b| {
+        let distance_fn = if legacy_semantics {
+            get_legacy_weighted_levenshtein_distance
+        } else {
+            get_weighted_levenshtein_distance_with_cutoff
+        };
+        distance_fn(
+            a,
+            b,
+            deletion_cost.as_int().unwrap(),
+            insertion_cost.as_int().unwrap(),
+            substitution_cost.as_int().unwrap(),
+            max_distance.map_or(usize::MAX, |max_distance| max_distance.max(0.0) as usize),
+            )
+    });
+    Ok(distance.into_py(_py))
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1_list, str2_list, deletion_cost=Cost::Int(1), insertion_cost=Cost::Int(1), substitution_cost=Cost::Int(1), unit="byte", processor=None, max_distance=None, costs=None, legacy_semantics=false, none_policy="zero"))]
+fn weighted_levenshtein_distance_batched(
+    _py: Python, 
+    str1_list: Option<&PyAny>, 
+    str2_list: Option<&PyAny>,
+    deletion_cost: Cost,
+    insertion_cost: Cost,
+    substitution_cost: Cost,
+    unit: &str,
+    processor: Option<&PyAny>,
+    max_distance: Option<f64>,
+    costs: Option<&PyAny>,
+    legacy_semantics: bool,
+    none_policy: &str,
+    ) -> PyResult<PyObject> {
+    let unit = parse_unit(unit)?;
+    let none_policy = parse_none_policy(none_policy)?;
+    let table = parse_costs(costs, deletion_cost, insertion_cost, substitution_cost)?;
+    if legacy_semantics && table.is_some() {
+        return Err(pyo3::exceptions::PyValueError::new_err(LEGACY_COSTS_ERROR));
+    }
+    let (str1_vec, str2_vec, missing, arrays) = extract_string_pairs(str1_list, str2_list, parse_processor(processor)?, none_policy)?;
+
+    if let Some(table) = table {
+        table.check_unit(unit).map_err(pyo3::exceptions::PyValueError::new_err)?;
+        let mut distances: Vec<f64> = _py.allow_threads(|| {
+            str1_vec.par_iter().zip(str2_vec.par_iter()).map(|(str1, str2)| {
+                with_units!(unit, str1, str2, |a, b| {
+                    levenshtein::get_cost_table_levenshtein_distance(a, b, &table, max_distance.unwrap_or(f64::INFINITY))
+                })
+            }).collect()
+        });
+        for (distance, _) in distances.iter_mut().zip(&missing).filter(|(_, &missing)| missing) {
+            *distance = none_policy.fallback(0.0).unwrap_or(f64::NAN);
+        }
+        return batch_to_py(_py, distances, arrays);
+    }
+
+    let distances: Vec<usize> = _py.allow_threads(|| {
+        str1_vec.par_iter().zip(str2_vec.par_iter()).map(|(str1, str2)| {
+            with_units!(unit, str1, str2, |a, b| {
+                let distance_fn = if legacy_semantics {
+                    get_legacy_weighted_levenshtein_distance
+                } else {
+                    get_weighted_levenshtein_distance_with_cutoff
+                };
+                distance_fn(
+                    a,
+                    b,
+                    deletion_cost.as_int().unwrap(),
+                    insertion_cost.as_int().unwrap(),
+                    substitution_cost.as_int().unwrap(),
+                    max_distance.map_or(usize::MAX, |max_distance| max_distance.max(0.0) as usize),
+                )
+            })
+        }).collect()
+    });
+    optional_batch_to_py(_py, none_policy.mask_values(distances, &missing, 0), arrays, none_policy)
+}
+
+/// An `(op, src_pos, dest_pos)` edit operation.
+type PyEditop = (&'static str, usize, usize);
+
+/// A difflib style `(tag, i1, i2, j1, j2)` opcode.
+type PyOpcode = (&'static str, usize, usize, usize, usize);
+
+/// Parses `(op, src_pos, dest_pos)` tuples as returned by
+/// `weighted_levenshtein_editops`.
+fn extract_editops(editops: &PyList) -> PyResult<Vec<levenshtein::Editop>> {
+    editops.iter().map(|editop| {
+        let (op, src_pos, dest_pos): (&str, usize, usize) = editop.extract()?;
+        let op = match op {
+            "replace" => levenshtein::EditOp::Replace,
+            "insert"  => levenshtein::EditOp::Insert,
+            "delete"  => levenshtein::EditOp::Delete,
+            _ => {
+                return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                    "edit operation must be one of 'replace', 'insert' or 'delete', got '{}'",
+                    op,
+                )));
+            }
+        };
+        Ok(levenshtein::Editop { op, src_pos, dest_pos })
+    }).collect()
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1, str2, deletion_cost=1, insertion_cost=1, substitution_cost=1, unit="byte", processor=None, none_policy="zero"))]
+fn weighted_levenshtein_editops(
+    _py: Python, 
+    str1: Option<&PyString>, 
+    str2: Option<&PyString>,
+    deletion_cost: usize,
+    insertion_cost: usize,
+    substitution_cost: usize,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<Option<Vec<PyEditop>>> {
+    let unit = parse_unit(unit)?;
+    let processor = parse_processor(processor)?;
+    let none_policy = parse_none_policy(none_policy)?;
+
+    let (str1, str2) = match none_policy.pair_or_empty(&processor, str1, str2)? {
+        Some(pair) => pair,
+        None => return Ok(None),
+    };
+
+    let editops = with_units!(unit, &str1, &str2, |a, b| {
+        levenshtein::get_weighted_levenshtein_editops(
+            a,
+            b,
+            deletion_cost,
+            insertion_cost,
+            substitution_cost,
+            )
+    });
+    Ok(Some(editops.iter().map(|editop| (editop.op.as_str(), editop.src_pos, editop.dest_pos)).collect()))
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1, str2, deletion_cost=1, insertion_cost=1, substitution_cost=1, unit="byte", processor=None, none_policy="zero"))]
+fn weighted_levenshtein_opcodes(
+    _py: Python, 
+    str1: Option<&PyString>, 
+    str2: Option<&PyString>,
+    deletion_cost: usize,
+    insertion_cost: usize,
+    substitution_cost: usize,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<Option<Vec<PyOpcode>>> {
+    let unit = parse_unit(unit)?;
+    let processor = parse_processor(processor)?;
+    let none_policy = parse_none_policy(none_policy)?;
+
+    let (str1, str2) = match none_policy.pair_or_empty(&processor, str1, str2)? {
+        Some(pair) => pair,
+        None => return Ok(None),
+    };
+
+    let opcodes = with_units!(unit, &str1, &str2, |a, b| {
+        let editops = levenshtein::get_weighted_levenshtein_editops(
+            a,
+            b,
+            deletion_cost,
+            insertion_cost,
+            substitution_cost,
+            );
+        levenshtein::editops_to_opcodes(&editops, a.len(), b.len())
+    });
+    Ok(Some(opcodes.iter().map(|opcode| {
+        (opcode.tag_str(), opcode.src_start, opcode.src_end, opcode.dest_start, opcode.dest_end)
+    }).collect()))
+}
+
+#[pyfunction]
+#[pyo3(signature = (editops, str1, str2, unit="byte", processor=None))]
+fn apply_editops(
+    _py: Python, 
+    editops: &PyList, 
+    str1: &PyString, 
+    str2: &PyString,
+    unit: &str,
+    processor: Option<&PyAny>,
+    ) -> PyResult<String> {
+    let unit = parse_unit(unit)?;
+    let processor = parse_processor(processor)?;
+    let editops = extract_editops(editops)?;
+    let (str1, str2) = (processor.apply(str1)?, processor.apply(str2)?);
+    let (str1, str2): (&str, &str) = (&str1, &str2);
+
+    match unit {
+        Unit::Byte => {
+            let bytes = levenshtein::apply_editops(&editops, str1.as_bytes(), str2.as_bytes()).map_err(pyo3::exceptions::PyValueError::new_err)?;
+            String::from_utf8(bytes).map_err(|_| {
+                pyo3::exceptions::PyValueError::new_err("edit operations split a multi-byte character, use unit='char'")
+            })
+        }
+        Unit::Char => {
+            let chars1: Vec<char> = str1.chars().collect();
+            let chars2: Vec<char> = str2.chars().collect();
+            let chars = levenshtein::apply_editops(&editops, &chars1, &chars2).map_err(pyo3::exceptions::PyValueError::new_err)?;
+            Ok(chars.into_iter().collect())
+        }
+        Unit::Grapheme => {
+            let graphemes1: Vec<&str> = unicode_segmentation::UnicodeSegmentation::graphemes(str1, true).collect();
+            let graphemes2: Vec<&str> = unicode_segmentation::UnicodeSegmentation::graphemes(str2, true).collect();
+            let graphemes = levenshtein::apply_editops(&editops, &graphemes1, &graphemes2).map_err(pyo3::exceptions::PyValueError::new_err)?;
+            Ok(graphemes.concat())
+        }
+    }
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1, str2, deletion_cost=1, insertion_cost=1, substitution_cost=1, transposition_cost=1, unit="byte", processor=None, none_policy="zero"))]
+fn osa_distance(
+    _py: Python, 
+    str1: Option<&PyString>, 
+    str2: Option<&PyString>,
+    deletion_cost: usize,
+    insertion_cost: usize,
+    substitution_cost: usize,
+    transposition_cost: usize,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<Option<usize>> {
+    let unit = parse_unit(unit)?;
+    let processor = parse_processor(processor)?;
+    let none_policy = parse_none_policy(none_policy)?;
+
+    let (str1, str2) = match none_policy.pair(&processor, str1, str2)? {
+        Some(pair) => pair,
+        None => return Ok(none_policy.fallback(0)),
+    };
+
+    Ok(Some(with_units!(unit, &str1, &str2, |a, b| {
+        damerau::get_osa_distance(
+            a,
+            b,
+            deletion_cost,
+            insertion_cost,
+            substitution_cost,
+            transposition_cost,
+            )
+    })))
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1_list, str2_list, deletion_cost=1, insertion_cost=1, substitution_cost=1, transposition_cost=1, unit="byte", processor=None, none_policy="zero"))]
+fn osa_distance_batched(
+    _py: Python, 
+    str1_list: Option<&PyAny>, 
+    str2_list: Option<&PyAny>,
+    deletion_cost: usize,
+    insertion_cost: usize,
+    substitution_cost: usize,
+    transposition_cost: usize,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<PyObject> {
+    let unit = parse_unit(unit)?;
+    let none_policy = parse_none_policy(none_policy)?;
+    let (str1_vec, str2_vec, missing, arrays) = extract_string_pairs(str1_list, str2_list, parse_processor(processor)?, none_policy)?;
+
+    let distances: Vec<usize> = _py.allow_threads(|| {
+        str1_vec.par_iter().zip(str2_vec.par_iter()).map(|(str1, str2)| {
+            with_units!(unit, str1, str2, |a, b| {
+                damerau::get_osa_distance(
+                    a,
+                    b,
+                    deletion_cost,
+                    insertion_cost,
+                    substitution_cost,
+                    transposition_cost,
+                )
+            })
+        }).collect()
+    });
+    optional_batch_to_py(_py, none_policy.mask_values(distances, &missing, 0), arrays, none_policy)
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1, str2, deletion_cost=1, insertion_cost=1, substitution_cost=1, transposition_cost=1, unit="byte", processor=None, none_policy="zero"))]
+fn damerau_levenshtein_distance(
+    _py: Python, 
+    str1: Option<&PyString>, 
+    str2: Option<&PyString>,
+    deletion_cost: usize,
+    insertion_cost: usize,
+    substitution_cost: usize,
+    transposition_cost: usize,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<Option<usize>> {
+    let unit = parse_unit(unit)?;
+    let processor = parse_processor(processor)?;
+    let none_policy = parse_none_policy(none_policy)?;
+
+    let (str1, str2) = match none_policy.pair(&processor, str1, str2)? {
+        Some(pair) => pair,
+        None => return Ok(none_policy.fallback(0)),
+    };
+
+    Ok(Some(with_units!(unit, &str1, &str2, |a, b| {
+        damerau::get_damerau_levenshtein_distance(
+            a,
+            b,
+            deletion_cost,
+            insertion_cost,
+            substitution_cost,
+            transposition_cost,
+            )
+    })))
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1_list, str2_list, deletion_cost=1, insertion_cost=1, substitution_cost=1, transposition_cost=1, unit="byte", processor=None, none_policy="zero"))]
+fn damerau_levenshtein_distance_batched(
+    _py: Python, 
+    str1_list: Option<&PyAny>, 
+    str2_list: Option<&PyAny>,
+    deletion_cost: usize,
+    insertion_cost: usize,
+    substitution_cost: usize,
+    transposition_cost: usize,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<PyObject> {
+    let unit = parse_unit(unit)?;
+    let none_policy = parse_none_policy(none_policy)?;
+    let (str1_vec, str2_vec, missing, arrays) = extract_string_pairs(str1_list, str2_list, parse_processor(processor)?, none_policy)?;
+
+    let distances: Vec<usize> = _py.allow_threads(|| {
+        str1_vec.par_iter().zip(str2_vec.par_iter()).map(|(str1, str2)| {
+            with_units!(unit, str1, str2, |a, b| {
+                damerau::get_damerau_levenshtein_distance(
+                    a,
+                    b,
+                    deletion_cost,
+                    insertion_cost,
+                    substitution_cost,
+                    transposition_cost,
+                )
+            })
+        }).collect()
+    });
+    optional_batch_to_py(_py, none_policy.mask_values(distances, &missing, 0), arrays, none_policy)
+}
+
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1, str2, deletion_cost=1, insertion_cost=1, substitution_cost=1, unit="byte", processor=None, none_policy="zero"))]
+fn weighted_levenshtein_normalized_distance(
+    _py: Python, 
+    str1: Option<&PyString>, 
+    str2: Option<&PyString>,
+    deletion_cost: usize,
+    insertion_cost: usize,
+    substitution_cost: usize,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<Option<f32>> {
+    let unit = parse_unit(unit)?;
+    let processor = parse_processor(processor)?;
+    let none_policy = parse_none_policy(none_policy)?;
+    let distance = EditDistance::WeightedLevenshtein {
+        deletion_cost,
+        insertion_cost,
+        substitution_cost,
+    };
+
+    let (str1, str2) = match none_policy.pair(&processor, str1, str2)? {
+        Some(pair) => pair,
+        None => return Ok(none_policy.fallback(1.0)),
+    };
+
+    Ok(Some(with_units!(unit, &str1, &str2, |a, b| {
+        distance.normalized_distance(a, b, 1.0)
+    })))
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1_list, str2_list, deletion_cost=1, insertion_cost=1, substitution_cost=1, unit="byte", processor=None, none_policy="zero"))]
+fn weighted_levenshtein_normalized_distance_batched(
+    _py: Python, 
+    str1_list: Option<&PyAny>, 
+    str2_list: Option<&PyAny>,
+    deletion_cost: usize,
+    insertion_cost: usize,
+    substitution_cost: usize,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<PyObject> {
+    let distance = EditDistance::WeightedLevenshtein {
+        deletion_cost,
+        insertion_cost,
+        substitution_cost,
+    };
+    score_pairs_batched(_py, str1_list, str2_list, Scorer::NormalizedDistance(distance), parse_unit(unit)?, parse_processor(processor)?, none_policy)
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1, str2, deletion_cost=1, insertion_cost=1, substitution_cost=1, unit="byte", processor=None, none_policy="zero"))]
+fn weighted_levenshtein_normalized_similarity(
+    _py: Python, 
+    str1: Option<&PyString>, 
+    str2: Option<&PyString>,
+    deletion_cost: usize,
+    insertion_cost: usize,
+    substitution_cost: usize,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<Option<f32>> {
+    let unit = parse_unit(unit)?;
+    let processor = parse_processor(processor)?;
+    let none_policy = parse_none_policy(none_policy)?;
+    let distance = EditDistance::WeightedLevenshtein {
+        deletion_cost,
+        insertion_cost,
+        substitution_cost,
+    };
+
+    let (str1, str2) = match none_policy.pair(&processor, str1, str2)? {
+        Some(pair) => pair,
+        None => return Ok(none_policy.fallback(0.0)),
+    };
+
+    Ok(Some(with_units!(unit, &str1, &str2, |a, b| {
+        distance.normalized_similarity(a, b, 0.0)
+    })))
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1_list, str2_list, deletion_cost=1, insertion_cost=1, substitution_cost=1, unit="byte", processor=None, none_policy="zero"))]
+fn weighted_levenshtein_normalized_similarity_batched(
+    _py: Python, 
+    str1_list: Option<&PyAny>, 
+    str2_list: Option<&PyAny>,
+    deletion_cost: usize,
+    insertion_cost: usize,
+    substitution_cost: usize,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<PyObject> {
+    let distance = EditDistance::WeightedLevenshtein {
+        deletion_cost,
+        insertion_cost,
+        substitution_cost,
+    };
+    score_pairs_batched(_py, str1_list, str2_list, Scorer::NormalizedSimilarity(distance), parse_unit(unit)?, parse_processor(processor)?, none_policy)
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1, str2, deletion_cost=1, insertion_cost=1, substitution_cost=1, transposition_cost=1, unit="byte", processor=None, none_policy="zero"))]
+fn osa_normalized_distance(
+    _py: Python, 
+    str1: Option<&PyString>, 
+    str2: Option<&PyString>,
+    deletion_cost: usize,
+    insertion_cost: usize,
+    substitution_cost: usize,
+    transposition_cost: usize,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<Option<f32>> {
+    let unit = parse_unit(unit)?;
+    let processor = parse_processor(processor)?;
+    let none_policy = parse_none_policy(none_policy)?;
+    let distance = EditDistance::Osa {
+        deletion_cost,
+        insertion_cost,
+        substitution_cost,
+        transposition_cost,
+    };
+
+    let (str1, str2) = match none_policy.pair(&processor, str1, str2)? {
+        Some(pair) => pair,
+        None => return Ok(none_policy.fallback(1.0)),
+    };
+
+    Ok(Some(with_units!(unit, &str1, &str2, |a, b| {
+        distance.normalized_distance(a, b, 1.0)
+    })))
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1_list, str2_list, deletion_cost=1, insertion_cost=1, substitution_cost=1, transposition_cost=1, unit="byte", processor=None, none_policy="zero"))]
+fn osa_normalized_distance_batched(
+    _py: Python, 
+    str1_list: Option<&PyAny>, 
+    str2_list: Option<&PyAny>,
+    deletion_cost: usize,
+    insertion_cost: usize,
+    substitution_cost: usize,
+    transposition_cost: usize,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<PyObject> {
+    let distance = EditDistance::Osa {
+        deletion_cost,
+        insertion_cost,
+        substitution_cost,
+        transposition_cost,
+    };
+    score_pairs_batched(_py, str1_list, str2_list, Scorer::NormalizedDistance(distance), parse_unit(unit)?, parse_processor(processor)?, none_policy)
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1, str2, deletion_cost=1, insertion_cost=1, substitution_cost=1, transposition_cost=1, unit="byte", processor=None, none_policy="zero"))]
+fn osa_normalized_similarity(
+    _py: Python, 
+    str1: Option<&PyString>, 
+    str2: Option<&PyString>,
+    deletion_cost: usize,
+    insertion_cost: usize,
+    substitution_cost: usize,
+    transposition_cost: usize,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<Option<f32>> {
+    let unit = parse_unit(unit)?;
+    let processor = parse_processor(processor)?;
+    let none_policy = parse_none_policy(none_policy)?;
+    let distance = EditDistance::Osa {
+        deletion_cost,
+        insertion_cost,
+        substitution_cost,
+        transposition_cost,
+    };
+
+    let (str1, str2) = match none_policy.pair(&processor, str1, str2)? {
+        Some(pair) => pair,
+        None => return Ok(none_policy.fallback(0.0)),
+    };
+
+    Ok(Some(with_units!(unit, &str1, &str2, |a, b| {
+        distance.normalized_similarity(a, b, 0.0)
+    })))
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1_list, str2_list, deletion_cost=1, insertion_cost=1, substitution_cost=1, transposition_cost=1, unit="byte", processor=None, none_policy="zero"))]
+fn osa_normalized_similarity_batched(
+    _py: Python, 
+    str1_list: Option<&PyAny>, 
+    str2_list: Option<&PyAny>,
+    deletion_cost: usize,
+    insertion_cost: usize,
+    substitution_cost: usize,
+    transposition_cost: usize,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<PyObject> {
+    let distance = EditDistance::Osa {
+        deletion_cost,
+        insertion_cost,
+        substitution_cost,
+        transposition_cost,
+    };
+    score_pairs_batched(_py, str1_list, str2_list, Scorer::NormalizedSimilarity(distance), parse_unit(unit)?, parse_processor(processor)?, none_policy)
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1, str2, deletion_cost=1, insertion_cost=1, substitution_cost=1, transposition_cost=1, unit="byte", processor=None, none_policy="zero"))]
+fn damerau_levenshtein_normalized_distance(
+    _py: Python, 
+    str1: Option<&PyString>, 
+    str2: Option<&PyString>,
+    deletion_cost: usize,
+    insertion_cost: usize,
+    substitution_cost: usize,
+    transposition_cost: usize,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<Option<f32>> {
+    let unit = parse_unit(unit)?;
+    let processor = parse_processor(processor)?;
+    let none_policy = parse_none_policy(none_policy)?;
+    let distance = EditDistance::DamerauLevenshtein {
+        deletion_cost,
+        insertion_cost,
+        substitution_cost,
+        transposition_cost,
+    };
+
+    let (str1, str2) = match none_policy.pair(&processor, str1, str2)? {
+        Some(pair) => pair,
+        None => return Ok(none_policy.fallback(1.0)),
+    };
+
+    Ok(Some(with_units!(unit, &str1, &str2, |a, b| {
+        distance.normalized_distance(a, b, 1.0)
+    })))
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1_list, str2_list, deletion_cost=1, insertion_cost=1, substitution_cost=1, transposition_cost=1, unit="byte", processor=None, none_policy="zero"))]
+fn damerau_levenshtein_normalized_distance_batched(
+    _py: Python, 
+    str1_list: Option<&PyAny>, 
+    str2_list: Option<&PyAny>,
+    deletion_cost: usize,
+    insertion_cost: usize,
+    substitution_cost: usize,
+    transposition_cost: usize,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<PyObject> {
+    let distance = EditDistance::DamerauLevenshtein {
+        deletion_cost,
+        insertion_cost,
+        substitution_cost,
+        transposition_cost,
+    };
+    score_pairs_batched(_py, str1_list, str2_list, Scorer::NormalizedDistance(distance), parse_unit(unit)?, parse_processor(processor)?, none_policy)
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1, str2, deletion_cost=1, insertion_cost=1, substitution_cost=1, transposition_cost=1, unit="byte", processor=None, none_policy="zero"))]
+fn damerau_levenshtein_normalized_similarity(
+    _py: Python, 
+    str1: Option<&PyString>, 
+    str2: Option<&PyString>,
+    deletion_cost: usize,
+    insertion_cost: usize,
+    substitution_cost: usize,
+    transposition_cost: usize,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<Option<f32>> {
+    let unit = parse_unit(unit)?;
+    let processor = parse_processor(processor)?;
+    let none_policy = parse_none_policy(none_policy)?;
+    let distance = EditDistance::DamerauLevenshtein {
+        deletion_cost,
+        insertion_cost,
+        substitution_cost,
+        transposition_cost,
+    };
+
+    let (str1, str2) = match none_policy.pair(&processor, str1, str2)? {
+        Some(pair) => pair,
+        None => return Ok(none_policy.fallback(0.0)),
+    };
+
+    Ok(Some(with_units!(unit, &str1, &str2, |a, b| {
+        distance.normalized_similarity(a, b, 0.0)
+    })))
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1_list, str2_list, deletion_cost=1, insertion_cost=1, substitution_cost=1, transposition_cost=1, unit="byte", processor=None, none_policy="zero"))]
+fn damerau_levenshtein_normalized_similarity_batched(
+    _py: Python, 
+    str1_list: Option<&PyAny>, 
+    str2_list: Option<&PyAny>,
+    deletion_cost: usize,
+    insertion_cost: usize,
+    substitution_cost: usize,
+    transposition_cost: usize,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<PyObject> {
+    let distance = EditDistance::DamerauLevenshtein {
+        deletion_cost,
+        insertion_cost,
+        substitution_cost,
+        transposition_cost,
+    };
+    score_pairs_batched(_py, str1_list, str2_list, Scorer::NormalizedSimilarity(distance), parse_unit(unit)?, parse_processor(processor)?, none_policy)
+}
+
+
+#[pyfunction]
+#[pyo3(signature = (str1, str2, unit="byte", processor=None, none_policy="zero"))]
+fn lcs_length(
+    _py: Python, 
+    str1: Option<&PyString>, 
+    str2: Option<&PyString>,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<Option<usize>> {
+    let unit = parse_unit(unit)?;
+    let processor = parse_processor(processor)?;
+    let none_policy = parse_none_policy(none_policy)?;
+
+    let (str1, str2) = match none_policy.pair(&processor, str1, str2)? {
+        Some(pair) => pair,
+        None => return Ok(none_policy.fallback(0)),
+    };
+
+    Ok(Some(with_units!(unit, &str1, &str2, |a, b| {
+        lcs::get_lcs_length(a, b)
+    })))
+}
+
+#[pyfunction]
+#[pyo3(signature = (str1_list, str2_list, unit="byte", processor=None, none_policy="zero"))]
+fn lcs_length_batched(
+    _py: Python, 
+    str1_list: Option<&PyAny>, 
+    str2_list: Option<&PyAny>,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<PyObject> {
+    let unit = parse_unit(unit)?;
+    let none_policy = parse_none_policy(none_policy)?;
+    let (str1_vec, str2_vec, missing, arrays) = extract_string_pairs(str1_list, str2_list, parse_processor(processor)?, none_policy)?;
+
+    let lengths: Vec<usize> = _py.allow_threads(|| {
+        str1_vec.par_iter().zip(str2_vec.par_iter()).map(|(str1, str2)| {
+            with_units!(unit, str1, str2, |a, b| lcs::get_lcs_length(a, b))
+        }).collect()
+    });
+    optional_batch_to_py(_py, none_policy.mask_values(lengths, &missing, 0), arrays, none_policy)
+}
+
+#[pyfunction]
+#[pyo3(signature = (str1, str2, unit="byte", processor=None, none_policy="zero"))]
+fn lcs_similarity(
+    _py: Python, 
+    str1: Option<&PyString>, 
+    str2: Option<&PyString>,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<Option<f32>> {
+    let unit = parse_unit(unit)?;
+    let processor = parse_processor(processor)?;
+    let none_policy = parse_none_policy(none_policy)?;
+
+    let (str1, str2) = match none_policy.pair(&processor, str1, str2)? {
+        Some(pair) => pair,
+        None => return Ok(none_policy.fallback(0.0)),
+    };
+
+    Ok(Some(with_units!(unit, &str1, &str2, |a, b| {
+        lcs::get_lcs_similarity(a, b)
+    })))
+}
+
+#[pyfunction]
+#[pyo3(signature = (str1_list, str2_list, unit="byte", processor=None, none_policy="zero"))]
+fn lcs_similarity_batched(
+    _py: Python, 
+    str1_list: Option<&PyAny>, 
+    str2_list: Option<&PyAny>,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<PyObject> {
+    score_pairs_batched(_py, str1_list, str2_list, Scorer::LcsSimilarity, parse_unit(unit)?, parse_processor(processor)?, none_policy)
+}
+
+#[pyfunction]
+#[pyo3(signature = (str1, str2, unit="byte", processor=None, none_policy="zero"))]
+fn indel_distance(
+    _py: Python, 
+    str1: Option<&PyString>, 
+    str2: Option<&PyString>,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<Option<usize>> {
+    let unit = parse_unit(unit)?;
+    let processor = parse_processor(processor)?;
+    let none_policy = parse_none_policy(none_policy)?;
+
+    let (str1, str2) = match none_policy.pair(&processor, str1, str2)? {
+        Some(pair) => pair,
+        None => return Ok(none_policy.fallback(0)),
+    };
+
+    Ok(Some(with_units!(unit, &str1, &str2, |a, b| {
+        lcs::get_indel_distance(a, b)
+    })))
+}
+
+#[pyfunction]
+#[pyo3(signature = (str1_list, str2_list, unit="byte", processor=None, none_policy="zero"))]
+fn indel_distance_batched(
+    _py: Python, 
+    str1_list: Option<&PyAny>, 
+    str2_list: Option<&PyAny>,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<PyObject> {
+    let unit = parse_unit(unit)?;
+    let none_policy = parse_none_policy(none_policy)?;
+    let (str1_vec, str2_vec, missing, arrays) = extract_string_pairs(str1_list, str2_list, parse_processor(processor)?, none_policy)?;
+
+    let distances: Vec<usize> = _py.allow_threads(|| {
+        str1_vec.par_iter().zip(str2_vec.par_iter()).map(|(str1, str2)| {
+            with_units!(unit, str1, str2, |a, b| lcs::get_indel_distance(a, b))
+        }).collect()
+    });
+    optional_batch_to_py(_py, none_policy.mask_values(distances, &missing, 0), arrays, none_policy)
+}
+
+#[pyfunction]
+#[pyo3(signature = (str1, str2, unit="byte", processor=None, none_policy="zero"))]
+fn indel_normalized_distance(
+    _py: Python, 
+    str1: Option<&PyString>, 
+    str2: Option<&PyString>,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<Option<f32>> {
+    let unit = parse_unit(unit)?;
+    let processor = parse_processor(processor)?;
+    let none_policy = parse_none_policy(none_policy)?;
+
+    let (str1, str2) = match none_policy.pair(&processor, str1, str2)? {
+        Some(pair) => pair,
+        None => return Ok(none_policy.fallback(1.0)),
+    };
+
+    Ok(Some(with_units!(unit, &str1, &str2, |a, b| {
+        EditDistance::Indel.normalized_distance(a, b, 1.0)
+    })))
+}
+
+#[pyfunction]
+#[pyo3(signature = (str1_list, str2_list, unit="byte", processor=None, none_policy="zero"))]
+fn indel_normalized_distance_batched(
+    _py: Python, 
+    str1_list: Option<&PyAny>, 
+    str2_list: Option<&PyAny>,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<PyObject> {
+    score_pairs_batched(_py, str1_list, str2_list, Scorer::NormalizedDistance(EditDistance::Indel), parse_unit(unit)?, parse_processor(processor)?, none_policy)
+}
+
+#[pyfunction]
+#[pyo3(signature = (str1, str2, unit="byte", processor=None, none_policy="zero"))]
+fn indel_normalized_similarity(
+    _py: Python, 
+    str1: Option<&PyString>, 
+    str2: Option<&PyString>,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<Option<f32>> {
+    let unit = parse_unit(unit)?;
+    let processor = parse_processor(processor)?;
+    let none_policy = parse_none_policy(none_policy)?;
+
+    let (str1, str2) = match none_policy.pair(&processor, str1, str2)? {
+        Some(pair) => pair,
+        None => return Ok(none_policy.fallback(0.0)),
+    };
+
+    Ok(Some(with_units!(unit, &str1, &str2, |a, b| {
+        EditDistance::Indel.normalized_similarity(a, b, 0.0)
+    })))
+}
+
+#[pyfunction]
+#[pyo3(signature = (str1_list, str2_list, unit="byte", processor=None, none_policy="zero"))]
+fn indel_normalized_similarity_batched(
+    _py: Python, 
+    str1_list: Option<&PyAny>, 
+    str2_list: Option<&PyAny>,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<PyObject> {
+    score_pairs_batched(_py, str1_list, str2_list, Scorer::NormalizedSimilarity(EditDistance::Indel), parse_unit(unit)?, parse_processor(processor)?, none_policy)
+}
+
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1, str2, q=1, pad=false, weighted=false, legacy=false, unit="byte", processor=None, none_policy="zero"))]
+fn jaccard_similarity(
+    _py: Python, 
+    str1: Option<&PyString>, 
+    str2: Option<&PyString>,
+    q: usize,
+    pad: bool,
+    weighted: bool,
+    legacy: bool,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<Option<f32>> {
+    let unit = parse_unit(unit)?;
+    let processor = parse_processor(processor)?;
+    let none_policy = parse_none_policy(none_policy)?;
+    let scorer = qgram_scorer(SetMeasure::Jaccard, q, pad, weighted, legacy)?;
+
+    let (str1, str2) = match none_policy.pair(&processor, str1, str2)? {
+        Some(pair) => pair,
+        None => return Ok(none_policy.fallback(0.0)),
+    };
+
+    Ok(Some(with_units!(unit, &str1, &str2, |a, b| {
+        scorer.score(a, b, None)
+    })))
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1_list, str2_list, q=1, pad=false, weighted=false, legacy=false, unit="byte", processor=None, none_policy="zero"))]
+fn jaccard_similarity_batched(
+    _py: Python, 
+    str1_list: Option<&PyAny>, 
+    str2_list: Option<&PyAny>,
+    q: usize,
+    pad: bool,
+    weighted: bool,
+    legacy: bool,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<PyObject> {
+    let scorer = qgram_scorer(SetMeasure::Jaccard, q, pad, weighted, legacy)?;
+    score_pairs_batched(_py, str1_list, str2_list, scorer, parse_unit(unit)?, parse_processor(processor)?, none_policy)
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1, str2, q=1, pad=false, weighted=false, unit="byte", processor=None, none_policy="zero"))]
+fn sorensen_dice_similarity(
+    _py: Python, 
+    str1: Option<&PyString>, 
+    str2: Option<&PyString>,
+    q: usize,
+    pad: bool,
+    weighted: bool,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<Option<f32>> {
+    let unit = parse_unit(unit)?;
+    let processor = parse_processor(processor)?;
+    let none_policy = parse_none_policy(none_policy)?;
+    let scorer = qgram_scorer(SetMeasure::SorensenDice, q, pad, weighted, false)?;
+
+    let (str1, str2) = match none_policy.pair(&processor, str1, str2)? {
+        Some(pair) => pair,
+        None => return Ok(none_policy.fallback(0.0)),
+    };
+
+    Ok(Some(with_units!(unit, &str1, &str2, |a, b| {
+        scorer.score(a, b, None)
+    })))
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1_list, str2_list, q=1, pad=false, weighted=false, unit="byte", processor=None, none_policy="zero"))]
+fn sorensen_dice_similarity_batched(
+    _py: Python, 
+    str1_list: Option<&PyAny>, 
+    str2_list: Option<&PyAny>,
+    q: usize,
+    pad: bool,
+    weighted: bool,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<PyObject> {
+    let scorer = qgram_scorer(SetMeasure::SorensenDice, q, pad, weighted, false)?;
+    score_pairs_batched(_py, str1_list, str2_list, scorer, parse_unit(unit)?, parse_processor(processor)?, none_policy)
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1, str2, q=1, pad=false, weighted=false, unit="byte", processor=None, none_policy="zero"))]
+fn overlap_coefficient(
+    _py: Python, 
+    str1: Option<&PyString>, 
+    str2: Option<&PyString>,
+    q: usize,
+    pad: bool,
+    weighted: bool,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<Option<f32>> {
+    let unit = parse_unit(unit)?;
+    let processor = parse_processor(processor)?;
+    let none_policy = parse_none_policy(none_policy)?;
+    let scorer = qgram_scorer(SetMeasure::Overlap, q, pad, weighted, false)?;
+
+    let (str1, str2) = match none_policy.pair(&processor, str1, str2)? {
+        Some(pair) => pair,
+        None => return Ok(none_policy.fallback(0.0)),
+    };
+
+    Ok(Some(with_units!(unit, &str1, &str2, |a, b| {
+        scorer.score(a, b, None)
+    })))
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1_list, str2_list, q=1, pad=false, weighted=false, unit="byte", processor=None, none_policy="zero"))]
+fn overlap_coefficient_batched(
+    _py: Python, 
+    str1_list: Option<&PyAny>, 
+    str2_list: Option<&PyAny>,
+    q: usize,
+    pad: bool,
+    weighted: bool,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<PyObject> {
+    let scorer = qgram_scorer(SetMeasure::Overlap, q, pad, weighted, false)?;
+    score_pairs_batched(_py, str1_list, str2_list, scorer, parse_unit(unit)?, parse_processor(processor)?, none_policy)
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1, str2, alpha=1.0, beta=1.0, q=1, pad=false, weighted=false, unit="byte", processor=None, none_policy="zero"))]
+fn tversky_similarity(
+    _py: Python, 
+    str1: Option<&PyString>, 
+    str2: Option<&PyString>,
+    alpha: f32,
+    beta: f32,
+    q: usize,
+    pad: bool,
+    weighted: bool,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<Option<f32>> {
+    let unit = parse_unit(unit)?;
+    let processor = parse_processor(processor)?;
+    let none_policy = parse_none_policy(none_policy)?;
+    let measure = SetMeasure::tversky(alpha, beta).map_err(pyo3::exceptions::PyValueError::new_err)?;
+    let scorer = qgram_scorer(measure, q, pad, weighted, false)?;
+
+    let (str1, str2) = match none_policy.pair(&processor, str1, str2)? {
+        Some(pair) => pair,
+        None => return Ok(none_policy.fallback(0.0)),
+    };
+
+    Ok(Some(with_units!(unit, &str1, &str2, |a, b| {
+        scorer.score(a, b, None)
+    })))
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1_list, str2_list, alpha=1.0, beta=1.0, q=1, pad=false, weighted=false, unit="byte", processor=None, none_policy="zero"))]
+fn tversky_similarity_batched(
+    _py: Python, 
+    str1_list: Option<&PyAny>, 
+    str2_list: Option<&PyAny>,
+    alpha: f32,
+    beta: f32,
+    q: usize,
+    pad: bool,
+    weighted: bool,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<PyObject> {
+    let measure = SetMeasure::tversky(alpha, beta).map_err(pyo3::exceptions::PyValueError::new_err)?;
+    let scorer = qgram_scorer(measure, q, pad, weighted, false)?;
+    score_pairs_batched(_py, str1_list, str2_list, scorer, parse_unit(unit)?, parse_processor(processor)?, none_policy)
+}
+
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1, str2, tokenizer="whitespace", pattern=None, unit="byte", processor=None, none_policy="zero"))]
+fn token_jaccard_similarity(
+    _py: Python, 
+    str1: Option<&PyString>, 
+    str2: Option<&PyString>,
+    tokenizer: &str,
+    pattern: Option<&str>,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<Option<f32>> {
+    let unit = parse_unit(unit)?;
+    let processor = parse_processor(processor)?;
+    let none_policy = parse_none_policy(none_policy)?;
+    let scorer = TokenSimilarity {
+        tokenizer: parse_tokenizer(tokenizer, pattern)?,
+        measure: TokenMeasure::Set(SetMeasure::Jaccard),
+    };
+
+    let (str1, str2) = match none_policy.pair(&processor, str1, str2)? {
+        Some(pair) => pair,
+        None => return Ok(none_policy.fallback(0.0)),
+    };
+
+    Ok(Some(with_units!(unit, &str1, &str2, |a, b| {
+        scorer.similarity(a, b)
+    })))
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1_list, str2_list, tokenizer="whitespace", pattern=None, unit="byte", processor=None, none_policy="zero"))]
+fn token_jaccard_similarity_batched(
+    _py: Python, 
+    str1_list: Option<&PyAny>, 
+    str2_list: Option<&PyAny>,
+    tokenizer: &str,
+    pattern: Option<&str>,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<PyObject> {
+    let scorer = Scorer::Token(TokenSimilarity {
+        tokenizer: parse_tokenizer(tokenizer, pattern)?,
+        measure: TokenMeasure::Set(SetMeasure::Jaccard),
+    });
+    score_pairs_batched(_py, str1_list, str2_list, scorer, parse_unit(unit)?, parse_processor(processor)?, none_policy)
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1, str2, tokenizer="whitespace", pattern=None, unit="byte", processor=None, none_policy="zero"))]
+fn token_dice_similarity(
+    _py: Python, 
+    str1: Option<&PyString>, 
+    str2: Option<&PyString>,
+    tokenizer: &str,
+    pattern: Option<&str>,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<Option<f32>> {
+    let unit = parse_unit(unit)?;
+    let processor = parse_processor(processor)?;
+    let none_policy = parse_none_policy(none_policy)?;
+    let scorer = TokenSimilarity {
+        tokenizer: parse_tokenizer(tokenizer, pattern)?,
+        measure: TokenMeasure::Set(SetMeasure::SorensenDice),
+    };
+
+    let (str1, str2) = match none_policy.pair(&processor, str1, str2)? {
+        Some(pair) => pair,
+        None => return Ok(none_policy.fallback(0.0)),
+    };
+
+    Ok(Some(with_units!(unit, &str1, &str2, |a, b| {
+        scorer.similarity(a, b)
+    })))
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1_list, str2_list, tokenizer="whitespace", pattern=None, unit="byte", processor=None, none_policy="zero"))]
+fn token_dice_similarity_batched(
+    _py: Python, 
+    str1_list: Option<&PyAny>, 
+    str2_list: Option<&PyAny>,
+    tokenizer: &str,
+    pattern: Option<&str>,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<PyObject> {
+    let scorer = Scorer::Token(TokenSimilarity {
+        tokenizer: parse_tokenizer(tokenizer, pattern)?,
+        measure: TokenMeasure::Set(SetMeasure::SorensenDice),
+    });
+    score_pairs_batched(_py, str1_list, str2_list, scorer, parse_unit(unit)?, parse_processor(processor)?, none_policy)
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1, str2, tokenizer="whitespace", pattern=None, unit="byte", processor=None, none_policy="zero"))]
+fn token_cosine_similarity(
+    _py: Python, 
+    str1: Option<&PyString>, 
+    str2: Option<&PyString>,
+    tokenizer: &str,
+    pattern: Option<&str>,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<Option<f32>> {
+    let unit = parse_unit(unit)?;
+    let processor = parse_processor(processor)?;
+    let none_policy = parse_none_policy(none_policy)?;
+    let scorer = TokenSimilarity {
+        tokenizer: parse_tokenizer(tokenizer, pattern)?,
+        measure: TokenMeasure::Set(SetMeasure::Cosine),
+    };
+
+    let (str1, str2) = match none_policy.pair(&processor, str1, str2)? {
+        Some(pair) => pair,
+        None => return Ok(none_policy.fallback(0.0)),
+    };
+
+    Ok(Some(with_units!(unit, &str1, &str2, |a, b| {
+        scorer.similarity(a, b)
+    })))
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1_list, str2_list, tokenizer="whitespace", pattern=None, unit="byte", processor=None, none_policy="zero"))]
+fn token_cosine_similarity_batched(
+    _py: Python, 
+    str1_list: Option<&PyAny>, 
+    str2_list: Option<&PyAny>,
+    tokenizer: &str,
+    pattern: Option<&str>,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<PyObject> {
+    let scorer = Scorer::Token(TokenSimilarity {
+        tokenizer: parse_tokenizer(tokenizer, pattern)?,
+        measure: TokenMeasure::Set(SetMeasure::Cosine),
+    });
+    score_pairs_batched(_py, str1_list, str2_list, scorer, parse_unit(unit)?, parse_processor(processor)?, none_policy)
+}
+
+/// Collects the keyword arguments of `monge_elkan_similarity` into the
+/// parameters `parse_monge_elkan` expects.
+fn monge_elkan_params<'py>(
+    py: Python<'py>,
+    inner: &str,
+    tokenizer: &str,
+    pattern: Option<&str>,
+    params: Option<&'py PyDict>,
+    ) -> PyResult<&'py PyDict> {
+    let all_params = match params {
+        Some(params) => params.copy()?,
+        None => PyDict::new(py),
+    };
+    all_params.set_item("inner", inner)?;
+    all_params.set_item("tokenizer", tokenizer)?;
+    if let Some(pattern) = pattern {
+        all_params.set_item("pattern", pattern)?;
+    }
+    Ok(all_params)
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1, str2, inner="jaro_winkler", tokenizer="whitespace", pattern=None, unit="byte", processor=None, none_policy="zero", **params))]
+fn monge_elkan_similarity(
+    _py: Python, 
+    str1: Option<&PyString>, 
+    str2: Option<&PyString>,
+    inner: &str,
+    tokenizer: &str,
+    pattern: Option<&str>,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    params: Option<&PyDict>,
+    ) -> PyResult<Option<f32>> {
+    let unit = parse_unit(unit)?;
+    let processor = parse_processor(processor)?;
+    let none_policy = parse_none_policy(none_policy)?;
+    let scorer = parse_monge_elkan(Some(monge_elkan_params(_py, inner, tokenizer, pattern, params)?))?;
+
+    let (str1, str2) = match none_policy.pair(&processor, str1, str2)? {
+        Some(pair) => pair,
+        None => return Ok(none_policy.fallback(0.0)),
+    };
+
+    Ok(Some(with_units!(unit, &str1, &str2, |a, b| {
+        scorer.score(a, b, None)
+    })))
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (str1_list, str2_list, inner="jaro_winkler", tokenizer="whitespace", pattern=None, unit="byte", processor=None, none_policy="zero", **params))]
+fn monge_elkan_similarity_batched(
+    _py: Python, 
+    str1_list: Option<&PyAny>, 
+    str2_list: Option<&PyAny>,
+    inner: &str,
+    tokenizer: &str,
+    pattern: Option<&str>,
+    unit: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    params: Option<&PyDict>,
+    ) -> PyResult<PyObject> {
+    let scorer = parse_monge_elkan(Some(monge_elkan_params(_py, inner, tokenizer, pattern, params)?))?;
+    score_pairs_batched(_py, str1_list, str2_list, scorer, parse_unit(unit)?, parse_processor(processor)?, none_policy)
+}
+
+/// Generates the single and batched pyfunction of each fuzz ratio.
+macro_rules! fuzz_functions {
+    ($($fuzz:ident => $single:ident, $batched:ident;)*) => {
+        $(
+            #[pyfunction]
+            #[pyo3(signature = (str1, str2, unit="byte", processor=ProcessorArg::Default, none_policy="zero"))]
+            fn $single(
+                _py: Python, 
+                str1: Option<&PyString>, 
+                str2: Option<&PyString>,
+                unit: &str,
+                processor: ProcessorArg,
+                none_policy: &str,
+                ) -> PyResult<Option<f32>> {
+                let unit = parse_unit(unit)?;
+                let processor = processor.resolve(Fuzz::$fuzz.default_processor())?;
+                let none_policy = parse_none_policy(none_policy)?;
+
+                let (str1, str2) = match none_policy.pair(&processor, str1, str2)? {
+                    Some(pair) => pair,
+                    None => return Ok(none_policy.fallback(0.0)),
+                };
+
+                Ok(Some(with_units!(unit, &str1, &str2, |a, b| Fuzz::$fuzz.score(a, b))))
+            }
+
+            #[pyfunction]
+            #[pyo3(signature = (str1_list, str2_list, unit="byte", processor=ProcessorArg::Default, none_policy="zero"))]
+            fn $batched(
+                _py: Python, 
+                str1_list: Option<&PyAny>, 
+                str2_list: Option<&PyAny>,
+                unit: &str,
+                processor: ProcessorArg,
+                none_policy: &str,
+                ) -> PyResult<PyObject> {
+                let processor = processor.resolve(Fuzz::$fuzz.default_processor())?;
+                score_pairs_batched(_py, str1_list, str2_list, Scorer::Fuzz(Fuzz::$fuzz), parse_unit(unit)?, processor, none_policy)
+            }
+        )*
+    };
+}
+
+fuzz_functions! {
+    Ratio => ratio, ratio_batched;
+    PartialRatio => partial_ratio, partial_ratio_batched;
+    TokenSortRatio => token_sort_ratio, token_sort_ratio_batched;
+    TokenSetRatio => token_set_ratio, token_set_ratio_batched;
+    TokenRatio => token_ratio, token_ratio_batched;
+    PartialTokenSortRatio => partial_token_sort_ratio, partial_token_sort_ratio_batched;
+    PartialTokenSetRatio => partial_token_set_ratio, partial_token_set_ratio_batched;
+    PartialTokenRatio => partial_token_ratio, partial_token_ratio_batched;
+    WRatio => wratio, wratio_batched;
+}
+
+
+/// Encodes a Python list in parallel with the GIL released. A None is
+/// encoded as the empty string, which has empty keys.
+fn encode_batched<T: Send + Clone + Default>(
+    _py: Python, 
+    str_list: Option<&PyAny>,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    encode: fn(&str) -> T,
+    ) -> PyResult<Vec<Option<T>>> {
+    let processor = parse_processor(processor)?;
+    let none_policy = parse_none_policy(none_policy)?;
+    let (strings, missing) = match str_list {
+        Some(str_list) => processor.apply_all(str_list, "str_list", none_policy)?,
+        None => return Err(pyo3::exceptions::PyValueError::new_err("str_list must be a list or array")),
+    };
+    let keys = _py.allow_threads(|| strings.par_iter().map(|string| encode(string)).collect());
+    Ok(none_policy.mask_values(keys, &missing, T::default()))
+}
+
+/// Encodes a single string. A None has empty keys unless the policy says
+/// otherwise.
+fn encode_one<T: Default>(
+    string: Option<&PyString>,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    encode: fn(&str) -> T,
+    ) -> PyResult<Option<T>> {
+    let processor = parse_processor(processor)?;
+    let none_policy = parse_none_policy(none_policy)?;
+    match string {
+        Some(string) => Ok(Some(encode(&processor.apply(string)?))),
+        None if none_policy == NonePolicy::Raise => Err(none_error("string")),
+        None => Ok(none_policy.fallback(T::default())),
+    }
+}
+
+fn parse_phonetic_algorithm(algorithm: &str) -> PyResult<PhoneticAlgorithm> {
+    algorithm.parse::<PhoneticAlgorithm>().map_err(pyo3::exceptions::PyValueError::new_err)
+}
+
+#[pyfunction]
+#[pyo3(signature = (string, processor=None, none_policy="zero"))]
+fn soundex(
+    string: Option<&PyString>,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<Option<String>> {
+    encode_one(string, processor, none_policy, phonetic::soundex)
+}
+
+#[pyfunction]
+#[pyo3(signature = (str_list, processor=None, none_policy="zero"))]
+fn soundex_batched(
+    _py: Python, 
+    str_list: Option<&PyAny>,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<Vec<Option<String>>> {
+    encode_batched(_py, str_list, processor, none_policy, phonetic::soundex)
+}
+
+#[pyfunction]
+#[pyo3(signature = (string, processor=None, none_policy="zero"))]
+fn refined_soundex(
+    string: Option<&PyString>,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<Option<String>> {
+    encode_one(string, processor, none_policy, phonetic::refined_soundex)
+}
+
+#[pyfunction]
+#[pyo3(signature = (str_list, processor=None, none_policy="zero"))]
+fn refined_soundex_batched(
+    _py: Python, 
+    str_list: Option<&PyAny>,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<Vec<Option<String>>> {
+    encode_batched(_py, str_list, processor, none_policy, phonetic::refined_soundex)
+}
+
+#[pyfunction]
+#[pyo3(signature = (string, processor=None, none_policy="zero"))]
+fn metaphone(
+    string: Option<&PyString>,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<Option<String>> {
+    encode_one(string, processor, none_policy, phonetic::metaphone)
+}
+
+#[pyfunction]
+#[pyo3(signature = (str_list, processor=None, none_policy="zero"))]
+fn metaphone_batched(
+    _py: Python, 
+    str_list: Option<&PyAny>,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<Vec<Option<String>>> {
+    encode_batched(_py, str_list, processor, none_policy, phonetic::metaphone)
+}
+
+/// Returns the primary and alternate keys.
+#[pyfunction]
+#[pyo3(signature = (string, processor=None, none_policy="zero"))]
+fn double_metaphone(
+    string: Option<&PyString>,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<Option<(String, String)>> {
+    encode_one(string, processor, none_policy, phonetic::double_metaphone)
+}
+
+#[pyfunction]
+#[pyo3(signature = (str_list, processor=None, none_policy="zero"))]
+fn double_metaphone_batched(
+    _py: Python, 
+    str_list: Option<&PyAny>,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<Vec<Option<(String, String)>>> {
+    encode_batched(_py, str_list, processor, none_policy, phonetic::double_metaphone)
+}
+
+#[pyfunction]
+#[pyo3(signature = (string, processor=None, none_policy="zero"))]
+fn nysiis(
+    string: Option<&PyString>,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<Option<String>> {
+    encode_one(string, processor, none_policy, phonetic::nysiis)
+}
+
+#[pyfunction]
+#[pyo3(signature = (str_list, processor=None, none_policy="zero"))]
+fn nysiis_batched(
+    _py: Python, 
+    str_list: Option<&PyAny>,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<Vec<Option<String>>> {
+    encode_batched(_py, str_list, processor, none_policy, phonetic::nysiis)
+}
+
+/// Whether the two strings share a phonetic key, for blocking candidates
+/// before a more expensive metric.
+#[pyfunction]
+#[pyo3(signature = (str1, str2, algorithm="soundex", processor=None, none_policy="zero"))]
+fn phonetic_match(
+    str1: Option<&PyString>, 
+    str2: Option<&PyString>,
+    algorithm: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<Option<bool>> {
+    let algorithm = parse_phonetic_algorithm(algorithm)?;
+    let processor = parse_processor(processor)?;
+    let none_policy = parse_none_policy(none_policy)?;
+
+    // a None has nothing to match
+    let (str1, str2) = match none_policy.pair(&processor, str1, str2)? {
+        Some(pair) => pair,
+        None => return Ok(none_policy.fallback(false)),
+    };
+
+    Ok(Some(algorithm.is_match(&str1, &str2)))
+}
+
+#[pyfunction]
+#[pyo3(signature = (str1_list, str2_list, algorithm="soundex", processor=None, none_policy="zero"))]
+fn phonetic_match_batched(
+    _py: Python, 
+    str1_list: Option<&PyAny>, 
+    str2_list: Option<&PyAny>,
+    algorithm: &str,
+    processor: Option<&PyAny>,
+    none_policy: &str,
+    ) -> PyResult<PyObject> {
+    let algorithm = parse_phonetic_algorithm(algorithm)?;
+    let none_policy = parse_none_policy(none_policy)?;
+    let (str1_vec, str2_vec, missing, arrays) = extract_string_pairs(str1_list, str2_list, parse_processor(processor)?, none_policy)?;
+
+    let matches = _py.allow_threads(|| {
+        str1_vec.par_iter().zip(str2_vec.par_iter()).map(|(str1, str2)| algorithm.is_match(str1, str2)).collect()
+    });
+    optional_batch_to_py(_py, none_policy.mask_values(matches, &missing, false), arrays, none_policy)
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (queries, choices, metric="jaro_winkler", unit="byte", processor=ProcessorArg::Default, score_cutoff=None, workers=-1, none_policy="zero", **params))]
+fn cdist<'py>(
+    _py: Python<'py>, 
+    queries: Option<&PyAny>, 
+    choices: Option<&PyAny>,
+    metric: &str,
+    unit: &str,
+    processor: ProcessorArg,
+    score_cutoff: Option<f32>,
+    workers: i64,
+    none_policy: &str,
+    params: Option<&PyDict>,
+    ) -> PyResult<&'py PyAny> {
+    let unit = parse_unit(unit)?;
+    let none_policy = parse_none_policy(none_policy)?;
+    let scorer = parse_scorer(metric, params)?;
+    scorer.check_unit(unit).map_err(pyo3::exceptions::PyValueError::new_err)?;
+
+    if queries.is_none() || choices.is_none() {
+        return Err(pyo3::exceptions::PyValueError::new_err("queries and choices must be lists or arrays"));
+    }
+    let processor = processor.resolve(scorer.default_processor())?;
+    let (queries, missing_queries) = processor.apply_all(queries.unwrap(), "queries", none_policy)?;
+    let (choices, missing_choices) = processor.apply_all(choices.unwrap(), "choices", none_policy)?;
+    let shape = (queries.len(), choices.len());
+
+    let none_score = scorer.none_score();
+    let score = |query: usize, choice: usize, score: f32| {
+        none_policy.mask_score(score, missing_queries[query] || missing_choices[choice], none_score)
+    };
+    let cdist = CdistArgs { queries: &queries, choices: &choices, scorer: &scorer, unit, score_cutoff, workers };
+    // propagated Nones need a float matrix to be NaN in
+    if scorer.is_integer() && none_policy != NonePolicy::Propagate {
+        cdist.to_numpy(_py, shape, |query, choice, distance| score(query, choice, distance) as u32)
+    } else {
+        cdist.to_numpy(_py, shape, score)
+    }
+}
+
+/// The arguments of a `cdist` call, once processed.
+struct CdistArgs<'a, 'py> {
+    queries: &'a [Cow<'py, str>],
+    choices: &'a [Cow<'py, str>],
+    scorer: &'a Scorer,
+    unit: Unit,
+    score_cutoff: Option<f32>,
+    workers: i64,
+}
+
+impl CdistArgs<'_, '_> {
+    /// Scores every pair straight into a NumPy matrix of `E`, converting
+    /// each score with `cell(query, choice, score)`.
+    fn to_numpy<'py, E: NumpyElement + Element + Send>(
+        &self,
+        py: Python<'py>,
+        shape: (usize, usize),
+        cell: impl Fn(usize, usize, f32) -> E + Sync + Send,
+        ) -> PyResult<&'py PyAny> {
+        numpy_matrix_with(py, shape, |matrix: &mut [E]| {
+            with_workers(self.workers, || {
+                process::cdist_into(self.queries, self.choices, self.scorer, self.unit, self.score_cutoff, matrix, cell)
+            })
+        })
+    }
+}
+
+
+
+/// Converts a score back to the Python type of its metric, `int` for raw
+/// distances and `float` for everything else.
+fn score_to_py(py: Python, scorer: &Scorer, score: f32) -> PyObject {
+    if scorer.is_integer() {
+        (score as u32).into_py(py)
+    } else {
+        score.into_py(py)
+    }
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (query, choices, metric="jaro_winkler", limit=5, score_cutoff=None, unit="byte", processor=ProcessorArg::Default, workers=-1, none_policy="zero", **params))]
+fn extract(
+    _py: Python, 
+    query: Option<&PyString>, 
+    choices: Option<&PyAny>,
+    metric: &str,
+    limit: Option<usize>,
+    score_cutoff: Option<f32>,
+    unit: &str,
+    processor: ProcessorArg,
+    workers: i64,
+    none_policy: &str,
+    params: Option<&PyDict>,
+    ) -> PyResult<Option<Vec<(PyObject, PyObject, usize)>>> {
+    let unit = parse_unit(unit)?;
+    let none_policy = parse_none_policy(none_policy)?;
+    let scorer = parse_scorer(metric, params)?;
+    scorer.check_unit(unit).map_err(pyo3::exceptions::PyValueError::new_err)?;
+
+    let choices = match choices {
+        Some(choices) => choices,
+        None => return Err(pyo3::exceptions::PyValueError::new_err("query must be a string and choices a list or array")),
+    };
+    // a None query matches nothing
+    let query = match query {
+        Some(query) => query,
+        None if none_policy == NonePolicy::Raise => return Err(none_error("query")),
+        None => return Ok(none_policy.fallback(vec![])),
+    };
+    let processor = processor.resolve(scorer.default_processor())?;
+    let query = processor.apply_item(query)?;
+    let (choice_vec, missing) = processor.apply_all(choices, "choices", none_policy)?;
+
+    // None choices have no score to rank them by, so they are left out
+    let (indices, choice_vec): (Vec<usize>, Vec<Cow<str>>) = choice_vec.into_iter()
+        .enumerate()
+        .filter(|&(index, _)| !missing[index])
+        .unzip();
+
+    let best = _py.allow_threads(|| {
+        with_workers(workers, || process::extract(&query, &choice_vec, &scorer, unit, limit, score_cutoff))
+    })?;
+
+    best.into_iter().map(|(index, score)| {
+        let index = indices[index];
+        // pandas indexes by label, so go by position, and Arrow arrays
+        // return scalars, which `as_py` turns back into a str
+        let mut choice = if choices.hasattr("iloc")? {
+            choices.getattr("iloc")?.get_item(index)?
+        } else {
+            choices.get_item(index)?
+        };
+        if choice.hasattr("as_py")? {
+            choice = choice.call_method0("as_py")?;
+        }
+        Ok((choice.into_py(_py), score_to_py(_py, &scorer, score), index))
+    }).collect::<PyResult<_>>().map(Some)
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (query, choices, metric="jaro_winkler", score_cutoff=None, unit="byte", processor=ProcessorArg::Default, workers=-1, none_policy="zero", **params))]
+fn extract_one(
+    _py: Python, 
+    query: Option<&PyString>, 
+    choices: Option<&PyAny>,
+    metric: &str,
+    score_cutoff: Option<f32>,
+    unit: &str,
+    processor: ProcessorArg,
+    workers: i64,
+    none_policy: &str,
+    params: Option<&PyDict>,
+    ) -> PyResult<Option<(PyObject, PyObject, usize)>> {
+    let best = extract(_py, query, choices, metric, Some(1), score_cutoff, unit, processor, workers, none_policy, params)?;
+    Ok(best.and_then(|best| best.into_iter().next()))
+}
+
+/// `None`, `'qgram'` or `'prefix'`, blocking on q-grams or prefixes of
+/// `length` units.
+fn parse_blocking(blocking: Option<&str>, length: usize) -> PyResult<Blocking> {
+    match blocking {
+        None | Some("none") => Ok(Blocking::None),
+        Some("qgram") => Ok(Blocking::QGram(length)),
+        Some("prefix") => Ok(Blocking::Prefix(length)),
+        Some(blocking) => Err(pyo3::exceptions::PyValueError::new_err(format!(
+            "blocking must be one of None, 'qgram' or 'prefix', got '{}'",
+            blocking,
+        ))),
+    }
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (strings, metric="jaro_winkler", threshold=0.9, blocking=None, blocking_length=3, unit="byte", processor=ProcessorArg::Default, workers=-1, none_policy="zero", **params))]
+fn dedupe(
+    _py: Python, 
+    strings: Option<&PyAny>, 
+    metric: &str,
+    threshold: f32,
+    blocking: Option<&str>,
+    blocking_length: usize,
+    unit: &str,
+    processor: ProcessorArg,
+    workers: i64,
+    none_policy: &str,
+    params: Option<&PyDict>,
+    ) -> PyResult<PyObject> {
+    let unit = parse_unit(unit)?;
+    let none_policy = parse_none_policy(none_policy)?;
+    let scorer = parse_scorer(metric, params)?;
+    scorer.check_unit(unit).map_err(pyo3::exceptions::PyValueError::new_err)?;
+    let blocking = parse_blocking(blocking, blocking_length)?;
+
+    let strings = strings.ok_or_else(|| pyo3::exceptions::PyValueError::new_err("strings must be a list or array"))?;
+    let arrays = arrays::is_array(strings)?;
+    let (strings, missing) = processor.resolve(scorer.default_processor())?.apply_all(strings, "strings", none_policy)?;
+    let present: Vec<&str> = strings.iter().zip(&missing).filter(|(_, &missing)| !missing).map(|(string, _)| string.as_ref()).collect();
+
+    let labels: Vec<usize> = _py.allow_threads(|| {
+        with_workers(workers, || clusters::dedupe(&present, &scorer, unit, threshold, blocking))
+    })?;
+
+    // a None matches nothing, so it is a cluster of its own unless propagated
+    let mut labels = labels.into_iter();
+    let mut n_clusters = 0;
+    let mut renumbered: HashMap<usize, usize> = HashMap::new();
+    let mut next_id = || {
+        n_clusters += 1;
+        n_clusters - 1
+    };
+    let ids: Vec<Option<usize>> = missing.iter().map(|&missing| match (missing, none_policy) {
+        (true, NonePolicy::Propagate) => None,
+        (true, _) => Some(next_id()),
+        (false, _) => labels.next().map(|label| *renumbered.entry(label).or_insert_with(&mut next_id)),
+    }).collect();
+    optional_batch_to_py(_py, ids, arrays, none_policy)
+}
+
+
+
+#[pymodule]
+fn string_sim_metrics(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(jaro_winkler_similarity, m)?)?;
+    m.add_function(wrap_pyfunction!(weighted_levenshtein_distance, m)?)?;
+    m.add_function(wrap_pyfunction!(jaccard_similarity, m)?)?;
+    m.add_function(wrap_pyfunction!(jaro_winkler_similarity_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(weighted_levenshtein_distance_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(jaccard_similarity_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(weighted_levenshtein_editops, m)?)?;
+    m.add_function(wrap_pyfunction!(weighted_levenshtein_opcodes, m)?)?;
+    m.add_function(wrap_pyfunction!(apply_editops, m)?)?;
+    m.add_function(wrap_pyfunction!(osa_distance, m)?)?;
+    m.add_function(wrap_pyfunction!(osa_distance_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(damerau_levenshtein_distance, m)?)?;
+    m.add_function(wrap_pyfunction!(damerau_levenshtein_distance_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(weighted_levenshtein_normalized_distance, m)?)?;
+    m.add_function(wrap_pyfunction!(weighted_levenshtein_normalized_distance_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(weighted_levenshtein_normalized_similarity, m)?)?;
+    m.add_function(wrap_pyfunction!(weighted_levenshtein_normalized_similarity_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(osa_normalized_distance, m)?)?;
+    m.add_function(wrap_pyfunction!(osa_normalized_distance_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(osa_normalized_similarity, m)?)?;
+    m.add_function(wrap_pyfunction!(osa_normalized_similarity_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(damerau_levenshtein_normalized_distance, m)?)?;
+    m.add_function(wrap_pyfunction!(damerau_levenshtein_normalized_distance_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(damerau_levenshtein_normalized_similarity, m)?)?;
+    m.add_function(wrap_pyfunction!(damerau_levenshtein_normalized_similarity_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(lcs_length, m)?)?;
+    m.add_function(wrap_pyfunction!(lcs_length_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(lcs_similarity, m)?)?;
+    m.add_function(wrap_pyfunction!(lcs_similarity_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(indel_distance, m)?)?;
+    m.add_function(wrap_pyfunction!(indel_distance_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(indel_normalized_distance, m)?)?;
+    m.add_function(wrap_pyfunction!(indel_normalized_distance_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(indel_normalized_similarity, m)?)?;
+    m.add_function(wrap_pyfunction!(indel_normalized_similarity_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(sorensen_dice_similarity, m)?)?;
+    m.add_function(wrap_pyfunction!(sorensen_dice_similarity_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(overlap_coefficient, m)?)?;
+    m.add_function(wrap_pyfunction!(overlap_coefficient_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(tversky_similarity, m)?)?;
+    m.add_function(wrap_pyfunction!(tversky_similarity_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(token_jaccard_similarity, m)?)?;
+    m.add_function(wrap_pyfunction!(token_jaccard_similarity_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(token_dice_similarity, m)?)?;
+    m.add_function(wrap_pyfunction!(token_dice_similarity_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(token_cosine_similarity, m)?)?;
+    m.add_function(wrap_pyfunction!(token_cosine_similarity_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(monge_elkan_similarity, m)?)?;
+    m.add_function(wrap_pyfunction!(monge_elkan_similarity_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(ratio, m)?)?;
+    m.add_function(wrap_pyfunction!(ratio_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(partial_ratio, m)?)?;
+    m.add_function(wrap_pyfunction!(partial_ratio_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(token_sort_ratio, m)?)?;
+    m.add_function(wrap_pyfunction!(token_sort_ratio_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(token_set_ratio, m)?)?;
+    m.add_function(wrap_pyfunction!(token_set_ratio_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(token_ratio, m)?)?;
+    m.add_function(wrap_pyfunction!(token_ratio_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(partial_token_sort_ratio, m)?)?;
+    m.add_function(wrap_pyfunction!(partial_token_sort_ratio_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(partial_token_set_ratio, m)?)?;
+    m.add_function(wrap_pyfunction!(partial_token_set_ratio_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(partial_token_ratio, m)?)?;
+    m.add_function(wrap_pyfunction!(partial_token_ratio_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(wratio, m)?)?;
+    m.add_function(wrap_pyfunction!(wratio_batched, m)?)?;
+    m.add("WRatio", m.getattr("wratio")?)?;
+    m.add("WRatio_batched", m.getattr("wratio_batched")?)?;
+    m.add_function(wrap_pyfunction!(soundex, m)?)?;
+    m.add_function(wrap_pyfunction!(soundex_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(refined_soundex, m)?)?;
+    m.add_function(wrap_pyfunction!(refined_soundex_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(metaphone, m)?)?;
+    m.add_function(wrap_pyfunction!(metaphone_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(double_metaphone, m)?)?;
+    m.add_function(wrap_pyfunction!(double_metaphone_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(nysiis, m)?)?;
+    m.add_function(wrap_pyfunction!(nysiis_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(phonetic_match, m)?)?;
+    m.add_function(wrap_pyfunction!(phonetic_match_batched, m)?)?;
+    m.add_function(wrap_pyfunction!(cdist, m)?)?;
+    m.add_function(wrap_pyfunction!(extract, m)?)?;
+    m.add_function(wrap_pyfunction!(extract_one, m)?)?;
+    m.add_function(wrap_pyfunction!(dedupe, m)?)?;
+    m.add_class::<index::BKTree>()?;
+    m.add_class::<index::VPTree>()?;
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    m.add("__author__", env!("CARGO_PKG_AUTHORS"))?;
+    m.add("__description__", env!("CARGO_PKG_DESCRIPTION"))?;
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn none_policy_masks() {
+        let missing = [false, true, false];
+
+        let mut scores = vec![0.5, 0.9, 0.7];
+        NonePolicy::Zero.mask_scores(&mut scores, &missing, 0.0);
+        assert_eq!(scores, vec![0.5, 0.0, 0.7]);
+        NonePolicy::Propagate.mask_scores(&mut scores, &missing, 0.0);
+        assert!(scores[1].is_nan());
+
+        assert_eq!(NonePolicy::Zero.mask_values(vec![1, 3, 2], &missing, 0), vec![Some(1), Some(0), Some(2)]);
+        assert_eq!(NonePolicy::Propagate.mask_values(vec![1, 3, 2], &missing, 0), vec![Some(1), None, Some(2)]);
+        assert_eq!(NonePolicy::Propagate.fallback(0.0), None);
+    }
+
+    #[test]
+    fn none_policy_scores() {
+        // `Raise` raises before anything is scored, so only `Zero` and
+        // `Propagate` ever mask a score
+        let lev = EditDistance::WeightedLevenshtein { deletion_cost: 1, insertion_cost: 1, substitution_cost: 1 };
+        let scorers = [
+            (Scorer::Distance(lev), 0.0),
+            (Scorer::NormalizedDistance(lev), 1.0),
+            (Scorer::NormalizedSimilarity(lev), 0.0),
+            (Scorer::JaroWinkler { max_prefix_length: 4, scaling_factor: 0.1 }, 0.0),
+        ];
+        for (scorer, zero) in scorers {
+            assert_eq!(scorer.none_score(), zero);
+            let mut scores = vec![0.25, 3.0];
+            NonePolicy::Zero.mask_scores(&mut scores, &[false, true], scorer.none_score());
+            assert_eq!(scores, vec![0.25, zero]);
+            NonePolicy::Propagate.mask_scores(&mut scores, &[false, true], scorer.none_score());
+            assert!(scores[1].is_nan());
+        }
+
+        // raw distances mask their integer batches and single pairs with 0
+        assert_eq!(NonePolicy::Zero.mask_values(vec![0, 3, 4], &[true, true, false], 0), vec![Some(0), Some(0), Some(4)]);
+        assert_eq!(NonePolicy::Zero.fallback(0), Some(0));
+        assert_eq!(NonePolicy::Propagate.fallback(0), None);
+    }
+}
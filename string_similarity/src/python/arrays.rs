@@ -0,0 +1,298 @@
+//! Reads string columns out of Arrow and NumPy arrays through the buffer
+//! protocol, without creating a Python string per element.
+
+use std::borrow::Cow;
+
+use pyo3::buffer::PyBuffer;
+use pyo3::exceptions::{ PyTypeError, PyValueError };
+use pyo3::prelude::*;
+
+
+/// The library an array argument comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArrayKind {
+    Arrow,
+    NumPy,
+    Pandas,
+    Polars,
+}
+
+fn array_kind(object: &PyAny) -> PyResult<Option<ArrayKind>> {
+    let module: String = object.get_type().getattr("__module__")?.extract()?;
+    Ok(match module.split('.').next() {
+        Some("pyarrow") => Some(ArrayKind::Arrow),
+        Some("numpy")   => Some(ArrayKind::NumPy),
+        Some("pandas")  => Some(ArrayKind::Pandas),
+        Some("polars")  => Some(ArrayKind::Polars),
+        _ => None,
+    })
+}
+
+/// Whether `object` is an array or column rather than a Python sequence.
+/// Batch results for arrays are returned as NumPy arrays.
+pub fn is_array(object: &PyAny) -> PyResult<bool> {
+    Ok(array_kind(object)?.is_some())
+}
+
+/// The strings of an Arrow string array (or chunked array), a NumPy `U`
+/// array or a pandas or polars column backed by one, `None` for nulls.
+/// Returns `Ok(None)` for anything else, including NumPy object arrays,
+/// which hold Python strings and are iterated like a list.
+///
+/// Arrow strings are borrowed from the array's data buffer. NumPy stores
+/// UCS-4 code points, so those are decoded into owned strings.
+pub fn read_strings(object: &PyAny) -> PyResult<Option<Vec<Option<Cow<'_, str>>>>> {
+    match array_kind(object)? {
+        Some(ArrayKind::Arrow) => read_arrow(object).map(Some),
+        Some(ArrayKind::NumPy) => read_numpy(object),
+        Some(ArrayKind::Pandas) => {
+            let array = object.getattr("array")?;
+            if array.hasattr("__arrow_array__")? {
+                read_arrow(array.call_method0("__arrow_array__")?).map(Some)
+            } else {
+                read_numpy(object.call_method0("to_numpy")?)
+            }
+        }
+        Some(ArrayKind::Polars) => read_arrow(object.call_method0("to_arrow")?).map(Some),
+        None => Ok(None),
+    }
+}
+
+
+fn read_arrow(array: &PyAny) -> PyResult<Vec<Option<Cow<'_, str>>>> {
+    if !array.hasattr("chunks")? {
+        return read_arrow_chunk(array);
+    }
+    let mut strings = Vec::with_capacity(array.len()?);
+    for chunk in array.getattr("chunks")?.iter()? {
+        strings.extend(read_arrow_chunk(chunk?)?);
+    }
+    Ok(strings)
+}
+
+fn read_arrow_chunk(array: &PyAny) -> PyResult<Vec<Option<Cow<'_, str>>>> {
+    let py = array.py();
+    // string views (as polars exports) and dictionary encoded strings are
+    // cast to the offset layout first
+    let data_type = array.getattr("type")?.str()?.to_str()?;
+    let array = match data_type {
+        "string" | "utf8" | "large_string" | "large_utf8" => array,
+        _ if is_castable_string(data_type) => {
+            let large_string = py.import("pyarrow")?.call_method0("large_string")?;
+            array.call_method1("cast", (large_string,))?
+        }
+        _ => {
+            return Err(PyTypeError::new_err(format!("expected an arrow string array, got one of type {}", data_type)));
+        }
+    };
+    let offset_width = match array.getattr("type")?.str()?.to_str()? {
+        "string" | "utf8" => 4,
+        _ => 8,
+    };
+
+    let len = array.len()?;
+    let offset: usize = array.getattr("offset")?.extract()?;
+    let buffers = array.call_method0("buffers")?;
+    let validity = buffers.get_item(0)?;
+    let validity = if validity.is_none() { None } else { Some(buffer_bytes(validity)?) };
+    let offsets = buffer_bytes(buffers.get_item(1)?)?;
+    let data = buffers.get_item(2)?;
+    let data = if data.is_none() { &[][..] } else { buffer_bytes(data)? };
+
+    let chunk = ArrowChunk { validity, offsets, data, offset, len, offset_width };
+    Ok(chunk.strings().map_err(PyValueError::new_err)?.into_iter().map(|string| string.map(Cow::Borrowed)).collect())
+}
+
+/// Arrow types holding strings in another layout, which `cast` converts.
+fn is_castable_string(data_type: &str) -> bool {
+    data_type == "string_view"
+        || data_type.starts_with("dictionary<values=string,")
+        || data_type.starts_with("dictionary<values=large_string,")
+}
+
+/// The buffers of an Arrow string array with 32 or 64 bit offsets, where
+/// element `idx` is at `offset + idx`. Offsets and validity are indexed
+/// from the start of their buffers, so slices share them with the array
+/// they were sliced from.
+struct ArrowChunk<'a> {
+    validity: Option<&'a [u8]>,
+    offsets: &'a [u8],
+    data: &'a [u8],
+    offset: usize,
+    len: usize,
+    offset_width: usize,
+}
+
+impl<'a> ArrowChunk<'a> {
+    fn strings(&self) -> Result<Vec<Option<&'a str>>, &'static str> {
+        let offset_width = self.offset_width;
+        let offsets = self
+            .offsets
+            .get(self.offset * offset_width..(self.offset + self.len + 1) * offset_width)
+            .ok_or("arrow offsets buffer is too short")?;
+        let offsets: Vec<usize> = offsets
+            .chunks_exact(offset_width)
+            .map(|bytes| match offset_width {
+                4 => i32::from_le_bytes(bytes.try_into().unwrap()) as usize,
+                _ => i64::from_le_bytes(bytes.try_into().unwrap()) as usize,
+            })
+            .collect();
+
+        (0..self.len).map(|idx| {
+            if let Some(validity) = self.validity {
+                let bit = self.offset + idx;
+                let byte = validity.get(bit / 8).ok_or("arrow validity buffer is too short")?;
+                if byte >> (bit % 8) & 1 == 0 {
+                    return Ok(None);
+                }
+            }
+            let bytes = self
+                .data
+                .get(offsets[idx]..offsets[idx + 1])
+                .ok_or("arrow offsets point past the data buffer")?;
+            let string = std::str::from_utf8(bytes).map_err(|_| "arrow string array holds invalid UTF-8")?;
+            Ok(Some(string))
+        }).collect()
+    }
+}
+
+/// Holds a buffer export until the GIL pool the slices of `buffer_bytes`
+/// borrow from is released.
+#[pyclass]
+struct HeldBuffer {
+    _buffer: PyBuffer<u8>,
+}
+
+/// The bytes of an immutable buffer protocol object, such as a
+/// `pyarrow.Buffer`, valid for as long as the object is.
+fn buffer_bytes(object: &PyAny) -> PyResult<&[u8]> {
+    let buffer = PyBuffer::<u8>::get(object)?;
+    if !buffer.is_c_contiguous() {
+        return Err(PyValueError::new_err("array buffers must be contiguous"));
+    }
+    if buffer.len_bytes() == 0 {
+        return Ok(&[]);
+    }
+    let (ptr, len) = (buffer.buf_ptr() as *const u8, buffer.len_bytes());
+    PyCell::new(object.py(), HeldBuffer { _buffer: buffer })?;
+    // SAFETY: the export is held by the `HeldBuffer` in the GIL pool, which
+    // lives as long as `object`, and Arrow buffers are never written to
+    // once built.
+    Ok(unsafe { std::slice::from_raw_parts(ptr, len) })
+}
+
+
+fn read_numpy(array: &PyAny) -> PyResult<Option<Vec<Option<Cow<'_, str>>>>> {
+    let py = array.py();
+    let dtype = array.getattr("dtype")?;
+    if dtype.getattr("kind")?.extract::<&str>()? != "U" {
+        return Ok(None);
+    }
+    if array.getattr("ndim")?.extract::<usize>()? != 1 {
+        return Err(PyValueError::new_err("string arrays must be one dimensional"));
+    }
+    let width = dtype.getattr("itemsize")?.extract::<usize>()? / 4;
+    if width == 0 || array.len()? == 0 {
+        return Ok(Some(vec![Some(Cow::Borrowed("")); array.len()?]));
+    }
+
+    let code_points = py
+        .import("numpy")?
+        .call_method1("ascontiguousarray", (array,))?
+        .call_method1("view", ("uint32",))?;
+    let buffer = PyBuffer::<u32>::get(code_points)?;
+    let code_points = buffer
+        .as_slice(py)
+        .ok_or_else(|| PyValueError::new_err("string arrays must be contiguous"))?;
+
+    // elements are padded with trailing NULs up to the width of the dtype
+    code_points.chunks(width).map(|element| {
+        let len = element.iter().rposition(|code_point| code_point.get() != 0).map_or(0, |idx| idx + 1);
+        element[..len]
+            .iter()
+            .map(|code_point| char::from_u32(code_point.get()))
+            .collect::<Option<String>>()
+            .map(|string| Some(Cow::Owned(string)))
+            .ok_or_else(|| PyValueError::new_err("string array holds an invalid code point"))
+    }).collect::<PyResult<_>>().map(Some)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The buffers pyarrow builds for `pa.array(strings)`.
+    fn buffers(strings: &[Option<&str>], offset_width: usize) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+        let mut validity = vec![0; strings.len().div_ceil(8)];
+        let mut offsets = vec![0; offset_width];
+        let mut data = Vec::new();
+        for (idx, string) in strings.iter().enumerate() {
+            if let Some(string) = string {
+                validity[idx / 8] |= 1 << (idx % 8);
+                data.extend_from_slice(string.as_bytes());
+            }
+            offsets.extend_from_slice(&(data.len() as u64).to_le_bytes()[..offset_width]);
+        }
+        (validity, offsets, data)
+    }
+
+    #[test]
+    fn nulls() {
+        let strings = [Some("martha"), None, Some(""), Some("dixon"), None, None, None, None, Some("東京")];
+        for offset_width in [4, 8] {
+            let (validity, offsets, data) = buffers(&strings, offset_width);
+            let chunk = ArrowChunk { validity: Some(&validity), offsets: &offsets, data: &data, offset: 0, len: 9, offset_width };
+            assert_eq!(chunk.strings().unwrap(), strings);
+
+            let chunk = ArrowChunk { validity: None, ..chunk };
+            assert_eq!(chunk.strings().unwrap()[1], Some(""));
+        }
+    }
+
+    #[test]
+    fn sliced() {
+        // `array[3:7]` shares the buffers of `array`
+        let strings = [Some("a"), None, Some("bc"), Some("def"), None, Some("g"), Some("hi"), None, Some("j")];
+        let (validity, offsets, data) = buffers(&strings, 4);
+        let chunk = ArrowChunk { validity: Some(&validity), offsets: &offsets, data: &data, offset: 3, len: 4, offset_width: 4 };
+        assert_eq!(chunk.strings().unwrap(), &strings[3..7]);
+
+        let empty = ArrowChunk { offset: 9, len: 0, ..chunk };
+        assert_eq!(empty.strings().unwrap(), vec![]);
+    }
+
+    #[test]
+    fn chunked() {
+        // a chunked array is read a chunk at a time, each with its own buffers
+        let chunks = [vec![Some("jonathan"), None], vec![Some("joanna")], vec![None, Some("johan"), Some("")]];
+        let mut strings = Vec::new();
+        for chunk in &chunks {
+            let (validity, offsets, data) = buffers(chunk, 8);
+            let chunk = ArrowChunk { validity: Some(&validity), offsets: &offsets, data: &data, offset: 0, len: chunk.len(), offset_width: 8 };
+            strings.extend(chunk.strings().unwrap().into_iter().map(|string| string.map(str::to_string)));
+        }
+        let expected: Vec<Option<String>> = chunks.concat().into_iter().map(|string| string.map(str::to_string)).collect();
+        assert_eq!(strings, expected);
+    }
+
+    #[test]
+    fn rejects_bad_buffers() {
+        let (validity, offsets, data) = buffers(&[Some("ab"), Some("c")], 4);
+        let chunk = ArrowChunk { validity: Some(&validity), offsets: &offsets, data: &data, offset: 1, len: 2, offset_width: 4 };
+        assert!(chunk.strings().is_err());
+        let chunk = ArrowChunk { offset: 0, data: &data[..2], ..chunk };
+        assert!(chunk.strings().is_err());
+        let chunk = ArrowChunk { data: b"a\xffc", ..chunk };
+        assert!(chunk.strings().is_err());
+    }
+
+    #[test]
+    fn castable_types() {
+        assert!(is_castable_string("string_view"));
+        assert!(is_castable_string("dictionary<values=string, indices=int32, ordered=0>"));
+        assert!(!is_castable_string("dictionary<values=int64, indices=int32, ordered=0>"));
+        assert!(!is_castable_string("int64"));
+        assert!(!is_castable_string("binary"));
+    }
+}
@@ -0,0 +1,270 @@
+//! The `BKTree` and `VPTree` classes, which index a fixed set of strings
+//! for repeated lookups:
+//!
+//! ```python
+//! names = BKTree(["jonathan", "joanna", "johan"])
+//! names.query("jonatan", 2)   # [("jonathan", 1, 0)]
+//! names.nearest("joana", k=2) # [("joanna", 1, 1), ("johan", 2, 2)]
+//! names.save("names.idx")
+//! names = BKTree.load("names.idx")
+//! ```
+//!
+//! Queries release the GIL. Loaded indexes are memory mapped and read in
+//! place until a string is added to them.
+
+use std::borrow::Cow;
+use std::hash::Hash;
+
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use crate::index::{ BkTree, IndexError, IndexUnit, MappedBkTree, MappedVpTree, VpTree };
+use crate::preprocess::Preprocessor;
+use crate::process::Scorer;
+use crate::unit::{ Symbol, Unit };
+use super::{ parse_scorer, parse_unit, Processor, ProcessorArg };
+
+
+/// A tree of each unit. Graphemes are kept as owned strings, as the
+/// strings they were split from aren't.
+enum Trees<B, C, G> {
+    Byte(B),
+    Char(C),
+    Grapheme(G),
+}
+
+/// An index built in memory, with the strings as they were added, or one
+/// mapped from a file.
+enum Storage<O, M> {
+    Owned { trees: O, strings: Vec<String> },
+    Mapped(M),
+}
+
+/// Splits `$string` into the unit of `$trees` and evaluates `$body` with
+/// the tree of that unit bound to `$tree` and the units to `$units`.
+macro_rules! with_tree {
+    ($trees:expr, $string:expr, |$tree:ident, $units:ident| $body:expr) => {
+        match $trees {
+            Trees::Byte($tree) => {
+                let $units = u8::split(&$string);
+                $body
+            }
+            Trees::Char($tree) => {
+                let $units = char::split(&$string);
+                $body
+            }
+            Trees::Grapheme($tree) => {
+                let $units = <Box<str>>::split(&$string);
+                $body
+            }
+        }
+    };
+}
+
+/// The `processor` an index was built with, kept between calls.
+enum StoredProcessor {
+    Steps(Preprocessor),
+    Callable(PyObject),
+}
+
+fn index_error(err: IndexError) -> PyErr {
+    match err {
+        IndexError::Io(err) => err.into(),
+        err => pyo3::exceptions::PyValueError::new_err(err.to_string()),
+    }
+}
+
+fn bk_tree<T: Eq + Hash + Sync>(scorer: &Scorer) -> PyResult<BkTree<T>> {
+    match scorer {
+        Scorer::Distance(distance) => BkTree::new(*distance).map_err(pyo3::exceptions::PyValueError::new_err),
+        _ => Err(pyo3::exceptions::PyValueError::new_err(
+            "BKTree needs an edit distance such as 'weighted_levenshtein', 'damerau_levenshtein' or 'indel', \
+             use VPTree for similarities and normalized distances",
+        )),
+    }
+}
+
+fn vp_tree<T: Symbol>(scorer: &Scorer) -> PyResult<VpTree<T>> {
+    VpTree::new(scorer.clone()).map_err(pyo3::exceptions::PyValueError::new_err)
+}
+
+/// A class per tree, which only differ in the distances they return and
+/// the metrics they accept.
+macro_rules! index_class {
+    ($(#[$doc:meta])* $class:ident, $tree:ident, $mapped:ident, $distance:ty, $metric:literal, $new_tree:ident) => {
+        $(#[$doc])*
+        #[pyclass(module = "string_sim_metrics")]
+        pub struct $class {
+            storage: Storage<Trees<$tree<u8>, $tree<char>, $tree<Box<str>>>, $mapped>,
+            processor: StoredProcessor,
+        }
+
+        impl $class {
+            fn process<'py>(&self, py: Python<'py>, string: &'py PyAny) -> PyResult<Cow<'py, str>> {
+                if string.is_none() {
+                    return Err(pyo3::exceptions::PyTypeError::new_err("string must not be None"));
+                }
+                let processor = match &self.processor {
+                    StoredProcessor::Steps(steps) => Processor::Steps(*steps),
+                    StoredProcessor::Callable(function) => Processor::Callable(function.clone_ref(py).into_ref(py)),
+                };
+                processor.apply_item(string)
+            }
+
+            fn matches(&self, matches: Vec<(usize, $distance)>) -> Vec<(String, $distance, usize)> {
+                let label = |id: usize| match &self.storage {
+                    Storage::Owned { strings, .. } => strings[id].clone(),
+                    Storage::Mapped(mapped) => mapped.label(id).unwrap().to_string(),
+                };
+                matches.into_iter().map(|(id, distance)| (label(id), distance, id)).collect()
+            }
+        }
+
+        #[pymethods]
+        impl $class {
+            #[new]
+            #[pyo3(signature = (strings=None, metric=$metric, unit="byte", processor=ProcessorArg::Default, **params))]
+            fn new(
+                py: Python,
+                strings: Option<&PyAny>,
+                metric: &str,
+                unit: &str,
+                processor: ProcessorArg,
+                params: Option<&PyDict>,
+                ) -> PyResult<Self> {
+                let unit = parse_unit(unit)?;
+                let scorer = parse_scorer(metric, params)?;
+                scorer.check_unit(unit).map_err(pyo3::exceptions::PyValueError::new_err)?;
+                let processor = match processor.resolve(scorer.default_processor())? {
+                    Processor::Steps(steps) => StoredProcessor::Steps(steps),
+                    Processor::Callable(function) => StoredProcessor::Callable(function.into()),
+                };
+                let trees = match unit {
+                    Unit::Byte => Trees::Byte($new_tree(&scorer)?),
+                    Unit::Char => Trees::Char($new_tree(&scorer)?),
+                    Unit::Grapheme => Trees::Grapheme($new_tree(&scorer)?),
+                };
+                let mut index = $class { storage: Storage::Owned { trees, strings: vec![] }, processor };
+                if let Some(strings) = strings {
+                    index.extend(py, strings)?;
+                }
+                Ok(index)
+            }
+
+            /// Opens an index written by `save`, memory mapped so that
+            /// processes loading the same file share its pages. `verify`
+            /// checks the checksum, which reads the whole file.
+            #[staticmethod]
+            #[pyo3(signature = (path, verify=true))]
+            fn load(py: Python, path: std::path::PathBuf, verify: bool) -> PyResult<Self> {
+                let mapped = py.allow_threads(|| $mapped::open(&path, verify)).map_err(index_error)?;
+                let processor = StoredProcessor::Steps(mapped.preprocessor());
+                Ok($class { storage: Storage::Mapped(mapped), processor })
+            }
+
+            /// Writes the index to `path`, replacing any file there without
+            /// disturbing processes that have it loaded.
+            fn save(&self, py: Python, path: std::path::PathBuf) -> PyResult<()> {
+                let steps = match self.processor {
+                    StoredProcessor::Steps(steps) => steps,
+                    StoredProcessor::Callable(_) => {
+                        return Err(pyo3::exceptions::PyValueError::new_err(
+                            "an index with a callable processor can't be saved, process the strings before adding them instead",
+                        ));
+                    }
+                };
+                let saved = py.allow_threads(|| match &self.storage {
+                    // without processing the strings are their own labels
+                    Storage::Owned { trees, strings } => {
+                        let labels = (!steps.is_identity()).then_some(strings.as_slice());
+                        match trees {
+                            Trees::Byte(tree) => tree.save(&path, steps, labels),
+                            Trees::Char(tree) => tree.save(&path, steps, labels),
+                            Trees::Grapheme(tree) => tree.save(&path, steps, labels),
+                        }
+                    }
+                    Storage::Mapped(mapped) => mapped.save(&path),
+                });
+                saved.map_err(index_error)
+            }
+
+            /// Adds a string, returning its index. Indexes count up from 0
+            /// in the order strings were added. A loaded index is copied into
+            /// memory first.
+            fn add(&mut self, py: Python, string: &PyAny) -> PyResult<usize> {
+                let processed = self.process(py, string)?;
+                if let Storage::Mapped(mapped) = &self.storage {
+                    let trees = match mapped.unit() {
+                        Unit::Byte => Trees::Byte(mapped.to_tree().map_err(index_error)?),
+                        Unit::Char => Trees::Char(mapped.to_tree().map_err(index_error)?),
+                        Unit::Grapheme => Trees::Grapheme(mapped.to_tree().map_err(index_error)?),
+                    };
+                    let strings = (0..mapped.len()).map(|id| mapped.label(id).unwrap().to_string()).collect();
+                    self.storage = Storage::Owned { trees, strings };
+                }
+                match &mut self.storage {
+                    Storage::Owned { trees, strings } => {
+                        let id = with_tree!(trees, processed, |tree, units| tree.add(units));
+                        strings.push(string.str()?.to_str()?.to_string());
+                        Ok(id)
+                    }
+                    Storage::Mapped(_) => unreachable!("loaded indexes are copied into memory above"),
+                }
+            }
+
+            /// Adds every string of an iterable.
+            fn extend(&mut self, py: Python, strings: &PyAny) -> PyResult<()> {
+                for string in strings.iter()? {
+                    self.add(py, string?)?;
+                }
+                Ok(())
+            }
+
+            /// Every string within `max_distance` of `string` as
+            /// `(string, distance, index)` tuples, closest first.
+            fn query(&self, py: Python, string: &PyAny, max_distance: $distance) -> PyResult<Vec<(String, $distance, usize)>> {
+                let processed = self.process(py, string)?;
+                let matches = py.allow_threads(|| match &self.storage {
+                    Storage::Owned { trees, .. } => with_tree!(trees, processed, |tree, units| tree.query(&units, max_distance)),
+                    Storage::Mapped(mapped) => mapped.query(&processed, max_distance),
+                });
+                Ok(self.matches(matches))
+            }
+
+            /// The `k` strings closest to `string` as `(string, distance,
+            /// index)` tuples, closest first.
+            #[pyo3(signature = (string, k=1))]
+            fn nearest(&self, py: Python, string: &PyAny, k: usize) -> PyResult<Vec<(String, $distance, usize)>> {
+                let processed = self.process(py, string)?;
+                let matches = py.allow_threads(|| match &self.storage {
+                    Storage::Owned { trees, .. } => with_tree!(trees, processed, |tree, units| tree.nearest(&units, k)),
+                    Storage::Mapped(mapped) => mapped.nearest(&processed, k),
+                });
+                Ok(self.matches(matches))
+            }
+
+            fn __len__(&self) -> usize {
+                match &self.storage {
+                    Storage::Owned { strings, .. } => strings.len(),
+                    Storage::Mapped(mapped) => mapped.len(),
+                }
+            }
+        }
+    };
+}
+
+index_class!(
+    /// A BK-tree over an edit distance, for exact searches by whole number
+    /// distances. `metric` and `**params` are those of `cdist`, limited to
+    /// `weighted_levenshtein` (without a cost table or legacy semantics),
+    /// `damerau_levenshtein` and `indel`.
+    BKTree, BkTree, MappedBkTree, usize, "weighted_levenshtein", bk_tree
+);
+
+index_class!(
+    /// A vantage point tree over `1 - similarity` (or a normalized
+    /// distance) for any metric `cdist` accepts besides the raw distances.
+    /// Searches are exact when that distance is a metric, as the q-gram
+    /// Jaccard distance is, and approximate otherwise.
+    VPTree, VpTree, MappedVpTree, f32, "jaro_winkler", vp_tree
+);
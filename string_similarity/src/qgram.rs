@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+
+/// Splits a string into its overlapping substrings of `q` units. With `pad`
+/// the string is extended by `q - 1` padding units (`None`) on either side, so
+/// the first and last units also start and end a gram of their own. Strings
+/// shorter than `q` have no grams unless padded.
+pub fn qgrams<T: Clone>(string: &[T], q: usize, pad: bool) -> Vec<Vec<Option<T>>> {
+    padded(string, q, pad)
+        .windows(q.max(1))
+        .map(|gram| gram.iter().map(|unit| unit.cloned()).collect())
+        .collect()
+}
+
+fn padded<T>(string: &[T], q: usize, pad: bool) -> Vec<Option<&T>> {
+    let padding = if pad && !string.is_empty() { q.saturating_sub(1) } else { 0 };
+    let mut padded = Vec::with_capacity(string.len() + 2 * padding);
+    padded.extend((0..padding).map(|_| None));
+    padded.extend(string.iter().map(Some));
+    padded.extend((0..padding).map(|_| None));
+    padded
+}
+
+fn gram_counts<'a, T: Eq + Hash>(padded: &'a [Option<&'a T>], q: usize) -> HashMap<&'a [Option<&'a T>], usize> {
+    let mut counts = HashMap::new();
+    for gram in padded.windows(q) {
+        *counts.entry(gram).or_insert(0) += 1;
+    }
+    counts
+}
+
+
+/// How the overlap of two gram sets is turned into a similarity in `[0, 1]`,
+/// given the size of the intersection and the size of either set.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SetMeasure {
+    /// `|A ∩ B| / |A ∪ B|`
+    Jaccard,
+    /// `2|A ∩ B| / (|A| + |B|)`
+    SorensenDice,
+    /// `|A ∩ B| / min(|A|, |B|)`
+    Overlap,
+    /// `|A ∩ B| / sqrt(|A| |B|)`, the cosine of the sets' indicator vectors.
+    Cosine,
+    /// `|A ∩ B| / (|A ∩ B| + alpha |A - B| + beta |B - A|)`, which is Jaccard
+    /// for `alpha = beta = 1` and Sørensen–Dice for `alpha = beta = 0.5`.
+    Tversky {
+        alpha: f32,
+        beta: f32,
+    },
+}
+
+impl SetMeasure {
+    /// A Tversky measure, failing for weights that are negative or not
+    /// finite, which would give scores outside `[0, 1]` or NaN.
+    pub fn tversky(alpha: f32, beta: f32) -> Result<SetMeasure, String> {
+        if !(alpha.is_finite() && alpha >= 0.0 && beta.is_finite() && beta >= 0.0) {
+            return Err(format!("alpha and beta must be finite and not negative, got {} and {}", alpha, beta));
+        }
+        Ok(SetMeasure::Tversky { alpha, beta })
+    }
+
+    pub fn from_counts(&self, intersection: usize, size1: usize, size2: usize) -> f32 {
+        let intersection = intersection as f32;
+        let size1 = size1 as f32;
+        let size2 = size2 as f32;
+
+        let denominator = match *self {
+            SetMeasure::Jaccard => size1 + size2 - intersection,
+            SetMeasure::SorensenDice => (size1 + size2) / 2.0,
+            SetMeasure::Overlap => size1.min(size2),
+            SetMeasure::Cosine => (size1 * size2).sqrt(),
+            SetMeasure::Tversky { alpha, beta } => {
+                intersection + alpha * (size1 - intersection) + beta * (size2 - intersection)
+            }
+        };
+        if denominator <= 0.0 {
+            return 0.0;
+        }
+        intersection / denominator
+    }
+}
+
+
+/// A similarity over the q-grams of two strings. Grams are compared as sets
+/// unless `weighted`, in which case repeated grams count once per occurrence
+/// (multiset intersection and union).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QGram {
+    pub q: usize,
+    pub pad: bool,
+    pub weighted: bool,
+    pub measure: SetMeasure,
+}
+
+impl QGram {
+    pub fn new(measure: SetMeasure) -> QGram {
+        QGram { q: 1, pad: false, weighted: false, measure }
+    }
+
+    pub fn q(mut self, q: usize) -> QGram {
+        self.q = q;
+        self
+    }
+
+    pub fn pad(mut self, pad: bool) -> QGram {
+        self.pad = pad;
+        self
+    }
+
+    pub fn weighted(mut self, weighted: bool) -> QGram {
+        self.weighted = weighted;
+        self
+    }
+
+    /// Two strings without any grams score 1 if they are equal and 0
+    /// otherwise, as do strings where only one has grams.
+    pub fn similarity<T: Eq + Hash>(&self, str1: &[T], str2: &[T]) -> f32 {
+        let q = self.q.max(1);
+        let padded1 = padded(str1, q, self.pad);
+        let padded2 = padded(str2, q, self.pad);
+        let counts1 = gram_counts(&padded1, q);
+        let counts2 = gram_counts(&padded2, q);
+
+        if counts1.is_empty() || counts2.is_empty() {
+            return if counts1.is_empty() && counts2.is_empty() && str1 == str2 { 1.0 } else { 0.0 };
+        }
+
+        let (intersection, size1, size2) = if self.weighted {
+            let intersection = counts1
+                .iter()
+                .map(|(gram, count)| counts2.get(gram).map_or(0, |other| *count.min(other)))
+                .sum();
+            (intersection, counts1.values().sum(), counts2.values().sum())
+        } else {
+            let intersection = counts1.keys().filter(|gram| counts2.contains_key(*gram)).count();
+            (intersection, counts1.len(), counts2.len())
+        };
+        self.measure.from_counts(intersection, size1, size2)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn qgram(q: usize, pad: bool, weighted: bool, measure: SetMeasure) -> QGram {
+        QGram { q, pad, weighted, measure }
+    }
+
+    #[test]
+    fn tokenizer() {
+        assert_eq!(qgrams(b"abc", 2, false), vec![
+            vec![Some(b'a'), Some(b'b')],
+            vec![Some(b'b'), Some(b'c')],
+        ]);
+        assert_eq!(qgrams(b"ab", 2, true), vec![
+            vec![None, Some(b'a')],
+            vec![Some(b'a'), Some(b'b')],
+            vec![Some(b'b'), None],
+        ]);
+        assert!(qgrams(b"a", 2, false).is_empty());
+        assert!(qgrams(b"", 3, true).is_empty());
+    }
+
+    #[test]
+    fn set_and_multiset_jaccard() {
+        // {a, b} vs {a, b, c}, independent of repeats.
+        let jaccard = QGram::new(SetMeasure::Jaccard);
+        assert_eq!(jaccard.similarity(b"aab", b"abc"), 2.0 / 3.0);
+
+        // {a: 2, b: 1} vs {a: 1, b: 1, c: 1}: min sums to 2, max to 4.
+        let weighted = qgram(1, false, true, SetMeasure::Jaccard);
+        assert_eq!(weighted.similarity(b"aab", b"abc"), 0.5);
+
+        let bigrams = qgram(2, false, false, SetMeasure::Jaccard);
+        assert_eq!(bigrams.similarity(b"night", b"nacht"), 1.0 / 7.0);
+        assert_eq!(bigrams.similarity(b"abc", b"abc"), 1.0);
+        assert_eq!(bigrams.similarity(b"a", b"a"), 1.0);
+        assert_eq!(bigrams.similarity(b"a", b"b"), 0.0);
+        assert_eq!(bigrams.similarity(b"", b"abc"), 0.0);
+    }
+
+    #[test]
+    fn other_measures() {
+        let dice = qgram(2, false, false, SetMeasure::SorensenDice);
+        assert_eq!(dice.similarity(b"night", b"nacht"), 0.25);
+
+        let overlap = qgram(2, false, false, SetMeasure::Overlap);
+        assert_eq!(overlap.similarity(b"abc", b"xabcx"), 1.0);
+
+        let tversky = qgram(2, false, false, SetMeasure::Tversky { alpha: 1.0, beta: 1.0 });
+        assert_eq!(tversky.similarity(b"night", b"nacht"), 1.0 / 7.0);
+        let tversky = qgram(2, false, false, SetMeasure::Tversky { alpha: 0.5, beta: 0.5 });
+        assert_eq!(tversky.similarity(b"night", b"nacht"), 0.25);
+        assert_eq!(SetMeasure::tversky(0.5, 2.0), Ok(SetMeasure::Tversky { alpha: 0.5, beta: 2.0 }));
+        assert!(SetMeasure::tversky(-1.0, 1.0).is_err());
+        assert!(SetMeasure::tversky(1.0, f32::NAN).is_err());
+        assert!(SetMeasure::tversky(f32::INFINITY, 1.0).is_err());
+
+        // {_a, ab, b_} vs {_a, ab, bc, c_}
+        let padded = qgram(2, true, false, SetMeasure::Jaccard);
+        assert_eq!(padded.similarity(b"ab", b"abc"), 0.4);
+    }
+}
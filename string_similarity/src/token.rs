@@ -0,0 +1,192 @@
+use std::collections::HashSet;
+use std::ops::Range;
+
+use regex::Regex;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::process::Scorer;
+use crate::qgram::SetMeasure;
+use crate::unit::Symbol;
+
+
+/// How a string is split into tokens.
+#[derive(Debug, Clone)]
+pub enum Tokenizer {
+    /// Runs of non-whitespace characters.
+    Whitespace,
+    /// Unicode words (UAX #29), skipping whitespace and punctuation, so
+    /// "Main St." and "main-st" both give two tokens.
+    WordBoundary,
+    /// Every non-overlapping match of the pattern is a token.
+    Regex(Regex),
+}
+
+impl Tokenizer {
+    /// Byte ranges of the tokens of `string`.
+    pub fn token_ranges(&self, string: &str) -> Vec<Range<usize>> {
+        match self {
+            Tokenizer::Whitespace => {
+                let mut ranges = Vec::new();
+                let mut start = None;
+                for (idx, c) in string.char_indices() {
+                    match (c.is_whitespace(), start) {
+                        (true, Some(token_start)) => {
+                            ranges.push(token_start..idx);
+                            start = None;
+                        }
+                        (false, None) => start = Some(idx),
+                        _ => {}
+                    }
+                }
+                if let Some(token_start) = start {
+                    ranges.push(token_start..string.len());
+                }
+                ranges
+            }
+            Tokenizer::WordBoundary => {
+                string.unicode_word_indices().map(|(idx, word)| idx..idx + word.len()).collect()
+            }
+            Tokenizer::Regex(pattern) => {
+                pattern.find_iter(string).filter(|m| !m.is_empty()).map(|m| m.range()).collect()
+            }
+        }
+    }
+
+    /// Splits a string that has already been split into units into tokens,
+    /// each a slice of those units. A unit belongs to the token its first byte
+    /// falls in.
+    pub fn tokenize<'a, T: Symbol>(&self, units: &'a [T]) -> Vec<&'a [T]> {
+        let string = T::concat(units);
+        let mut offsets = Vec::with_capacity(units.len());
+        let mut offset = 0;
+        for unit in units {
+            offsets.push(offset);
+            offset += unit.byte_len();
+        }
+
+        self.token_ranges(&string)
+            .into_iter()
+            .filter_map(|range| {
+                let start = offsets.partition_point(|&offset| offset < range.start);
+                let end = offsets.partition_point(|&offset| offset < range.end);
+                if start < end { Some(&units[start..end]) } else { None }
+            })
+            .collect()
+    }
+}
+
+
+/// How two lists of tokens are compared.
+#[derive(Debug, Clone)]
+pub enum TokenMeasure {
+    /// A set similarity over the distinct tokens.
+    Set(SetMeasure),
+    /// Monge-Elkan: every token of the first string is matched with its most
+    /// similar token of the second under the inner scorer and the scores are
+    /// averaged. The inner scorer should be a similarity, and the result is on
+    /// its scale. Note that this is not symmetric.
+    MongeElkan(Box<Scorer>),
+}
+
+
+/// A similarity computed over the tokens of two strings rather than their
+/// units.
+#[derive(Debug, Clone)]
+pub struct TokenSimilarity {
+    pub tokenizer: Tokenizer,
+    pub measure: TokenMeasure,
+}
+
+impl TokenSimilarity {
+    /// Two strings without any tokens score 1, one string without tokens
+    /// scores 0.
+    pub fn similarity<T: Symbol>(&self, str1: &[T], str2: &[T]) -> f32 {
+        let tokens1 = self.tokenizer.tokenize(str1);
+        let tokens2 = self.tokenizer.tokenize(str2);
+        if tokens1.is_empty() || tokens2.is_empty() {
+            return if tokens1.is_empty() && tokens2.is_empty() { 1.0 } else { 0.0 };
+        }
+
+        match &self.measure {
+            TokenMeasure::Set(measure) => {
+                let set1: HashSet<&[T]> = tokens1.into_iter().collect();
+                let set2: HashSet<&[T]> = tokens2.into_iter().collect();
+                let intersection = set1.intersection(&set2).count();
+                measure.from_counts(intersection, set1.len(), set2.len())
+            }
+            TokenMeasure::MongeElkan(inner) => monge_elkan(&tokens1, &tokens2, |a, b| inner.score(a, b, None)),
+        }
+    }
+}
+
+
+/// Mean over the tokens of `tokens1` of their best `inner` score against any
+/// token of `tokens2`. `inner` can be any metric over units, such as
+/// `get_jaro_winkler_similarity`.
+pub fn monge_elkan<T, F: Fn(&[T], &[T]) -> f32>(tokens1: &[&[T]], tokens2: &[&[T]], inner: F) -> f32 {
+    if tokens1.is_empty() || tokens2.is_empty() {
+        return 0.0;
+    }
+    let total: f32 = tokens1
+        .iter()
+        .map(|token1| tokens2.iter().map(|token2| inner(token1, token2)).fold(f32::MIN, f32::max))
+        .sum();
+    total / tokens1.len() as f32
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_jaro_winkler_similarity;
+
+    fn tokens<'a>(tokenizer: &Tokenizer, string: &'a str) -> Vec<&'a str> {
+        tokenizer.token_ranges(string).into_iter().map(|range| &string[range]).collect()
+    }
+
+    #[test]
+    fn tokenizers() {
+        assert_eq!(tokens(&Tokenizer::Whitespace, "  123 Main\tSt. "), vec!["123", "Main", "St."]);
+        assert_eq!(tokens(&Tokenizer::WordBoundary, "123 Main St."), vec!["123", "Main", "St"]);
+        assert_eq!(tokens(&Tokenizer::WordBoundary, "can't-stop"), vec!["can't", "stop"]);
+
+        let digits = Tokenizer::Regex(Regex::new(r"\d+").unwrap());
+        assert_eq!(tokens(&digits, "Apt 4, 221B Baker"), vec!["4", "221"]);
+    }
+
+    #[test]
+    fn tokenize_units() {
+        let chars: Vec<char> = "café crème".chars().collect();
+        let words = Tokenizer::Whitespace.tokenize(&chars);
+        assert_eq!(words, vec![&chars[..4], &chars[5..]]);
+
+        let bytes = "café crème".as_bytes();
+        assert_eq!(Tokenizer::Whitespace.tokenize(bytes), vec![&bytes[..5], &bytes[6..]]);
+    }
+
+    #[test]
+    fn token_set_measures() {
+        let jaccard = TokenSimilarity { tokenizer: Tokenizer::WordBoundary, measure: TokenMeasure::Set(SetMeasure::Jaccard) };
+        assert_eq!(jaccard.similarity(b"123 Main St", b"Main St 123"), 1.0);
+        assert_eq!(jaccard.similarity(b"123 Main St", b"Main Street 123"), 0.5);
+
+        let cosine = TokenSimilarity { tokenizer: Tokenizer::Whitespace, measure: TokenMeasure::Set(SetMeasure::Cosine) };
+        assert!((cosine.similarity(b"a b c d", b"a b") - 2.0 / 8f32.sqrt()).abs() < 1e-6);
+        assert_eq!(cosine.similarity(b"", b"   "), 1.0);
+        assert_eq!(cosine.similarity(b"a", b"   "), 0.0);
+    }
+
+    #[test]
+    fn monge_elkan_with_jaro_winkler() {
+        let inner = Scorer::JaroWinkler { max_prefix_length: 4, scaling_factor: 0.1 };
+        let monge_elkan = TokenSimilarity { tokenizer: Tokenizer::Whitespace, measure: TokenMeasure::MongeElkan(Box::new(inner)) };
+
+        let street = get_jaro_winkler_similarity(b"St", b"Street", 4, 0.1);
+        let expected = (1.0 + 1.0 + street) / 3.0;
+        assert!((monge_elkan.similarity(b"123 Main St", b"Main Street 123") - expected).abs() < 1e-6);
+
+        let str1: Vec<&str> = UnicodeSegmentation::graphemes("Jöhn Smith", true).collect();
+        let str2: Vec<&str> = UnicodeSegmentation::graphemes("Smith Jöhn", true).collect();
+        assert_eq!(monge_elkan.similarity(&str1, &str2), 1.0);
+    }
+}
@@ -0,0 +1,223 @@
+use std::fmt;
+use std::hash::Hash;
+use std::str::FromStr;
+
+
+/// The unit a string is split into before a metric is computed.
+///
+/// `Byte` compares raw UTF-8 bytes (the original behaviour), `Char` compares
+/// Unicode scalar values and `Grapheme` compares extended grapheme clusters,
+/// so "café" vs "cafe" is a single substitution under both of the latter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Unit {
+    #[default]
+    Byte,
+    Char,
+    Grapheme,
+}
+
+impl FromStr for Unit {
+    type Err = String;
+
+    fn from_str(unit: &str) -> Result<Unit, String> {
+        match unit {
+            "byte"     => Ok(Unit::Byte),
+            "char"     => Ok(Unit::Char),
+            "grapheme" => Ok(Unit::Grapheme),
+            _ => Err(format!("unit must be one of 'byte', 'char' or 'grapheme', got '{}'", unit)),
+        }
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Unit::Byte     => "byte",
+            Unit::Char     => "char",
+            Unit::Grapheme => "grapheme",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+
+/// A single unit of a split string. Token based metrics need to know which
+/// units separate words and how to join words back together, everything else
+/// only compares units for equality.
+pub trait Symbol: Eq + Hash + Ord + Clone + Send + Sync {
+    fn is_whitespace(&self) -> bool;
+
+    /// The unit used to join tokens.
+    fn space() -> Self;
+
+    /// Length of the unit in UTF-8 bytes.
+    fn byte_len(&self) -> usize;
+
+    /// Joins units back into the string they were split from.
+    fn concat(units: &[Self]) -> String;
+
+    /// The unit as a string slice, written to `buf` if it isn't stored as
+    /// one. `None` for bytes that aren't ASCII, which aren't a string on
+    /// their own.
+    fn as_str<'b>(&'b self, buf: &'b mut [u8; 4]) -> Option<&'b str>;
+}
+
+impl Symbol for u8 {
+    fn is_whitespace(&self) -> bool {
+        self.is_ascii_whitespace()
+    }
+
+    fn space() -> u8 {
+        b' '
+    }
+
+    fn byte_len(&self) -> usize {
+        1
+    }
+
+    fn concat(units: &[u8]) -> String {
+        String::from_utf8_lossy(units).into_owned()
+    }
+
+    fn as_str<'b>(&'b self, buf: &'b mut [u8; 4]) -> Option<&'b str> {
+        if !self.is_ascii() {
+            return None;
+        }
+        buf[0] = *self;
+        std::str::from_utf8(&buf[..1]).ok()
+    }
+}
+
+impl Symbol for char {
+    fn is_whitespace(&self) -> bool {
+        char::is_whitespace(*self)
+    }
+
+    fn space() -> char {
+        ' '
+    }
+
+    fn byte_len(&self) -> usize {
+        self.len_utf8()
+    }
+
+    fn concat(units: &[char]) -> String {
+        units.iter().collect()
+    }
+
+    fn as_str<'b>(&'b self, buf: &'b mut [u8; 4]) -> Option<&'b str> {
+        Some(self.encode_utf8(buf))
+    }
+}
+
+impl<'a> Symbol for &'a str {
+    fn is_whitespace(&self) -> bool {
+        self.chars().all(char::is_whitespace)
+    }
+
+    fn space() -> &'a str {
+        " "
+    }
+
+    fn byte_len(&self) -> usize {
+        self.len()
+    }
+
+    fn concat(units: &[&'a str]) -> String {
+        units.concat()
+    }
+
+    fn as_str<'b>(&'b self, _buf: &'b mut [u8; 4]) -> Option<&'b str> {
+        Some(self)
+    }
+}
+
+/// Graphemes kept beyond the string they were split from, as indexes do.
+impl Symbol for Box<str> {
+    fn is_whitespace(&self) -> bool {
+        self.chars().all(char::is_whitespace)
+    }
+
+    fn space() -> Box<str> {
+        " ".into()
+    }
+
+    fn byte_len(&self) -> usize {
+        self.len()
+    }
+
+    fn concat(units: &[Box<str>]) -> String {
+        units.concat()
+    }
+
+    fn as_str<'b>(&'b self, _buf: &'b mut [u8; 4]) -> Option<&'b str> {
+        Some(self)
+    }
+}
+
+
+/// Splits every string of one or more batches into the requested unit once up
+/// front and evaluates `$body` with each batch bound to a `Vec` of sequences
+/// that implement `AsRef<[T: Symbol]>`.
+macro_rules! with_unit_batches {
+    ($unit:expr, [$($strings:expr => $seqs:ident),+ $(,)?], $body:expr) => {{
+        match $unit {
+            $crate::unit::Unit::Byte => {
+                $(
+                    let $seqs: Vec<&[u8]> = $strings.iter()
+                        .map(|string| AsRef::<str>::as_ref(string).as_bytes())
+                        .collect();
+                )+
+                $body
+            }
+            $crate::unit::Unit::Char => {
+                $(
+                    let $seqs: Vec<Vec<char>> = $strings.iter()
+                        .map(|string| AsRef::<str>::as_ref(string).chars().collect())
+                        .collect();
+                )+
+                $body
+            }
+            $crate::unit::Unit::Grapheme => {
+                $(
+                    let $seqs: Vec<Vec<&str>> = $strings.iter()
+                        .map(|string| unicode_segmentation::UnicodeSegmentation::graphemes(AsRef::<str>::as_ref(string), true).collect())
+                        .collect();
+                )+
+                $body
+            }
+        }
+    }};
+}
+
+pub(crate) use with_unit_batches;
+
+
+/// Splits two `&str`s into slices of the requested unit and evaluates `$body`
+/// with them bound to `$a` and `$b`. The body is monomorphised once per unit,
+/// so any function generic over `&[T: Eq]` can be called from it.
+macro_rules! with_units {
+    ($unit:expr, $str1:expr, $str2:expr, |$a:ident, $b:ident| $body:expr) => {{
+        let str1: &str = $str1;
+        let str2: &str = $str2;
+        match $unit {
+            $crate::unit::Unit::Byte => {
+                let $a: &[u8] = str1.as_bytes();
+                let $b: &[u8] = str2.as_bytes();
+                $body
+            }
+            $crate::unit::Unit::Char => {
+                let $a: &[char] = &str1.chars().collect::<Vec<char>>();
+                let $b: &[char] = &str2.chars().collect::<Vec<char>>();
+                $body
+            }
+            $crate::unit::Unit::Grapheme => {
+                let $a: &[&str] = &unicode_segmentation::UnicodeSegmentation::graphemes(str1, true).collect::<Vec<&str>>();
+                let $b: &[&str] = &unicode_segmentation::UnicodeSegmentation::graphemes(str2, true).collect::<Vec<&str>>();
+                $body
+            }
+        }
+    }};
+}
+
+pub(crate) use with_units;